@@ -0,0 +1,1771 @@
+#[cfg(any(feature = "parquet", feature = "arrow-ipc"))]
+use crate::columnar::ArrowSink;
+use crate::compress::{self, GzipSink, without_gz};
+use crate::config::{ColumnMap, find_header};
+use crate::console::{PROGRESS_ROWS, detail, info};
+use crate::dates::{CalendarColumns, DateParser};
+use crate::encoding::Decoder;
+use crate::error::DataAnalysisError;
+use crate::fields::{NegativePolicy, ParseMode, ParseOptions, RowFields};
+use crate::filters::RowFilters;
+use crate::outliers::{MIN_VALUES, OutlierMethod, OutlierRule, drop_outside};
+use crate::output::{MovingAverage, OutputFormat, OutputOptions, Selection};
+use crate::parsing::{Currency, Money, ParsedMoney, PercentMode, format_money};
+use crate::profile::Profiler;
+use crate::rows::Record;
+use crate::sink::{CellKind, CsvSink, FanOutSink, FileSink, JsonlSink, RowSink, snake_case};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::{SqliteSink, TableMode};
+use crate::summary::{
+    AppendCount, DropRateExceeded, DropReason, OutlierCount, RunSummary, TooManyErrors,
+};
+#[cfg(feature = "xlsx")]
+use crate::xlsx::{XlsxSink, read_xlsx};
+use chrono::NaiveDate;
+use csv::{Reader, ReaderBuilder, StringRecord};
+use rust_decimal::RoundingStrategy;
+use serde_json::value::RawValue;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// How a column's cells are read and written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Kept as it is, trimmed
+    Text,
+    /// A whole number of units
+    Count,
+    /// Money written with the price precision
+    Price,
+    /// Money written with the total precision
+    Total,
+    /// A percentage, left blank when it's missing or bad
+    Percent(PercentMode),
+}
+
+/// One column a [`CleanPipeline`] reads and writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    /// The name --config maps to a header, such as `units_sold`
+    pub field: &'static str,
+    /// The header the export usually has it under, also used in messages
+    pub header: &'static str,
+    /// The header in the cleaned file
+    pub output: &'static str,
+    pub kind: FieldKind,
+    /// What to do with a bad value instead of the run's parse mode
+    pub on_error: Option<ParseMode>,
+    /// Skip the row rather than take a missing or bad value as zero
+    pub never_zero: bool,
+    /// What to do with a negative value instead of the kind's usual policy
+    pub negatives: Option<NegativePolicy>,
+    /// Only read and written when the export has it
+    pub optional: bool,
+}
+
+impl Column {
+    pub fn new(field: &'static str, header: &'static str, kind: FieldKind) -> Self {
+        Column {
+            field,
+            header,
+            output: header,
+            kind,
+            on_error: None,
+            never_zero: false,
+            negatives: None,
+            optional: false,
+        }
+    }
+
+    pub fn text(field: &'static str, header: &'static str) -> Self {
+        Column::new(field, header, FieldKind::Text)
+    }
+
+    pub fn count(field: &'static str, header: &'static str) -> Self {
+        Column::new(field, header, FieldKind::Count)
+    }
+
+    pub fn price(field: &'static str, header: &'static str) -> Self {
+        Column::new(field, header, FieldKind::Price)
+    }
+
+    pub fn total(field: &'static str, header: &'static str) -> Self {
+        Column::new(field, header, FieldKind::Total)
+    }
+
+    /// Writes the column under `output` instead of its source header.
+    pub fn output(mut self, output: &'static str) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn on_error(mut self, mode: ParseMode) -> Self {
+        self.on_error = Some(mode);
+        self
+    }
+
+    /// Skips the row under default-zero instead of zeroing this column, for
+    /// one where a zero would pass for a real value, such as the sales a
+    /// moving average is taken over. A --column-mode for it still wins.
+    pub fn never_zero(mut self) -> Self {
+        self.never_zero = true;
+        self
+    }
+
+    /// Units Sold and prices reject negatives unless told otherwise, and
+    /// totals, which can be a loss or a discount, allow them.
+    pub fn negatives(mut self, policy: NegativePolicy) -> Self {
+        self.negatives = Some(policy);
+        self
+    }
+
+    /// Leaves the column out, of the cleaned file too, when the export
+    /// doesn't have it. Optional columns go last.
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+}
+
+/// Something done to the rows once they're all read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Drops rows outside the rule's bounds on its field, measured over every
+    /// row read
+    Outliers(OutlierRule),
+    SortByDate,
+    /// Adds a column averaging `field` over the rows in their current order
+    MovingAverage {
+        average: MovingAverage,
+        field: &'static str,
+    },
+}
+
+impl Step {
+    /// The number column the step works on, if it works on one.
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            Step::Outliers(rule) => Some(rule.field),
+            Step::SortByDate => None,
+            Step::MovingAverage { field, .. } => Some(field),
+        }
+    }
+}
+
+/// A value read from one of a pipeline's columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Text(String),
+    Count(i64),
+    Money(Money),
+    Percent(Option<Money>),
+    /// A missing or bad number kept as a gap under keep-blank
+    Blank,
+}
+
+impl Value {
+    /// The value as a number, for text `None`.
+    pub fn number(&self) -> Option<Money> {
+        match self {
+            Value::Text(_) => None,
+            Value::Count(count) => Some(Money::from(*count)),
+            Value::Money(amount) => Some(*amount),
+            Value::Percent(percent) => *percent,
+            Value::Blank => None,
+        }
+    }
+}
+
+/// One row that passed every check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanRow {
+    /// Line in the export
+    pub line: u64,
+    /// One for each of the pipeline's columns by field, `None` for an
+    /// optional one the export doesn't have
+    pub values: Vec<(&'static str, Option<Value>)>,
+    pub date: NaiveDate,
+    /// The date cell as it was written
+    pub raw_date: String,
+    /// Set when the money columns were seen to agree on one
+    pub currency: Option<Currency>,
+    /// Cells added by steps, such as a moving average
+    pub added: Vec<String>,
+    /// The export's record as it was read
+    pub record: StringRecord,
+}
+
+impl CleanRow {
+    /// The value read from the `field` column, if the row has one.
+    pub fn value(&self, field: &str) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(name, _)| *name == field)
+            .and_then(|(_, value)| value.as_ref())
+    }
+
+    pub fn value_mut(&mut self, field: &str) -> Option<&mut Value> {
+        self.values
+            .iter_mut()
+            .find(|(name, _)| *name == field)
+            .and_then(|(_, value)| value.as_mut())
+    }
+
+    pub fn text(&self, field: &str) -> Result<String, DataAnalysisError> {
+        match self.value(field) {
+            Some(Value::Text(text)) => Ok(text.clone()),
+            _ => Err(self.missing(field, "text")),
+        }
+    }
+
+    pub fn count(&self, field: &str) -> Result<i64, DataAnalysisError> {
+        match self.value(field) {
+            Some(Value::Count(count)) => Ok(*count),
+            _ => Err(self.missing(field, "count")),
+        }
+    }
+
+    pub fn money(&self, field: &str) -> Result<Money, DataAnalysisError> {
+        match self.value(field) {
+            Some(Value::Money(amount)) => Ok(*amount),
+            _ => Err(self.missing(field, "money")),
+        }
+    }
+
+    fn missing(&self, field: &str, kind: &str) -> DataAnalysisError {
+        match self.value(field) {
+            Some(Value::Blank) => format!("row {}: {} is blank", self.line, field).into(),
+            _ => format!("row {}: no {} {} column", self.line, kind, field).into(),
+        }
+    }
+}
+
+/// A row a pipeline left out, as it was read, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedRow {
+    pub line: u64,
+    pub record: StringRecord,
+    /// Such as "Sale Price 'S23' - not a number"
+    pub reason: String,
+}
+
+// What reading one record came to
+enum Outcome {
+    Row(CleanRow),
+    /// Every field was blank, which isn't worth reporting
+    Blank,
+    /// Left out, and why
+    Dropped(String),
+}
+
+impl Outcome {
+    fn dropped(fields: &mut RowFields) -> Self {
+        Outcome::Dropped(
+            fields
+                .drop_reason()
+                .unwrap_or_else(|| "skipped".to_string()),
+        )
+    }
+}
+
+// The records still to read, taken from the input one at a time
+type Records<'a> = Box<dyn Iterator<Item = csv::Result<StringRecord>> + 'a>;
+
+/// A pipeline's rows, parsed one at a time as they're asked for, with rows
+/// that fail a check skipped or, under a strict mode, given as errors. The
+/// export is read as the rows are, once its first few records have settled
+/// its date format. No steps are run, so nothing is sorted or dropped as an
+/// outlier.
+pub struct CleanRows<'a> {
+    pipeline: CleanPipeline<'a>,
+    layout: Layout,
+    dates: DateParser,
+    input: std::iter::Enumerate<Records<'a>>,
+    summary: RunSummary,
+    rejected: Vec<RejectedRow>,
+    finished: bool,
+}
+
+impl CleanRows<'_> {
+    /// What's been read and skipped so far, complete once the rows run out.
+    pub fn summary(&self) -> &RunSummary {
+        &self.summary
+    }
+
+    pub fn into_summary(self) -> RunSummary {
+        self.summary
+    }
+
+    /// The rows left out so far over a bad value, the date range or a
+    /// filter. Blank rows aren't counted.
+    pub fn rejected(&self) -> &[RejectedRow] {
+        &self.rejected
+    }
+
+    /// Runs the pipeline's steps over every row and writes what's left, and
+    /// what was left out to `rejects` when there was any.
+    fn write(
+        mut self,
+        output: &OutputOptions,
+        header: &Header,
+        selection: &Selection,
+        sink: &mut impl RowSink,
+        rejects: Option<&Path>,
+    ) -> Result<RunSummary, DataAnalysisError> {
+        let mut rows = self.by_ref().collect::<Result<Vec<_>, _>>()?;
+        let CleanRows {
+            pipeline,
+            layout,
+            mut summary,
+            mut rejected,
+            ..
+        } = self;
+        for step in &pipeline.steps {
+            pipeline.apply(step, &mut rows, &mut summary, &mut rejected);
+        }
+
+        info!("Saving cleaned CSV");
+        let (names, kinds) = (
+            selection.apply(&header.names),
+            selection.pick(&header.kinds),
+        );
+        let database = output.formats.iter().any(|format| format.is_database());
+        let existing = match &output.append {
+            // The database is added to in place
+            Some(path) if database && append_format(path, output).is_database() => None,
+            Some(path) => Some(Existing::read(path, &names, output)?),
+            None => None,
+        };
+        let cells: Vec<Vec<String>> = rows.iter().map(|row| pipeline.cells(row, output)).collect();
+        let cells: Vec<Vec<&str>> = cells.iter().map(|cells| selection.apply(cells)).collect();
+        let mut profiler = output.profile.then(|| Profiler::new(&names, &kinds));
+        sink.column_kinds(&kinds);
+        sink.write_header(&names)?;
+        match &existing {
+            None => {
+                for (row, cells) in rows.iter().zip(&cells) {
+                    sink.write_row(cells)?;
+                    if let Some(profiler) = &mut profiler {
+                        profiler.add(cells, row.date);
+                    }
+                    summary.rows_written += 1;
+                }
+            }
+            Some(existing) => {
+                let (mut merged, count, repeated) = existing.merge(&cells);
+                let repeated_rows = repeated.len();
+                for (row, later) in repeated {
+                    let (row, later) = (&rows[row], &rows[later]);
+                    rejected.push(RejectedRow {
+                        line: row.line,
+                        record: row.record.clone(),
+                        reason: format!("row {} has the same --append key", later.line),
+                    });
+                    summary.count_drop(DropReason::Duplicate, None, 1);
+                }
+                pipeline.resequence(&mut merged, &names, output);
+                for cells in &merged {
+                    let cells: Vec<&str> = cells.iter().map(String::as_str).collect();
+                    sink.write_row(&cells)?;
+                    if let Some(profiler) = &mut profiler
+                        && let Some(date) =
+                            cells.get(existing.date()).and_then(|c| output.read_date(c))
+                    {
+                        profiler.add(&cells, date);
+                    }
+                }
+                summary.rows_written = cells.len() - repeated_rows;
+                summary.appended = Some(count);
+            }
+        }
+        // A database is left as it was by not committing, where a file is
+        // removed once it's written
+        if database && !output.keep_partial {
+            summary = check_drop_rate(summary, output)?;
+        }
+        sink.finish()?;
+        summary.profile = profiler.map(Profiler::finish);
+        if let Some(path) = rejects
+            && !rejected.is_empty()
+        {
+            write_rejects(path, &layout.headers, &rejected, output)?;
+        }
+        Ok(summary)
+    }
+}
+
+impl Iterator for CleanRows<'_> {
+    type Item = Result<CleanRow, DataAnalysisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        for (i, record) in self.input.by_ref() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(DataAnalysisError::from(e)));
+                }
+            };
+            self.summary.rows_read = i + 1;
+            if (i + 1) % PROGRESS_ROWS == 0 {
+                detail!("{} rows read", i + 1);
+            }
+            let row = self
+                .pipeline
+                .read_row(&self.layout, &self.dates, &record, &mut self.summary);
+            if let Some(max) = self.pipeline.options.max_errors
+                && self.summary.error_count() >= max
+            {
+                self.finished = true;
+                return Some(Err(TooManyErrors {
+                    line: record.position().map_or(0, |p| p.line()),
+                    max,
+                    summary: Box::new(std::mem::take(&mut self.summary)),
+                }
+                .into()));
+            }
+            match row {
+                Ok(Outcome::Row(mut row)) => {
+                    for map in &mut self.pipeline.row_maps {
+                        map(&mut row);
+                    }
+                    return Some(Ok(row));
+                }
+                Ok(Outcome::Blank) => {}
+                Ok(Outcome::Dropped(reason)) => self.rejected.push(RejectedRow {
+                    line: record.position().map_or(0, |p| p.line()),
+                    record,
+                    reason,
+                }),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.finished = true;
+        // A few stray £ rows in a $ extract would skew any bounds
+        match self.summary.check_currencies(self.pipeline.options.mode) {
+            Ok(()) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// A cleaned file's header, and what each of its columns holds.
+struct Header {
+    names: Vec<String>,
+    kinds: Vec<CellKind>,
+}
+
+/// The layout of one export, worked out from its headers before any rows
+/// are read.
+struct Layout {
+    /// The export's header, which every record should be as wide as
+    headers: StringRecord,
+    indices: Vec<Option<usize>>,
+    date: usize,
+    calendar: CalendarColumns,
+    filters: RowFilters,
+}
+
+/// Reads an export's columns into checked values, runs any steps over the
+/// rows and writes what's left as a cleaned CSV:
+///
+/// ```no_run
+/// # use data_analysis::clean::{CleanPipeline, Column};
+/// # use data_analysis::{fields::ParseOptions, output::OutputOptions};
+/// # fn run(options: &ParseOptions, output: &OutputOptions) -> Result<(), data_analysis::error::DataAnalysisError> {
+/// CleanPipeline::new("returns", options)
+///     .column(Column::text("country", "Country"))
+///     .column(Column::count("units", "Units Returned"))
+///     .column(Column::total("refund", "Refund"))
+///     .run(output, "Data/returns.csv", "Data/returns_Cleaned.csv")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Every row needs a Date column, which is written after the columns and
+/// any steps add, followed by the currency and extra date columns. Rows with
+/// every cell missing are skipped. [`CleanPipeline::rows`] gives the rows
+/// instead of writing them.
+pub struct CleanPipeline<'a> {
+    name: &'static str,
+    options: &'a ParseOptions,
+    columns: Vec<Column>,
+    steps: Vec<Step>,
+    date_first: bool,
+    currency: bool,
+    row_maps: Vec<RowMap<'a>>,
+    schema: Option<&'static [&'static str]>,
+}
+
+type RowMap<'a> = Box<dyn FnMut(&mut CleanRow) + 'a>;
+
+impl<'a> CleanPipeline<'a> {
+    /// `name` is used in messages about the export's columns.
+    pub fn new(name: &'static str, options: &'a ParseOptions) -> Self {
+        CleanPipeline {
+            name,
+            options,
+            columns: Vec::new(),
+            steps: Vec::new(),
+            date_first: false,
+            currency: false,
+            row_maps: Vec::new(),
+            schema: None,
+        }
+    }
+
+    pub fn column(mut self, column: Column) -> Self {
+        self.columns.push(column);
+        self
+    }
+
+    /// The export's usual header, in order. A file whose header differs,
+    /// say with a column put in, is refused before any rows are read, unless
+    /// the options turn the check off or --config maps its columns. Optional
+    /// columns and any after these are left out of the comparison.
+    pub fn schema(mut self, headers: &'static [&'static str]) -> Self {
+        self.schema = Some(headers);
+        self
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Checks the date, date range and filters before the other columns, so
+    /// rows left out that way aren't reported for bad values too.
+    pub fn date_first(mut self) -> Self {
+        self.date_first = true;
+        self
+    }
+
+    /// Warns when a row's money columns are in different currencies and
+    /// writes a Currency column, plus Original Currency when converting.
+    pub fn with_currency(mut self) -> Self {
+        self.currency = true;
+        self
+    }
+
+    /// Adjusts each row once it's read, before any steps, so outlier bounds
+    /// and averages see the adjusted values:
+    ///
+    /// ```no_run
+    /// # use data_analysis::{clean::{CleanPipeline, Value}, fields::ParseOptions};
+    /// # use data_analysis::parsing::Money;
+    /// # use chrono::NaiveDate;
+    /// # fn run(options: &ParseOptions) {
+    /// let fixed_from = NaiveDate::from_ymd_opt(2014, 6, 1).unwrap();
+    /// CleanPipeline::new("dashboard", options).map_rows(move |row| {
+    ///     let montana = row.value("product") == Some(&Value::Text("Montana".into()));
+    ///     if montana && row.date < fixed_from {
+    ///         if let Some(Value::Money(price)) = row.value_mut("sale_price") {
+    ///             *price *= Money::new(11, 1);
+    ///         }
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn map_rows(mut self, map: impl FnMut(&mut CleanRow) + 'a) -> Self {
+        self.row_maps.push(Box::new(map));
+        self
+    }
+
+    /// Cleans the export at `input_path` into `output_path`. Either can be -
+    /// for standard input or output. When too many rows are dropped the
+    /// cleaned file is removed again, unless --keep-partial.
+    pub fn run(
+        self,
+        output: &OutputOptions,
+        input_path: impl AsRef<Path>,
+        output_path: impl AsRef<Path>,
+    ) -> Result<RunSummary, DataAnalysisError> {
+        let (input_path, output_path) = (input_path.as_ref(), output_path.as_ref());
+        let started = Instant::now();
+        info!("Opening {} file", self.name);
+        let mut rdr = open_input(self.name, input_path, self.options)?;
+        let layout = self.layout(rdr.headers().map_err(DataAnalysisError::from)?)?;
+        let (header, selection) = self.header(&layout, output)?;
+        let paths = output.output_paths(output_path)?;
+        let table = output.table.as_deref().unwrap_or(self.name);
+        let mut sink = match paths.as_slice() {
+            [(format, path)] => create_output(path, *format, table, output)?,
+            paths => {
+                let sinks = paths
+                    .iter()
+                    .map(|(format, path)| {
+                        let sink = create_output(path, *format, table, output)?;
+                        Ok((path.display().to_string(), sink))
+                    })
+                    .collect::<Result<_, DataAnalysisError>>()?;
+                Box::new(FanOutSink::new(sinks))
+            }
+        };
+        let rejects = (output.rejects && !output.dry_run && output_path != Path::new("-"))
+            .then(|| rejects_path(output_path));
+        let mut summary = self.read(layout, rdr)?.write(
+            output,
+            &header,
+            &selection,
+            &mut sink,
+            rejects.as_deref(),
+        )?;
+        drop(sink);
+        summary.duration = started.elapsed();
+
+        let result = check_drop_rate(summary, output);
+        if result.is_err()
+            && !output.keep_partial
+            && !output.dry_run
+            && output_path != Path::new("-")
+        {
+            // The rejects go with the output, when there were any to write
+            let written = rejects.iter().filter(|path| path.exists());
+            for path in paths.iter().map(|(_, path)| path).chain(written) {
+                std::fs::remove_file(path).map_err(|e| DataAnalysisError::io("remove", path, e))?;
+                info!("Removed {}", path.display());
+            }
+        }
+        let summary = result?;
+        if !output.dry_run {
+            info!("Done!");
+        }
+        Ok(summary)
+    }
+
+    /// Cleans CSV from `reader` into `writer`, which is written even on a
+    /// dry run.
+    pub fn run_on(
+        self,
+        output: &OutputOptions,
+        reader: impl Read + 'a,
+        writer: impl Write,
+    ) -> Result<RunSummary, DataAnalysisError> {
+        let mut sink = CsvSink::from_writer(output.writer_builder().from_writer(writer));
+        self.run_into(output, reader, &mut sink)
+    }
+
+    /// Cleans CSV from `reader` into any [`RowSink`], which is written even
+    /// on a dry run.
+    pub fn run_into(
+        self,
+        output: &OutputOptions,
+        reader: impl Read + 'a,
+        sink: &mut impl RowSink,
+    ) -> Result<RunSummary, DataAnalysisError> {
+        let started = Instant::now();
+        let mut rdr = reader_builder(self.options.delimiter)
+            .from_reader(Decoder::new(reader, self.options.encoding));
+        let layout = self.layout(rdr.headers().map_err(DataAnalysisError::from)?)?;
+        let (header, selection) = self.header(&layout, output)?;
+        let mut summary = self
+            .read(layout, rdr)?
+            .write(output, &header, &selection, sink, None)?;
+        summary.duration = started.elapsed();
+        Ok(check_drop_rate(summary, output)?)
+    }
+
+    /// The rows of the CSV from `reader`, as [`CleanPipeline::run`] would
+    /// read them before its steps.
+    pub fn rows(self, reader: impl Read + 'a) -> Result<CleanRows<'a>, DataAnalysisError> {
+        let mut rdr = reader_builder(self.options.delimiter)
+            .from_reader(Decoder::new(reader, self.options.encoding));
+        let layout = self.layout(rdr.headers().map_err(DataAnalysisError::from)?)?;
+        self.read(layout, rdr)
+    }
+
+    /// The rows of the export at `input_path`, which can be - for standard
+    /// input.
+    pub fn rows_from_path(
+        self,
+        input_path: impl AsRef<Path>,
+    ) -> Result<CleanRows<'a>, DataAnalysisError> {
+        info!("Opening {} file", self.name);
+        let mut rdr = open_input(self.name, input_path.as_ref(), self.options)?;
+        let layout = self.layout(rdr.headers().map_err(DataAnalysisError::from)?)?;
+        self.read(layout, rdr)
+    }
+
+    // Fails on a missing column or filter before any rows
+    fn layout(&self, headers: &StringRecord) -> Result<Layout, DataAnalysisError> {
+        self.check_steps()?;
+        self.check_schema(headers)?;
+        let mut defaults: Vec<(&'static str, &str)> = self
+            .columns
+            .iter()
+            .filter(|column| !column.optional)
+            .map(|column| (column.field, column.header))
+            .collect();
+        defaults.push(("date", "Date"));
+        let map = ColumnMap::resolve(self.name, headers, &defaults, &self.options.columns)?;
+        let indices: Vec<Option<usize>> = self
+            .columns
+            .iter()
+            .map(|column| match column.optional {
+                true => find_header(headers, column.header)
+                    .map_err(|e| format!("{}: {}; rename all but one", self.name, e)),
+                false => Ok(Some(map.at(column.field))),
+            })
+            .collect::<Result<_, _>>()?;
+        let filters = RowFilters::resolve(
+            &self.options.filters,
+            self.options.case_sensitive_filters,
+            headers,
+        )?;
+
+        Ok(Layout {
+            headers: headers.clone(),
+            indices,
+            date: map.at("date"),
+            calendar: CalendarColumns::from_headers(headers),
+            filters,
+        })
+    }
+
+    // The cleaned file's header, and what each column holds. Fails on a
+    // --select name it doesn't have
+    fn header(
+        &self,
+        layout: &Layout,
+        output: &OutputOptions,
+    ) -> Result<(Header, Selection), DataAnalysisError> {
+        let present = |optional: bool| {
+            self.columns
+                .iter()
+                .zip(&layout.indices)
+                .filter(move |(column, index)| column.optional == optional && index.is_some())
+                .map(|(column, _)| {
+                    let kind = match column.kind {
+                        FieldKind::Text => CellKind::Text,
+                        FieldKind::Count => CellKind::Integer,
+                        FieldKind::Price | FieldKind::Total => CellKind::Money,
+                        FieldKind::Percent(_) => CellKind::Number,
+                    };
+                    (column.output.to_string(), kind)
+                })
+        };
+        let mut header: Vec<(String, CellKind)> = present(false).collect();
+        for step in &self.steps {
+            if let Step::MovingAverage { average, field } = step {
+                let source = self
+                    .find_column(field)
+                    .map_or(*field, |column| column.header);
+                header.push((average.header(source), CellKind::Money));
+            }
+        }
+        header.push((output.date_header().to_string(), CellKind::Date));
+        if self.currency {
+            header.push(("Currency".to_string(), CellKind::Text));
+            if self.options.rates.is_some() {
+                header.push(("Original Currency".to_string(), CellKind::Text));
+            }
+        }
+        let dates = output.date_columns.headers().into_iter();
+        header.extend(dates.map(str::to_string).zip(output.date_columns.kinds()));
+        header.extend(present(true));
+        let (names, kinds): (Vec<String>, Vec<CellKind>) = header.into_iter().unzip();
+        let selection = output.selection(&names.iter().map(String::as_str).collect::<Vec<_>>())?;
+        Ok((Header { names, kinds }, selection))
+    }
+
+    // Settles the file's date format from the first of the slice's records,
+    // which are then read as rows with the rest, one at a time
+    fn read<R: Read + 'a>(
+        self,
+        layout: Layout,
+        rdr: Reader<R>,
+    ) -> Result<CleanRows<'a>, DataAnalysisError> {
+        let options = self.options;
+        let mut records = options.rows.records(rdr);
+        // The sample, and on until a date that only reads one way
+        let mut sample = Vec::new();
+        let mut settled = false;
+        while sample.len() < options.dates.sample() || !settled {
+            let Some(record) = records.next() else {
+                break;
+            };
+            let record = record.map_err(DataAnalysisError::from)?;
+            let date = record.get(layout.date).unwrap_or("");
+            settled = settled || options.dates.settles_order(date);
+            sample.push(record);
+        }
+        let dates = options.dates.detect(sample.iter().map(|r| {
+            let line = r.position().map_or(0, |p| p.line());
+            (line, r.get(layout.date).unwrap_or(""))
+        }))?;
+        let summary = RunSummary {
+            slice: options.rows,
+            date_order: dates.order_note(),
+            ..Default::default()
+        };
+        let input = sample.into_iter().map(Ok).chain(records);
+        Ok(CleanRows {
+            pipeline: self,
+            layout,
+            dates,
+            input: (Box::new(input) as Records).enumerate(),
+            summary,
+            rejected: Vec::new(),
+            finished: false,
+        })
+    }
+
+    // The record as a clean row, or why it was skipped
+    fn read_row(
+        &self,
+        layout: &Layout,
+        dates: &DateParser,
+        record: &StringRecord,
+        summary: &mut RunSummary,
+    ) -> Result<Outcome, DataAnalysisError> {
+        let options = self.options;
+        if record.iter().all(|s| options.money.missing().is_missing(s)) {
+            return Ok(Outcome::Blank);
+        }
+
+        let mut fields = RowFields::new(record, options, summary);
+        if !fields.check_width(layout.headers.len())? {
+            return Ok(Outcome::dropped(&mut fields));
+        }
+        let mut values: Vec<(&'static str, Option<Value>)> =
+            self.columns.iter().map(|c| (c.field, None)).collect();
+        let mut date = None;
+        if self.date_first {
+            date = self.read_date(&mut fields, layout, dates, record)?;
+            if date.is_none() {
+                return Ok(Outcome::dropped(&mut fields));
+            }
+        }
+        let mut money = Vec::new();
+        if !self.read_columns(&mut fields, layout, false, &mut values, &mut money)? {
+            return Ok(Outcome::dropped(&mut fields));
+        }
+        let currency = match self.currency {
+            true => fields.currency(&money),
+            false => None,
+        };
+        if !self.date_first {
+            date = self.read_date(&mut fields, layout, dates, record)?;
+        }
+        let Some(date) = date else {
+            return Ok(Outcome::dropped(&mut fields));
+        };
+        if !self.read_columns(&mut fields, layout, true, &mut values, &mut money)? {
+            return Ok(Outcome::dropped(&mut fields));
+        }
+        Ok(Outcome::Row(CleanRow {
+            line: fields.line(),
+            values,
+            date,
+            raw_date: fields.str(layout.date),
+            currency,
+            added: Vec::new(),
+            record: record.clone(),
+        }))
+    }
+
+    // The row's date, or `None` when it's missing, bad, out of range or
+    // filtered out
+    fn read_date(
+        &self,
+        fields: &mut RowFields,
+        layout: &Layout,
+        dates: &DateParser,
+        record: &StringRecord,
+    ) -> Result<Option<NaiveDate>, DataAnalysisError> {
+        fields.set_mode(self.options.mode);
+        let Some(date) = fields.date(layout.date, "Date", dates, &layout.calendar)? else {
+            return Ok(None);
+        };
+        if !self.options.date_range.contains(date) {
+            detail!("row {}: {} is outside the date range", fields.line(), date);
+            fields.set_drop_reason(format!("{} is outside the date range", date));
+            fields.summary().outside_date_range += 1;
+            fields.summary().count_drop(DropReason::DateRange, None, 1);
+            return Ok(None);
+        }
+        if !layout.filters.matches(record) {
+            detail!("row {}: left out by --filter", fields.line());
+            fields.set_drop_reason("left out by --filter".to_string());
+            fields.summary().filtered_out += 1;
+            fields.summary().count_drop(DropReason::Filter, None, 1);
+            return Ok(None);
+        }
+        Ok(Some(date))
+    }
+
+    // Fills in the required or the optional columns' values, adding the money
+    // read along the way to `money`. False when the row should be skipped
+    fn read_columns(
+        &self,
+        fields: &mut RowFields,
+        layout: &Layout,
+        optional: bool,
+        values: &mut [(&'static str, Option<Value>)],
+        money: &mut Vec<(&'static str, ParsedMoney)>,
+    ) -> Result<bool, DataAnalysisError> {
+        let options = self.options;
+        let columns = self.columns.iter().zip(&layout.indices).zip(values);
+        for ((column, index), value) in columns {
+            let Some(index) = *index else {
+                continue;
+            };
+            if column.optional != optional {
+                continue;
+            }
+            let mode = self.mode_for(column);
+            fields.set_mode(mode);
+            // A total nobody filled in, such as a blank Discounts, is nothing
+            // given rather than a bad row
+            if column.kind == FieldKind::Total && mode == ParseMode::SkipRow && !column.never_zero {
+                fields.set_missing_mode(ParseMode::DefaultZero);
+            }
+            value.1 = Some(match column.kind {
+                FieldKind::Text => Value::Text(fields.str(index)),
+                FieldKind::Count => match fields.count(index, column.header)? {
+                    Some(count) => {
+                        let policy = self.negatives_for(column);
+                        match fields.negative(index, column.header, Money::from(count), policy)? {
+                            Some(count) => Value::Count(count.try_into().unwrap_or(i64::MAX)),
+                            None => return Ok(false),
+                        }
+                    }
+                    None if mode == ParseMode::KeepBlank => Value::Blank,
+                    None => return Ok(false),
+                },
+                FieldKind::Price | FieldKind::Total => match fields.money(index, column.header)? {
+                    Some(mut parsed) => {
+                        let policy = self.negatives_for(column);
+                        match fields.negative(index, column.header, parsed.amount, policy)? {
+                            Some(amount) => parsed.amount = amount,
+                            None => return Ok(false),
+                        }
+                        money.push((column.header, parsed));
+                        Value::Money(parsed.amount)
+                    }
+                    None if mode == ParseMode::KeepBlank => Value::Blank,
+                    None => return Ok(false),
+                },
+                FieldKind::Percent(mode) => {
+                    Value::Percent(fields.optional(index, column.header, |s| {
+                        options.money.parse_percent(s, mode)
+                    })?)
+                }
+            });
+        }
+        Ok(true)
+    }
+
+    // --column-mode, then the column's own mode, then the run's
+    fn mode_for(&self, column: &Column) -> ParseMode {
+        if let Some(mode) = self.options.column_modes.get(column.field) {
+            return *mode;
+        }
+        match column.on_error.unwrap_or(self.options.mode) {
+            ParseMode::DefaultZero if column.never_zero => ParseMode::SkipRow,
+            mode => mode,
+        }
+    }
+
+    // --negatives, then the column's own policy, then its kind's
+    fn negatives_for(&self, column: &Column) -> NegativePolicy {
+        if let Some(policy) = self.options.negatives.get(column.field) {
+            return *policy;
+        }
+        column.negatives.unwrap_or(match column.kind {
+            FieldKind::Count if self.options.counts.allow_negative => NegativePolicy::Allow,
+            FieldKind::Count | FieldKind::Price => NegativePolicy::Reject,
+            _ => NegativePolicy::Allow,
+        })
+    }
+
+    fn find_column(&self, field: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.field == field)
+    }
+
+    /// Fails on a step over a field the pipeline doesn't read as a number.
+    pub fn check_steps(&self) -> Result<(), DataAnalysisError> {
+        for field in self.steps.iter().filter_map(Step::field) {
+            match self.find_column(field) {
+                Some(column) if column.kind != FieldKind::Text => {}
+                Some(column) => {
+                    return Err(format!(
+                        "{}: {} is text, so no step can work on it",
+                        self.name, column.header
+                    )
+                    .into());
+                }
+                None => {
+                    let numbers: Vec<&str> = self
+                        .columns
+                        .iter()
+                        .filter(|column| column.kind != FieldKind::Text)
+                        .map(|column| column.field)
+                        .collect();
+                    return Err(format!(
+                        "{}: no {} column for a step to work on (expected one of {})",
+                        self.name,
+                        field,
+                        numbers.join(", ")
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Lists where the header and the schema part ways, if they do
+    fn check_schema(&self, headers: &StringRecord) -> Result<(), String> {
+        let Some(schema) = self.schema else {
+            return Ok(());
+        };
+        if !self.options.check_headers || !self.options.columns.is_empty() {
+            return Ok(());
+        }
+        let optional = |header: &str| {
+            self.columns
+                .iter()
+                .any(|column| column.optional && column.header.eq_ignore_ascii_case(header))
+        };
+        let found: Vec<&str> = headers
+            .iter()
+            .map(str::trim)
+            .filter(|header| !optional(header))
+            .collect();
+        let differences: Vec<String> = schema
+            .iter()
+            .enumerate()
+            .filter_map(|(position, expected)| match found.get(position) {
+                Some(header) if header.eq_ignore_ascii_case(expected) => None,
+                Some(header) => Some(format!(
+                    "expected '{}' at position {}, found '{}'",
+                    expected, position, header
+                )),
+                None => Some(format!(
+                    "expected '{}' at position {}, found nothing",
+                    expected, position
+                )),
+            })
+            .collect();
+        let Some(first) = differences.first() else {
+            return Ok(());
+        };
+        let more = match differences.len() {
+            1 => String::new(),
+            n => format!(" and {} more", n - 1),
+        };
+        Err(format!(
+            "{}: the header isn't the export's usual one: {}{}; pass --no-header-check to read its columns by name anyway",
+            self.name, first, more
+        ))
+    }
+
+    fn apply(
+        &self,
+        step: &Step,
+        rows: &mut Vec<CleanRow>,
+        summary: &mut RunSummary,
+        rejected: &mut Vec<RejectedRow>,
+    ) {
+        match step {
+            Step::Outliers(rule) => {
+                info!("Calculating outlier bounds");
+                let field = rule.field;
+                let header = self
+                    .find_column(field)
+                    .map_or(field, |column| column.header);
+                let values = rows.iter().filter(|r| r.number(field).is_some()).count();
+                if rule.method != OutlierMethod::None && values < MIN_VALUES {
+                    info!(
+                        "warning: {} {} values are too few to find outliers among, so none were filtered",
+                        values, header
+                    );
+                    return;
+                }
+                let Some((lower, upper)) = rule.bounds_over(rows, field) else {
+                    return;
+                };
+                info!("Filtering out outliers");
+                let removed = drop_outside(rows, field, (lower, upper), |r, value| {
+                    detail!("row {}: {} {} is an outlier", r.line, header, value);
+                    rejected.push(RejectedRow {
+                        line: r.line,
+                        record: r.record.clone(),
+                        reason: format!(
+                            "{} {} is an outlier, outside {} to {}",
+                            header,
+                            value,
+                            lower.normalize(),
+                            upper.normalize()
+                        ),
+                    });
+                });
+                if removed > 0 {
+                    summary.count_drop(DropReason::Outlier, Some(header), removed);
+                }
+                summary.outliers = Some(OutlierCount {
+                    column: header.to_string(),
+                    lower,
+                    upper,
+                    removed,
+                });
+            }
+            Step::SortByDate => rows.sort_by_key(|r| r.date),
+            Step::MovingAverage { average, field } => {
+                let averages = average.over_rows(rows, field);
+                for (row, value) in rows.iter_mut().zip(averages) {
+                    row.added.push(average_cell(value));
+                }
+            }
+        }
+    }
+
+    // Sorts and averages the rows of an --append run again once they're
+    // merged, as the steps did the new rows on their own. Outliers stay as
+    // they were, and an average whose column or source isn't written is
+    // left alone
+    fn resequence(&self, rows: &mut [Vec<String>], names: &[&str], output: &OutputOptions) {
+        let position = |name: &str| names.iter().position(|n| *n == name);
+        fn cell(cells: &[String], i: usize) -> &str {
+            cells.get(i).map_or("", String::as_str)
+        }
+        for step in &self.steps {
+            match step {
+                Step::Outliers(_) => {}
+                Step::SortByDate => {
+                    if let Some(date) = position(output.date_header()) {
+                        rows.sort_by_key(|cells| output.read_date(cell(cells, date)));
+                    }
+                }
+                Step::MovingAverage { average, field } => {
+                    let Some(column) = self.find_column(field) else {
+                        continue;
+                    };
+                    let (Some(source), Some(target)) = (
+                        position(column.output),
+                        position(&average.header(column.header)),
+                    ) else {
+                        continue;
+                    };
+                    let values = rows
+                        .iter()
+                        .map(|cells| Money::from_str(cell(cells, source)).ok());
+                    let averages = average.over_gaps(values);
+                    for (cells, value) in rows.iter_mut().zip(averages) {
+                        if let Some(cell) = cells.get_mut(target) {
+                            *cell = average_cell(value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn cells(&self, row: &CleanRow, output: &OutputOptions) -> Vec<String> {
+        let precision = output.precision;
+        let cell = |column: &Column, value: &Value| match value {
+            Value::Text(text) => text.clone(),
+            Value::Count(count) => count.to_string(),
+            Value::Money(amount) => match column.kind {
+                FieldKind::Total => format_money(*amount, precision.totals),
+                _ => format_money(*amount, precision.prices),
+            },
+            Value::Percent(percent) => percent.map(|p| p.to_string()).unwrap_or_default(),
+            Value::Blank => String::new(),
+        };
+        let present = |optional: bool| {
+            self.columns
+                .iter()
+                .zip(&row.values)
+                .filter(move |(column, _)| column.optional == optional)
+                .filter_map(move |(column, (_, value))| value.as_ref().map(|v| cell(column, v)))
+        };
+        let mut out: Vec<String> = present(false).collect();
+        out.extend(row.added.iter().cloned());
+        out.push(output.format_date(row.date));
+        if self.currency {
+            let code = |c: Option<Currency>| c.map(|c| c.to_string()).unwrap_or_default();
+            match &self.options.rates {
+                Some(rates) => out.extend([rates.base.to_string(), code(row.currency)]),
+                None => out.push(code(row.currency)),
+            }
+        }
+        out.extend(output.date_columns.values(row.date, &row.raw_date));
+        out.extend(present(true));
+        out
+    }
+}
+
+/// How every export and cleaned file is read: a header row, cells trimmed and
+/// records of any length, to be checked against the header by whoever reads
+/// them. csv drops a UTF-8 BOM before the header itself.
+pub fn reader_builder(delimiter: u8) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .delimiter(delimiter);
+    builder
+}
+
+// A file or standard input, decoded to UTF-8
+type Input = Decoder<Box<dyn Read>>;
+
+/// Opens the export `pipeline` reads, saying which it was and why when it
+/// can't. "-" reads standard input, and a path ending in ".gz" is gunzipped.
+fn open_input(
+    pipeline: &str,
+    path: &Path,
+    options: &ParseOptions,
+) -> Result<Reader<Input>, DataAnalysisError> {
+    let unreadable = |source| DataAnalysisError::input(pipeline, path, source);
+    let source: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else if path.is_dir() {
+        return Err(unreadable(io::ErrorKind::IsADirectory.into()));
+    } else {
+        // The first worksheet, as CSV, and so UTF-8 whatever --encoding says
+        #[cfg(feature = "xlsx")]
+        if !compress::is_gzipped(path) && OutputFormat::from_path(path) == Some(OutputFormat::Xlsx)
+        {
+            let file = std::fs::File::open(path).map_err(unreadable)?;
+            let csv: Box<dyn Read> =
+                Box::new(io::Cursor::new(read_xlsx(file, path, options.delimiter)?));
+            return Ok(reader_builder(options.delimiter)
+                .from_reader(Decoder::new(csv, crate::encoding::Encoding::Utf8)));
+        }
+        compress::open(path).map_err(unreadable)?
+    };
+    Ok(reader_builder(options.delimiter).from_reader(Decoder::new(source, options.encoding)))
+}
+
+/// Creates a cleaned file, and the folders it goes in if they're missing.
+/// "-" writes to standard output, and a dry run writes nowhere. A database
+/// table or a worksheet is named `table`.
+fn create_output(
+    path: &Path,
+    format: OutputFormat,
+    table: &str,
+    output: &OutputOptions,
+) -> Result<Box<dyn RowSink>, DataAnalysisError> {
+    #[cfg(feature = "sqlite")]
+    if format == OutputFormat::Sqlite {
+        return create_table(path, table, output);
+    }
+    #[cfg(not(any(feature = "sqlite", feature = "xlsx")))]
+    let _ = table;
+    let builder = output.writer_builder();
+    let plain = move |writer: Box<dyn Write + Send>| -> Box<dyn RowSink> {
+        match format {
+            OutputFormat::Jsonl => Box::new(JsonlSink::new(writer)),
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => Box::new(ArrowSink::parquet(
+                writer,
+                output.batch_size,
+                output.date_format(),
+            )),
+            #[cfg(feature = "arrow-ipc")]
+            OutputFormat::ArrowIpc => Box::new(ArrowSink::arrow_ipc(
+                writer,
+                output.batch_size,
+                output.date_format(),
+            )),
+            #[cfg(feature = "xlsx")]
+            OutputFormat::Xlsx => Box::new(XlsxSink::new(writer, table, output.date_format())),
+            // CSV, as a database was opened above
+            _ => Box::new(CsvSink::from_writer(builder.from_writer(writer))),
+        }
+    };
+    let gzip = output.gzip_level(path);
+    let sink = move |writer: Box<dyn Write + Send>| -> Box<dyn RowSink> {
+        match gzip {
+            Some(level) => Box::new(GzipSink::new(writer, level, plain)),
+            None => plain(writer),
+        }
+    };
+    if output.dry_run {
+        info!("Dry run: not writing {}", path.display());
+        return Ok(sink(Box::new(io::sink())));
+    }
+    if path == Path::new("-") {
+        return Ok(sink(Box::new(io::stdout())));
+    }
+    // --append may name the file it writes, which is only replaced once done
+    if path.exists() && !output.overwrite && output.append.as_deref() != Some(path) {
+        return Err(format!(
+            "{} is already there; pass --overwrite to replace it",
+            path.display()
+        )
+        .into());
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| DataAnalysisError::io("create", dir, e))?;
+    }
+    Ok(Box::new(FileSink::create_with(path, |file| {
+        sink(Box::new(file))
+    })?))
+}
+
+/// Opens the database at `path` to write `table` in, and the folders it goes
+/// in if they're missing. The table is replaced under --overwrite, and added
+/// to when --append names the database; a dry run writes to one in memory.
+#[cfg(feature = "sqlite")]
+fn create_table(
+    path: &Path,
+    table: &str,
+    output: &OutputOptions,
+) -> Result<Box<dyn RowSink>, DataAnalysisError> {
+    let date_format = output.date_format();
+    if output.dry_run {
+        info!("Dry run: not writing {}", path.display());
+        return Ok(Box::new(SqliteSink::in_memory(table, date_format)?));
+    }
+    if path == Path::new("-") {
+        return Err("a SQLite database can't go to standard output; give it a file".into());
+    }
+    if output.gzip_level(path).is_some() {
+        return Err("a SQLite database can't be gzipped; leave off --compress and the .gz".into());
+    }
+    let mode = match &output.append {
+        Some(append) if append == path => TableMode::Append,
+        Some(append) => {
+            return Err(format!(
+                "--append adds to the table in the database it names, so give {} as --output too",
+                append.display()
+            )
+            .into());
+        }
+        None if output.overwrite => TableMode::Replace,
+        None => TableMode::Create,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| DataAnalysisError::io("create", dir, e))?;
+    }
+    Ok(Box::new(SqliteSink::open(path, table, mode, date_format)?))
+}
+
+// What --append reads `path` as: what its extension says, or else the
+// run's first --format
+fn append_format(path: &Path, output: &OutputOptions) -> OutputFormat {
+    OutputFormat::from_path(path)
+        .or(output.formats.first().copied())
+        .unwrap_or_default()
+}
+
+// What --append matches rows on, those of them the cleaned file has, and
+// then the date
+const APPEND_KEY: [&str; 3] = ["Segment", "Country", "Product"];
+
+// The cleaned file --append merges into, and where its key columns are
+struct Existing {
+    path: PathBuf,
+    rows: Vec<Vec<String>>,
+    key: Vec<usize>,
+}
+
+impl Existing {
+    // Fails unless the file has the columns this run writes, in its order.
+    // It's read as JSON Lines when its extension or the run's --format says
+    // so, and as CSV otherwise
+    fn read(
+        path: &Path,
+        names: &[&str],
+        output: &OutputOptions,
+    ) -> Result<Self, DataAnalysisError> {
+        let rows = match append_format(path, output) {
+            OutputFormat::Csv => read_csv_rows(path, names, output)?,
+            OutputFormat::Jsonl => read_jsonl_rows(path, names)?,
+            #[allow(unreachable_patterns)]
+            other => {
+                return Err(format!(
+                    "--append reads back CSV or JSON Lines, not {}",
+                    other.name()
+                )
+                .into());
+            }
+        };
+        let keyed: Vec<&str> = APPEND_KEY
+            .into_iter()
+            .filter(|column| names.contains(column))
+            .collect();
+        if keyed.is_empty() {
+            return Err(format!(
+                "--append matches rows on their {} and date, and the cleaned file has none of those",
+                APPEND_KEY.join(", ")
+            )
+            .into());
+        }
+        let date = names
+            .iter()
+            .position(|name| *name == output.date_header())
+            .ok_or_else(|| {
+                format!(
+                    "--append matches rows on their date, and the cleaned file has no {}",
+                    output.date_header()
+                )
+            })?;
+        let key = keyed
+            .iter()
+            .filter_map(|column| names.iter().position(|name| name == column))
+            .chain([date])
+            .collect();
+        Ok(Existing {
+            path: path.to_path_buf(),
+            rows,
+            key,
+        })
+    }
+
+    // Where the date is in each row, the last of the key
+    fn date(&self) -> usize {
+        self.key.last().copied().unwrap_or_default()
+    }
+
+    fn key<'r>(&self, cells: &[&'r str]) -> Vec<&'r str> {
+        self.key
+            .iter()
+            .map(|&i| cells.get(i).copied().unwrap_or(""))
+            .collect()
+    }
+
+    // The rows no new row replaces, then the new rows, counting those kept,
+    // replaced and added. Of new rows with the same key the last wins, as a
+    // resent row comes after the one it corrects; the others are given as
+    // (row, the later one) indices into `new`
+    fn merge(&self, new: &[Vec<&str>]) -> (Vec<Vec<String>>, AppendCount, Vec<(usize, usize)>) {
+        let mut last = HashMap::new();
+        for (i, cells) in new.iter().enumerate() {
+            last.insert(self.key(cells), i);
+        }
+        let mut count = AppendCount {
+            path: self.path.clone(),
+            ..AppendCount::default()
+        };
+        let mut merged = Vec::new();
+        let mut old_keys = HashSet::new();
+        for row in &self.rows {
+            let cells: Vec<&str> = row.iter().map(String::as_str).collect();
+            let key = self.key(&cells);
+            if last.contains_key(&key) {
+                count.replaced += 1;
+            } else {
+                merged.push(row.clone());
+                count.kept += 1;
+            }
+            old_keys.insert(key);
+        }
+        let mut repeated = Vec::new();
+        for (i, cells) in new.iter().enumerate() {
+            let key = self.key(cells);
+            match last.get(&key) {
+                Some(&later) if later != i => repeated.push((i, later)),
+                _ => {
+                    if !old_keys.contains(&key) {
+                        count.added += 1;
+                    }
+                    merged.push(cells.iter().map(|cell| cell.to_string()).collect());
+                }
+            }
+        }
+        (merged, count, repeated)
+    }
+}
+
+fn read_csv_rows(
+    path: &Path,
+    names: &[&str],
+    output: &OutputOptions,
+) -> Result<Vec<Vec<String>>, DataAnalysisError> {
+    let file = compress::open(path).map_err(|e| DataAnalysisError::io("read", path, e))?;
+    let mut rdr = reader_builder(output.delimiter).from_reader(file);
+    let headers = rdr.headers().map_err(DataAnalysisError::from)?;
+    if !headers.iter().eq(names.iter().copied()) {
+        let found: Vec<&str> = headers.iter().collect();
+        return Err(cant_append(path, &found, names));
+    }
+    rdr.records()
+        .map(|record| Ok(record?.iter().map(str::to_string).collect()))
+        .collect()
+}
+
+// Each line's object has a key for every column, as JsonlSink writes them
+fn read_jsonl_rows(path: &Path, names: &[&str]) -> Result<Vec<Vec<String>>, DataAnalysisError> {
+    let mut text = String::new();
+    compress::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| DataAnalysisError::io("read", path, e))?;
+    let keys: Vec<String> = names.iter().map(|name| snake_case(name)).collect();
+    let mut rows = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bad_json = |e: serde_json::Error| format!("{} line {}: {}", path.display(), i + 1, e);
+        let object: HashMap<String, &RawValue> = serde_json::from_str(line).map_err(bad_json)?;
+        if object.len() != keys.len() || keys.iter().any(|key| !object.contains_key(key)) {
+            let mut found: Vec<&str> = object.keys().map(String::as_str).collect();
+            found.sort_unstable();
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            return Err(cant_append(path, &found, &keys));
+        }
+        let cells = keys.iter().map(|key| match object[key].get() {
+            "null" => Ok(String::new()),
+            raw if raw.starts_with('"') => serde_json::from_str(raw).map_err(bad_json),
+            raw => Ok(raw.to_string()),
+        });
+        rows.push(cells.collect::<Result<_, _>>()?);
+    }
+    Ok(rows)
+}
+
+fn cant_append(path: &Path, found: &[&str], expected: &[&str]) -> DataAnalysisError {
+    format!(
+        "{} has the columns {}, not the {} this run writes, so it can't be appended to",
+        path.display(),
+        found.join(", "),
+        expected.join(", ")
+    )
+    .into()
+}
+
+// A moving average as the cleaned file has it
+fn average_cell(value: Option<Money>) -> String {
+    value
+        .map(|v| {
+            v.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+/// Where --rejects puts the rows left out of `output`: next to it, with
+/// "_rejected" after its name.
+pub fn rejects_path(output: &Path) -> PathBuf {
+    let output = without_gz(output);
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}_rejected.csv", stem))
+}
+
+// Writes the rows left out as they were read, after their line and why.
+// Those first, as a row of the wrong width has its own number of cells
+fn write_rejects(
+    path: &Path,
+    headers: &StringRecord,
+    rejected: &[RejectedRow],
+    output: &OutputOptions,
+) -> Result<(), DataAnalysisError> {
+    let mut sink = FileSink::create(path, output.writer_builder().flexible(true))?;
+    let mut header = vec!["Line", "Reason"];
+    header.extend(headers.iter());
+    sink.write_header(&header)?;
+    for row in rejected {
+        let line = row.line.to_string();
+        let mut cells = vec![line.as_str(), row.reason.as_str()];
+        cells.extend(row.record.iter());
+        sink.write_row(&cells)?;
+    }
+    sink.finish()?;
+    info!(
+        "Saved {} rejected rows to {}",
+        rejected.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Fails the run when more rows were dropped than --max-drop-rate allows.
+fn check_drop_rate(
+    summary: RunSummary,
+    output: &OutputOptions,
+) -> Result<RunSummary, DropRateExceeded> {
+    let rate = summary.drop_rate();
+    if rate <= output.max_drop_rate {
+        return Ok(summary);
+    }
+    Err(DropRateExceeded {
+        dropped: summary.rows_read - summary.rows_written,
+        read: summary.rows_read,
+        rate,
+        max: output.max_drop_rate,
+        summary: Box::new(summary),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::DateColumns;
+    use crate::parsing::MoneyPrecision;
+
+    const INPUT: &str = "Segment,Country,Product,Sales,Date\n";
+
+    // A file to --append to, unique to the test
+    fn existing(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("data_analysis_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn pipeline(options: &ParseOptions) -> CleanPipeline<'_> {
+        CleanPipeline::new("sales", options)
+            .column(Column::text("segment", "Segment"))
+            .column(Column::text("country", "Country"))
+            .column(Column::text("product", "Product"))
+            .column(Column::total("sales", "Sales"))
+            .step(Step::SortByDate)
+            .step(Step::MovingAverage {
+                average: MovingAverage {
+                    window: 2,
+                    min_periods: 1,
+                },
+                field: "sales",
+            })
+    }
+
+    fn append(path: &Path, rows: &str, output: &mut OutputOptions) -> (RunSummary, String) {
+        let options = ParseOptions::default();
+        output.append = Some(path.to_path_buf());
+        let mut cleaned = Vec::new();
+        let input = format!("{}{}", INPUT, rows);
+        let summary = match output.formats[0] {
+            OutputFormat::Jsonl => {
+                let mut sink = JsonlSink::new(&mut cleaned);
+                pipeline(&options).run_into(output, input.as_bytes(), &mut sink)
+            }
+            _ => pipeline(&options).run_on(output, input.as_bytes(), &mut cleaned),
+        };
+        std::fs::remove_file(path).unwrap();
+        (summary.unwrap(), String::from_utf8(cleaned).unwrap())
+    }
+
+    fn output() -> OutputOptions {
+        OutputOptions::new(MoneyPrecision::default(), DateColumns::default())
+    }
+
+    #[test]
+    fn append_keeps_the_last_of_the_new_rows_with_a_key() {
+        let path = existing(
+            "dedupe.csv",
+            "Segment,Country,Product,Sales,Sales_MA_2,Date_ISO\n\
+             Government,France,Paseo,10.00,10,2020-01-01\n\
+             Government,France,Paseo,20.00,15,2020-01-02\n",
+        );
+        let (summary, cleaned) = append(
+            &path,
+            "Government,France,Paseo,£30.00,02/01/2020\n\
+             Government,France,Paseo,£40.00,02/01/2020\n\
+             Midmarket,France,Paseo,£50.00,13/01/2020\n",
+            &mut output(),
+        );
+        let appended = summary.appended.as_ref().unwrap();
+        assert_eq!(
+            (appended.kept, appended.replaced, appended.added),
+            (1, 1, 1)
+        );
+        assert_eq!(summary.dropped_for(DropReason::Duplicate), 1);
+        assert_eq!(summary.rows_written, 2);
+        assert_eq!(
+            cleaned,
+            "Segment,Country,Product,Sales,Sales_MA_2,Date_ISO\n\
+             Government,France,Paseo,10.00,10,2020-01-01\n\
+             Government,France,Paseo,40.00,25,2020-01-02\n\
+             Midmarket,France,Paseo,50.00,45,2020-01-13\n"
+        );
+    }
+
+    #[test]
+    fn append_sorts_and_averages_the_merged_rows() {
+        let path = existing(
+            "sorted.csv",
+            "Segment,Country,Product,Sales,Sales_MA_2,Date_ISO\n\
+             Government,France,Paseo,10.00,10,2020-01-01\n\
+             Government,France,Paseo,30.00,20,2020-01-20\n",
+        );
+        let (_, cleaned) = append(
+            &path,
+            "Government,Mexico,Paseo,£20.00,15/01/2020\n\
+             Government,Mexico,Paseo,£40.00,25/01/2020\n",
+            &mut output(),
+        );
+        assert_eq!(
+            cleaned,
+            "Segment,Country,Product,Sales,Sales_MA_2,Date_ISO\n\
+             Government,France,Paseo,10.00,10,2020-01-01\n\
+             Government,Mexico,Paseo,20.00,15,2020-01-15\n\
+             Government,France,Paseo,30.00,25,2020-01-20\n\
+             Government,Mexico,Paseo,40.00,35,2020-01-25\n"
+        );
+    }
+
+    #[test]
+    fn append_reads_a_jsonl_file_as_jsonl() {
+        let path = existing(
+            "merged.jsonl",
+            "{\"segment\":\"Government\",\"country\":\"France\",\"product\":\"Paseo\",\
+             \"sales\":10.00,\"sales_ma_2\":10,\"date_iso\":\"2020-01-01\"}\n\
+             {\"segment\":\"Government\",\"country\":\"France\",\"product\":\"Paseo\",\
+             \"sales\":null,\"sales_ma_2\":10,\"date_iso\":\"2020-01-02\"}\n",
+        );
+        let mut output = output();
+        output.formats = vec![OutputFormat::Jsonl];
+        let (summary, cleaned) = append(
+            &path,
+            "Government,France,Paseo,£30.00,13/01/2020\n",
+            &mut output,
+        );
+        let appended = summary.appended.as_ref().unwrap();
+        assert_eq!(
+            (appended.kept, appended.replaced, appended.added),
+            (2, 0, 1)
+        );
+        let lines: Vec<&str> = cleaned.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[1].contains("\"sales\":null,\"sales_ma_2\":10,"),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].contains("\"sales\":30.00,\"sales_ma_2\":30,"),
+            "{}",
+            lines[2]
+        );
+    }
+
+    #[test]
+    fn a_cleaned_file_already_there_needs_overwrite() {
+        let input = existing(
+            "overwrite.csv",
+            "Country,Sales,Date\nFrance,£10.00,13/01/2020\n",
+        );
+        let cleaned = existing("overwrite_cleaned.csv", "left alone\n");
+        let options = ParseOptions::default();
+        let pipeline = || {
+            CleanPipeline::new("sales", &options)
+                .column(Column::text("country", "Country"))
+                .column(Column::total("sales", "Sales"))
+        };
+        let mut output = output();
+        let refused = pipeline().run(&output, &input, &cleaned).unwrap_err();
+        assert!(refused.to_string().contains("--overwrite"), "{}", refused);
+        assert_eq!(std::fs::read_to_string(&cleaned).unwrap(), "left alone\n");
+
+        output.overwrite = true;
+        pipeline().run(&output, &input, &cleaned).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&cleaned).unwrap(),
+            "Country,Sales,Date_ISO\nFrance,10.00,2020-01-13\n"
+        );
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&cleaned).unwrap();
+    }
+}
@@ -0,0 +1,658 @@
+use chrono::{Local, NaiveDate};
+use clap::{Args, Parser, Subcommand};
+use data_analysis::compress::{Compress, DEFAULT_LEVEL};
+use data_analysis::config::{Config, Delimiter, Settings};
+use data_analysis::dates::{DateOrder, DateParser, DateRange, NormalizeDates, Reconcile};
+use data_analysis::encoding::Encoding;
+use data_analysis::error::DataAnalysisError;
+use data_analysis::fields::{ColumnMode, ColumnNegatives, ParseMode, ParseOptions, RowSlice};
+use data_analysis::filters::RowFilter;
+#[cfg(feature = "dashboard")]
+use data_analysis::outliers::OutlierRule;
+use data_analysis::outliers::{OutlierColumn, OutlierMethod};
+#[cfg(feature = "forecasting")]
+use data_analysis::output::MovingAverage;
+use data_analysis::output::{
+    DEFAULT_BATCH_SIZE, DateColumns, LineEnding, OutputFormat, OutputOptions, QuoteStyle,
+};
+use data_analysis::parsing::{
+    CountRules, Currency, DEFAULT_FRACTION_TOLERANCE, Money, MoneyParser, MoneyPrecision,
+    NumberFormat, PercentMode,
+};
+use data_analysis::rates::ExchangeRates;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Where the exports live unless told otherwise
+pub const DATA_DIR: &str = "Data";
+pub const DASHBOARD_INPUT: &str = "Part_B_Dashboard_file.csv";
+#[cfg(feature = "dashboard")]
+pub const DASHBOARD_OUTPUT: &str = "Part_B_Dashboard_Cleaned.csv";
+pub const TIMESERIES_INPUT: &str = "Part_C_Timeseries.csv";
+#[cfg(feature = "timeseries")]
+pub const TIMESERIES_OUTPUT: &str = "Part_C_Timeseries_Cleaned.csv";
+pub const FORECAST_INPUT: &str = "Part_D_Forcasting.csv";
+#[cfg(feature = "forecasting")]
+pub const FORECAST_OUTPUT: &str = "Part_D_Forcasting_Cleaned.csv";
+
+/// Environment variables stand in for some flags as this and the flag's name,
+/// e.g. DATA_ANALYSIS_DELIMITER for --delimiter
+pub const ENV_PREFIX: &str = "DATA_ANALYSIS_";
+
+/// Cleans the sales exports in Data/ into *_Cleaned.csv files.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    after_help = "DATA_ANALYSIS_INPUT, _OUTPUT, _DATA_DIR, _CONFIG, _PROFILE, _DELIMITER, \
+                  _DATE_FORMATS, _MISSING_VALUES and _CURRENCY_SYMBOLS in the environment \
+                  stand in for their flags. Flags win over them, and they win over the \
+                  config file."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub clean: CleanArgs,
+}
+
+impl Cli {
+    /// What the command line, the environment and the config file set, in
+    /// that order of precedence. Anything none of them sets is left to the
+    /// built-in defaults.
+    pub fn settings(&self) -> Result<Settings, DataAnalysisError> {
+        let settings = Settings::resolve([self.flags()?, self.env()?, self.config()?]);
+        settings.check()?;
+        Ok(settings)
+    }
+
+    fn flags(&self) -> Result<Settings, String> {
+        let clean = &self.clean;
+        let settings = Settings {
+            delimiter: clean.delimiter,
+            date_formats: clean.date_formats.as_deref().map(list),
+            missing_values: clean.missing_values.as_deref().map(list),
+            currency_symbols: clean.currency_symbols.as_deref().map(list),
+            ..Settings::default()
+        };
+        match &self.command {
+            Some(Command::All(all)) => self.with_paths(settings, None, None, all.data_dir.clone()),
+            #[cfg(feature = "dashboard")]
+            Some(Command::Dashboard(paths)) => {
+                self.with_paths(settings, paths.input.clone(), paths.output.clone(), None)
+            }
+            #[cfg(feature = "timeseries")]
+            Some(Command::Timeseries(paths)) => {
+                self.with_paths(settings, paths.input.clone(), paths.output.clone(), None)
+            }
+            #[cfg(feature = "forecasting")]
+            Some(Command::Forecast(paths)) => {
+                self.with_paths(settings, paths.input.clone(), paths.output.clone(), None)
+            }
+            None => Ok(settings),
+        }
+    }
+
+    fn env(&self) -> Result<Settings, String> {
+        let delimiter = env("DELIMITER")
+            .map(|delimiter| delimiter.parse())
+            .transpose()
+            .map_err(|e| format!("{}DELIMITER: {}", ENV_PREFIX, e))?;
+        let settings = Settings {
+            delimiter,
+            date_formats: env("DATE_FORMATS")
+                .or_else(|| env("DATE_FORMAT"))
+                .as_deref()
+                .map(list),
+            missing_values: env("MISSING_VALUES").as_deref().map(list),
+            currency_symbols: env("CURRENCY_SYMBOLS").as_deref().map(list),
+            ..Settings::default()
+        };
+        self.with_paths(
+            settings,
+            env("INPUT").map(PathBuf::from),
+            env("OUTPUT").map(PathBuf::from),
+            env("DATA_DIR").map(PathBuf::from),
+        )
+    }
+
+    fn config(&self) -> Result<Settings, DataAnalysisError> {
+        let path = self
+            .clean
+            .config
+            .clone()
+            .or_else(|| env("CONFIG").map(PathBuf::from));
+        let profile = self.clean.profile.clone().or_else(|| env("PROFILE"));
+        match (path, profile) {
+            (Some(path), profile) => Config::load(&path)?.settings(profile.as_deref()),
+            (None, Some(profile)) => {
+                Err(format!("profile '{}' needs a --config file to come from", profile).into())
+            }
+            (None, None) => Ok(Settings::default()),
+        }
+    }
+
+    /// Points every dataset at its files in `data_dir`, then the one being
+    /// cleaned at `input` and `output`.
+    fn with_paths(
+        &self,
+        mut settings: Settings,
+        input: Option<PathBuf>,
+        output: Option<PathBuf>,
+        data_dir: Option<PathBuf>,
+    ) -> Result<Settings, String> {
+        if let Some(dir) = data_dir {
+            let datasets = [
+                (&mut settings.dashboard, DASHBOARD_INPUT),
+                (&mut settings.timeseries, TIMESERIES_INPUT),
+                (&mut settings.forecast, FORECAST_INPUT),
+            ];
+            for (dataset, input) in datasets {
+                dataset.input = Some(dir.join(input));
+                dataset.output_dir = Some(dir.clone());
+            }
+        }
+        let dataset = match &self.command {
+            #[cfg(feature = "dashboard")]
+            Some(Command::Dashboard(_)) => &mut settings.dashboard,
+            #[cfg(feature = "timeseries")]
+            Some(Command::Timeseries(_)) => &mut settings.timeseries,
+            #[cfg(feature = "forecasting")]
+            Some(Command::Forecast(_)) => &mut settings.forecast,
+            None => &mut settings.forecast,
+            Some(Command::All(_)) if input.is_some() || output.is_some() => {
+                return Err(format!(
+                    "{0}INPUT and {0}OUTPUT name one pipeline's files, so all can't use \
+                     them; set {0}DATA_DIR instead",
+                    ENV_PREFIX
+                ));
+            }
+            Some(Command::All(_)) => return Ok(settings),
+        };
+        dataset.input = input.or(dataset.input.take());
+        dataset.output = output.or(dataset.output.take());
+        Ok(settings)
+    }
+}
+
+/// A DATA_ANALYSIS_* variable, with an empty one counting as unset.
+fn env(name: &str) -> Option<String> {
+    std::env::var(format!("{}{}", ENV_PREFIX, name))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+fn list(values: &str) -> Vec<String> {
+    values
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .collect()
+}
+
+/// Pipelines left out of the build by their cargo feature have no command.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Part B: drops sale price outliers
+    #[cfg(feature = "dashboard")]
+    Dashboard(Paths),
+    /// Part C: the timeseries export
+    #[cfg(feature = "timeseries")]
+    Timeseries(Paths),
+    /// Part D: sorted by date with a sales moving average (the default)
+    #[cfg(feature = "forecasting")]
+    Forecast(Paths),
+    /// Every pipeline in the build, one after another
+    All(AllPaths),
+}
+
+/// Where one pipeline reads from and writes to.
+#[derive(Debug, Clone, Default, Args)]
+pub struct Paths {
+    /// The export to clean, or - for standard input. In a build with the
+    /// xlsx feature, an .xlsx file's first worksheet is read
+    /// [default: the pipeline's file in Data/]
+    #[arg(short, long)]
+    pub input: Option<PathBuf>,
+    /// Where to write the cleaned file, creating its folder if needed, or -
+    /// for standard output [default: the pipeline's *_Cleaned.csv in Data/]
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct AllPaths {
+    /// Folder holding the three exports, the cleaned files go alongside
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+    /// Stop at the first pipeline that fails instead of running the rest
+    #[arg(long)]
+    pub fail_fast: bool,
+}
+
+/// How the exports are read and the cleaned files written. These go before
+/// or after the subcommand and apply to every pipeline it runs.
+#[derive(Debug, Clone, Args)]
+pub struct CleanArgs {
+    /// Only print errors
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    /// Also print rows dropped for routine reasons, such as blank cells, and
+    /// a running row count
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    pub verbose: bool,
+    /// Read, check and summarise everything without writing the cleaned files
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Fail, with exit code 3, when more than this percentage of rows is dropped
+    #[arg(long, global = true, default_value = "100")]
+    pub max_drop_rate: Money,
+    /// Keep the cleaned file when --max-drop-rate fails the run
+    #[arg(long, global = true)]
+    pub keep_partial: bool,
+    /// Replace cleaned files that are already there
+    #[arg(long, global = true)]
+    pub overwrite: bool,
+    /// Write each pipeline's summary, with its paths, version and when it
+    /// ran, to this file as JSON: an object, or a list of them for `all`
+    #[arg(long, global = true)]
+    pub summary_out: Option<PathBuf>,
+    /// Write a Markdown data quality report to this file: rows in and out,
+    /// dates, outlier bounds, blanks per column and the top countries
+    #[arg(long, global = true)]
+    pub report_md: Option<PathBuf>,
+    /// Write an HTML profile of the cleaned columns to this file: blanks,
+    /// distinct values, number spreads and the most common values
+    #[arg(long, global = true)]
+    pub report_html: Option<PathBuf>,
+    /// Merge the new rows into this cleaned file, CSV or JSON Lines; --output
+    /// can be the same file, replaced once the run is done. A new row
+    /// replaces any there, or any earlier new row, with the same Segment,
+    /// Country and Product (of those the file has) and date. The forecast is
+    /// sorted and averaged again over the merged rows. With --format sqlite,
+    /// the database written, whose table is added to if its columns match.
+    /// Not for `all`, as each pipeline has its own file
+    #[arg(long, global = true)]
+    pub append: Option<PathBuf>,
+    /// Write the rows each pipeline left out to <output>_rejected.csv, with
+    /// their line and why. Only made when a row was left out
+    #[arg(long, global = true)]
+    pub rejects: bool,
+    /// Name each cleaned file from this, next to its export, e.g.
+    /// "{stem}_cleaned_{date}.csv" where {stem} is the export's name without
+    /// its extension and {date} is today. An output given for the pipeline wins
+    #[arg(long, global = true)]
+    pub output_template: Option<String>,
+    /// TOML file mapping each dataset's columns, date formats and missing markers
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Settings from the config's [profile.<name>] on top of its top level
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    /// What the exports are written in: utf-8, windows-1252 or latin-1
+    #[arg(long, global = true, default_value = "utf-8")]
+    pub encoding: Encoding,
+    /// Read an export whose header isn't the usual one, by column name
+    #[arg(long, global = true)]
+    pub no_header_check: bool,
+    /// Stop reading, and fail, once this many bad values and rows with the
+    /// wrong number of fields have been met, as when the delimiter is wrong
+    #[arg(long, global = true)]
+    pub max_errors: Option<usize>,
+    /// Field separator in the exports, e.g. ';' or \t [default: ,]
+    #[arg(long, global = true)]
+    pub delimiter: Option<Delimiter>,
+    /// Field separator in the cleaned files
+    #[arg(long, global = true, default_value = ",")]
+    pub output_delimiter: Delimiter,
+    /// When cells in the cleaned files are quoted: necessary, always, or never,
+    /// in which case a cell holding the delimiter won't read back
+    #[arg(long, global = true, default_value = "necessary")]
+    pub quote_style: QuoteStyle,
+    /// Line ending in the cleaned files: lf or crlf
+    #[arg(long, global = true, default_value = "lf")]
+    pub terminator: LineEnding,
+    /// What the cleaned files are written as: csv, or jsonl for a JSON object
+    /// per row, with numbers as numbers and blanks as null, or in a build with
+    /// the feature of the same name, parquet, arrow-ipc (an Arrow IPC file,
+    /// .arrow), sqlite (a table in a SQLite database, see --table) or xlsx.
+    /// The default file names take its extension. Repeat it or list several, e.g. csv,jsonl, to write each
+    /// from one pass, named from the output with each extension
+    #[arg(long, global = true, value_delimiter = ',', default_value = "csv")]
+    pub format: Vec<OutputFormat>,
+    /// Rows in each record batch of a Parquet or Arrow IPC file
+    #[arg(long, global = true, default_value_t = DEFAULT_BATCH_SIZE)]
+    pub batch_size: usize,
+    /// The table --format sqlite writes, or the worksheet of --format xlsx,
+    /// named after the pipeline by default. A table already there fails the
+    /// run unless --overwrite replaces it or --append names the database to
+    /// add to it
+    #[arg(long, global = true)]
+    pub table: Option<String>,
+    /// Compress the cleaned files: none, or gzip, adding ".gz" to their
+    /// names. An output or export path ending in ".gz" is gzipped whatever
+    /// this says
+    #[arg(long, global = true, default_value = "none")]
+    pub compress: Compress,
+    /// How hard gzip works, from 0 (not at all) to 9 (smallest files)
+    #[arg(long, global = true, default_value_t = DEFAULT_LEVEL)]
+    pub compress_level: u32,
+    /// "1.234,56" style input: continental
+    #[arg(long, global = true)]
+    pub number_format: Option<NumberFormat>,
+    /// Bad numeric fields: strict, skip-row (the default), default-zero or
+    /// keep-blank. Under skip-row a missing total, such as Discounts, is
+    /// zero. Rows with bad dates are always skipped, unless strict stops the
+    /// run at the first one. The forecast's Sales skips the row under
+    /// default-zero or a missing value, as a zero would drag its moving
+    /// average down
+    #[arg(long, global = true)]
+    pub parse_mode: Option<ParseMode>,
+    /// One field's parse mode, e.g. "sales=default-zero" for the forecast's
+    /// old zeroed sales; repeat for several
+    #[arg(long, global = true)]
+    pub column_mode: Vec<ColumnMode>,
+    /// What one field does with a negative value: allow, reject, clamp-to-zero
+    /// or absolute-value, e.g. "discounts=reject"; repeat for several. Units
+    /// Sold and prices reject them and totals allow them unless told
+    #[arg(long, global = true)]
+    pub negatives: Vec<ColumnNegatives>,
+    /// Stop at the first bad field or date, naming its row and column;
+    /// short for --parse-mode strict
+    #[arg(long, global = true, conflicts_with = "parse_mode")]
+    pub strict: bool,
+    /// Units Sold fractions up to this much are rounded away, so with 0.5
+    /// "1,630.50" is 1630. Unless given any fraction is rejected and counted
+    /// in the summary
+    #[arg(long, global = true)]
+    pub units_tolerance: Option<rust_decimal::Decimal>,
+    /// Accept negative Units Sold, as returns; short for --negatives units_sold=allow
+    #[arg(long, global = true)]
+    pub allow_returns: bool,
+    /// Reject Units Sold above this
+    #[arg(long, global = true)]
+    pub max_units: Option<i64>,
+    /// Sanity ceiling for money cells, e.g. 1000000000
+    #[arg(long, global = true)]
+    pub max_amount: Option<Money>,
+    /// Read a lone dash in a money cell ("£-") as zero, the accounting way,
+    /// rather than as missing
+    #[arg(long, global = true)]
+    pub dash_is_zero: bool,
+    /// Markers for blank cells, e.g. "null,N/A,-"
+    #[arg(long, global = true)]
+    pub missing_values: Option<String>,
+    /// Only accept the symbols a file is known to use, e.g. "$,£"
+    #[arg(long, global = true)]
+    pub currency_symbols: Option<String>,
+    /// Currency the --rates table converts into
+    #[arg(long, global = true, default_value = "USD")]
+    pub base_currency: Currency,
+    /// Convert money columns into one currency with a table of
+    /// currency,rate rows, e.g. Data/rates.csv
+    #[arg(long, global = true)]
+    pub rates: Option<PathBuf>,
+    /// Date formats to try after ISO, in order, e.g. "%m/%d/%Y,%d/%m/%Y"
+    #[arg(long, global = true, visible_alias = "date-format")]
+    pub date_formats: Option<String>,
+    /// Rows sampled to pick the file's date format; 0 turns it off
+    #[arg(long, global = true)]
+    pub date_sample: Option<usize>,
+    /// Don't read numbers in the date column as Excel serials
+    #[arg(long, global = true)]
+    pub no_excel_dates: bool,
+    /// Two-digit years below the pivot are 20xx, e.g. 50
+    #[arg(long, global = true)]
+    pub year_pivot: Option<i32>,
+    /// day-first or month-first, needed when every date could be either way round
+    #[arg(long, global = true)]
+    pub date_order: Option<DateOrder>,
+    /// Skip this many data rows after the header before reading any
+    #[arg(long, global = true, default_value_t = 0)]
+    pub offset: usize,
+    /// Read at most this many data rows, after any --offset. The dashboard's
+    /// outlier bounds then come from these rows alone
+    #[arg(long, global = true)]
+    pub limit: Option<usize>,
+    /// Only rows where a column has, or with != doesn't have, a value, e.g.
+    /// "Country=France"; repeat to require several
+    #[arg(long, global = true)]
+    pub filter: Vec<RowFilter>,
+    /// Match --filter values exactly instead of ignoring case
+    #[arg(long, global = true)]
+    pub case_sensitive_filters: bool,
+    /// Only rows dated on or after this, e.g. 2014-01-01
+    #[arg(long, global = true)]
+    pub from_date: Option<NaiveDate>,
+    /// Only rows dated on or before this, e.g. 2014-12-31
+    #[arg(long, global = true)]
+    pub to_date: Option<NaiveDate>,
+    /// When Date and Month/Year disagree: prefer-date, prefer-month-year or reject
+    #[arg(long, global = true, default_value = "reject")]
+    pub reconcile_dates: Reconcile,
+    /// Monthly data stamped on different days: first-of-month, end-of-month or none
+    #[arg(long, global = true, default_value = "none")]
+    pub normalize_dates: NormalizeDates,
+    /// How to read "12.5" in a Discount % column: auto, percent or fraction
+    #[arg(long, global = true, default_value = "auto")]
+    pub percent_mode: PercentMode,
+    /// How the dashboard finds outliers: iqr, or none to keep every row
+    #[arg(long, global = true, default_value = "iqr")]
+    pub outlier_method: OutlierMethod,
+    /// The dashboard column outliers are judged on
+    #[arg(long, global = true, default_value = "Sale Price")]
+    pub outlier_column: OutlierColumn,
+    /// Interquartile ranges past the quartiles a value can be and still be kept
+    #[arg(long, global = true, default_value = "1.5")]
+    pub outlier_multiplier: Money,
+    /// Rows in the forecast's sales moving average
+    #[arg(long, global = true, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    pub ma_window: u64,
+    /// Average the first rows over as few as this many, instead of leaving
+    /// them blank until the window fills
+    #[arg(long, global = true)]
+    pub ma_min_periods: Option<u64>,
+    /// Decimal places for price columns
+    #[arg(long, global = true, default_value_t = 2)]
+    pub price_decimals: u32,
+    /// Decimal places for total columns
+    #[arg(long, global = true, default_value_t = 2)]
+    pub total_decimals: u32,
+    /// Adds Fiscal Year and Fiscal Quarter for years starting in this month, e.g. 7
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..=12))]
+    pub fiscal_year_start: Option<u32>,
+    /// Adds Fiscal Year and Fiscal Quarter for calendar years
+    #[arg(long, global = true)]
+    pub fiscal_columns: bool,
+    /// Adds ISO Year, ISO Week and Day of Week
+    #[arg(long, global = true)]
+    pub add_calendar_cols: bool,
+    /// Adds days since 1970-01-01 as Date_Epoch_Days
+    #[arg(long, global = true)]
+    pub epoch_days: bool,
+    /// Adds the date as written, next to the cleaned one
+    #[arg(long, global = true)]
+    pub keep_raw_date: bool,
+    /// Only write these cleaned columns, in this order, e.g.
+    /// "Country,Product,Sales Parsed,Date_ISO"
+    #[arg(long, global = true)]
+    pub select: Option<String>,
+    /// Dates written some other way than ISO, e.g. "%Y%m%d"
+    #[arg(long, global = true)]
+    pub output_date_format: Option<String>,
+}
+
+impl CleanArgs {
+    /// How every pipeline reads its export, before its dataset's settings.
+    pub fn parse_options(&self) -> Result<ParseOptions, DataAnalysisError> {
+        let mut money = MoneyParser::new()
+            .with_number_format(self.number_format.unwrap_or_default())
+            .with_dash_as_zero(self.dash_is_zero);
+        if let Some(ceiling) = self.max_amount {
+            money = money.with_ceiling(ceiling);
+        }
+        let rates = match &self.rates {
+            Some(path) => Some(ExchangeRates::load(path, self.base_currency)?),
+            None => None,
+        };
+
+        let mut dates = DateParser::new();
+        if let Some(sample) = self.date_sample {
+            dates = dates.with_detection(sample);
+        }
+        if self.no_excel_dates {
+            dates = dates.with_excel_serials(false);
+        }
+        if let Some(pivot) = self.year_pivot {
+            dates = dates.with_year_pivot(pivot);
+        }
+        if let Some(order) = self.date_order {
+            dates = dates.with_order(order);
+        }
+
+        Ok(ParseOptions {
+            mode: match self.strict {
+                true => ParseMode::Strict,
+                false => self.parse_mode.unwrap_or_default(),
+            },
+            column_modes: self
+                .column_mode
+                .iter()
+                .map(|column| (column.field.clone(), column.mode))
+                .collect(),
+            money,
+            counts: CountRules {
+                fraction_tolerance: self.units_tolerance.unwrap_or(DEFAULT_FRACTION_TOLERANCE),
+                allow_negative: self.allow_returns,
+                max: self.max_units,
+            },
+            dates,
+            date_range: DateRange {
+                from: self.from_date,
+                to: self.to_date,
+            },
+            reconcile: self.reconcile_dates,
+            normalize_dates: self.normalize_dates,
+            columns: BTreeMap::new(),
+            delimiter: b',',
+            rows: RowSlice {
+                offset: self.offset,
+                limit: self.limit,
+            },
+            filters: self.filter.clone(),
+            case_sensitive_filters: self.case_sensitive_filters,
+            rates,
+            check_headers: !self.no_header_check,
+            encoding: self.encoding,
+            negatives: self
+                .negatives
+                .iter()
+                .map(|column| (column.field.clone(), column.policy))
+                .collect(),
+            max_errors: self.max_errors,
+        })
+    }
+
+    pub fn output_options(&self) -> Result<OutputOptions, DataAnalysisError> {
+        let precision = MoneyPrecision {
+            prices: self.price_decimals,
+            totals: self.total_decimals,
+        };
+        let date_columns = DateColumns {
+            fiscal_year_start: self.fiscal_year_start.or(self.fiscal_columns.then_some(1)),
+            calendar: self.add_calendar_cols,
+            epoch_days: self.epoch_days,
+            raw: self.keep_raw_date,
+        };
+        let mut output = OutputOptions::new(precision, date_columns);
+        output.dry_run = self.dry_run;
+        output.delimiter = self.output_delimiter.0;
+        output.quote_style = self.quote_style;
+        output.terminator = self.terminator;
+        output.formats = Vec::new();
+        for format in &self.format {
+            if !output.formats.contains(format) {
+                output.formats.push(*format);
+            }
+        }
+        if self.batch_size == 0 {
+            return Err("--batch-size needs at least one row".into());
+        }
+        output.batch_size = self.batch_size;
+        if let Some(table) = &self.table {
+            if table.trim().is_empty() {
+                return Err("--table is empty".into());
+            }
+            output.table = Some(table.trim().to_string());
+        }
+        if self.compress_level > 9 {
+            return Err(format!(
+                "--compress-level is from 0 to 9, not {}",
+                self.compress_level
+            )
+            .into());
+        }
+        output.compress = self.compress;
+        output.compress_level = self.compress_level;
+        if !(Money::ZERO..=Money::ONE_HUNDRED).contains(&self.max_drop_rate) {
+            return Err(format!(
+                "--max-drop-rate is a percentage from 0 to 100, not {}",
+                self.max_drop_rate
+            )
+            .into());
+        }
+        output.max_drop_rate = self.max_drop_rate;
+        output.keep_partial = self.keep_partial;
+        output.overwrite = self.overwrite;
+        output.rejects = self.rejects;
+        output.profile = self.report_md.is_some() || self.report_html.is_some();
+        output.append = self.append.clone();
+        if let Some(template) = &self.output_template {
+            output = output.with_output_template(template, Local::now().date_naive())?;
+        }
+        output.select = self.select.as_ref().map(|list| {
+            list.split(',')
+                .map(|name| name.trim().to_string())
+                .collect()
+        });
+        if let Some(format) = &self.output_date_format {
+            output = output.with_date_format(format)?;
+        }
+        Ok(output)
+    }
+
+    /// The forecast's moving average, failing if it couldn't fill.
+    #[cfg(feature = "forecasting")]
+    pub fn moving_average(&self) -> Result<MovingAverage, DataAnalysisError> {
+        let window = self.ma_window as usize;
+        let min_periods = match self.ma_min_periods {
+            None => window,
+            Some(min) if (1..=self.ma_window).contains(&min) => min as usize,
+            Some(min) => {
+                return Err(format!(
+                    "--ma-min-periods must be between 1 and the window of {}, not {}",
+                    window, min
+                )
+                .into());
+            }
+        };
+        Ok(MovingAverage {
+            window,
+            min_periods,
+        })
+    }
+
+    #[cfg(feature = "dashboard")]
+    pub fn outlier_rule(&self) -> Result<OutlierRule, DataAnalysisError> {
+        if self.outlier_multiplier.is_sign_negative() {
+            return Err(format!(
+                "--outlier-multiplier can't be negative, not {}",
+                self.outlier_multiplier
+            )
+            .into());
+        }
+        Ok(OutlierRule {
+            method: self.outlier_method,
+            field: self.outlier_column.field(),
+            multiplier: self.outlier_multiplier,
+        })
+    }
+}
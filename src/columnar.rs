@@ -0,0 +1,356 @@
+//! Typed columnar output: the cleaned rows as Arrow record batches, written
+//! to a Parquet or Arrow IPC file.
+
+use crate::error::DataAnalysisError;
+use crate::output::epoch_days;
+use crate::sink::{CellKind, RowSink};
+use arrow::array::{ArrayRef, Date32Builder, Float64Builder, Int64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+#[cfg(feature = "arrow-ipc")]
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use chrono::NaiveDate;
+#[cfg(feature = "parquet")]
+use parquet::arrow::ArrowWriter;
+#[cfg(feature = "parquet")]
+use parquet::basic::Compression;
+#[cfg(feature = "parquet")]
+use parquet::file::properties::WriterProperties;
+use std::io::Write;
+use std::sync::Arc;
+
+/// The Arrow schema for a cleaned file's header: Utf8 for text, Int64 for
+/// whole numbers, Float64 for money and the other decimals, and Date32 for
+/// the date. Every column can be null, as a blank cell is.
+pub fn schema(header: &[&str], kinds: &[CellKind]) -> Schema {
+    let fields: Vec<Field> = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let kind = kinds.get(i).copied().unwrap_or(CellKind::Text);
+            Field::new(*name, data_type(kind), true)
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+fn data_type(kind: CellKind) -> DataType {
+    match kind {
+        CellKind::Text => DataType::Utf8,
+        CellKind::Money | CellKind::Number => DataType::Float64,
+        CellKind::Integer => DataType::Int64,
+        CellKind::Date => DataType::Date32,
+    }
+}
+
+// Where a batch goes once it's full: a Parquet or Arrow IPC file
+trait BatchWriter {
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), DataAnalysisError>;
+    // Writes the file's footer
+    fn close(self: Box<Self>) -> Result<(), DataAnalysisError>;
+}
+
+#[cfg(feature = "parquet")]
+impl<W: Write + Send> BatchWriter for ArrowWriter<W> {
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), DataAnalysisError> {
+        Ok(ArrowWriter::write(self, batch)?)
+    }
+
+    fn close(self: Box<Self>) -> Result<(), DataAnalysisError> {
+        ArrowWriter::close(*self)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "arrow-ipc")]
+impl<W: Write> BatchWriter for FileWriter<W> {
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), DataAnalysisError> {
+        Ok(FileWriter::write(self, batch)?)
+    }
+
+    fn close(mut self: Box<Self>) -> Result<(), DataAnalysisError> {
+        Ok(self.finish()?)
+    }
+}
+
+// Opens the file once the header gives its schema
+type OpenBatches = Box<dyn FnOnce(SchemaRef) -> Result<Box<dyn BatchWriter>, DataAnalysisError>>;
+
+/// Writes rows as Arrow record batches of `batch_size` rows, typed by
+/// [`schema`]. A blank cell is null, and dates are read back with the chrono
+/// format they were written in.
+pub struct ArrowSink {
+    open: Option<OpenBatches>,
+    writer: Option<Box<dyn BatchWriter>>,
+    schema: Option<SchemaRef>,
+    kinds: Vec<CellKind>,
+    columns: Vec<ColumnBuilder>,
+    rows: usize,
+    batch_size: usize,
+    date_format: String,
+}
+
+impl ArrowSink {
+    /// A Parquet file, snappy compressed.
+    #[cfg(feature = "parquet")]
+    pub fn parquet(
+        writer: impl Write + Send + 'static,
+        batch_size: usize,
+        date_format: &str,
+    ) -> Self {
+        ArrowSink::new(
+            Box::new(move |schema| {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                Ok(Box::new(ArrowWriter::try_new(writer, schema, Some(properties))?) as _)
+            }),
+            batch_size,
+            date_format,
+        )
+    }
+
+    /// An Arrow IPC file, in the File format with its footer, as Feather
+    /// readers expect.
+    #[cfg(feature = "arrow-ipc")]
+    pub fn arrow_ipc(writer: impl Write + 'static, batch_size: usize, date_format: &str) -> Self {
+        ArrowSink::new(
+            Box::new(move |schema| Ok(Box::new(FileWriter::try_new(writer, &schema)?) as _)),
+            batch_size,
+            date_format,
+        )
+    }
+
+    fn new(open: OpenBatches, batch_size: usize, date_format: &str) -> Self {
+        ArrowSink {
+            open: Some(open),
+            writer: None,
+            schema: None,
+            kinds: Vec::new(),
+            columns: Vec::new(),
+            rows: 0,
+            batch_size: batch_size.max(1),
+            date_format: date_format.to_string(),
+        }
+    }
+
+    // Hands the rows so far to the writer as one batch
+    fn flush_batch(&mut self) -> Result<(), DataAnalysisError> {
+        let (Some(writer), Some(schema)) = (&mut self.writer, &self.schema) else {
+            return Ok(());
+        };
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let columns: Vec<ArrayRef> = self.columns.iter_mut().map(ColumnBuilder::finish).collect();
+        writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+impl RowSink for ArrowSink {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        let Some(open) = self.open.take() else {
+            return Err("the header was already written".into());
+        };
+        let schema = Arc::new(schema(header, &self.kinds));
+        self.columns = schema
+            .fields()
+            .iter()
+            .map(|field| ColumnBuilder::new(field.name(), field.data_type()))
+            .collect();
+        self.writer = Some(open(schema.clone())?);
+        self.schema = Some(schema);
+        Ok(())
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        for (i, column) in self.columns.iter_mut().enumerate() {
+            let cell = cells.get(i).copied().unwrap_or_default();
+            column.append(cell, &self.date_format)?;
+        }
+        self.rows += 1;
+        if self.rows >= self.batch_size {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        self.flush_batch()?;
+        match self.writer.take() {
+            Some(writer) => writer.close(),
+            None => Ok(()),
+        }
+    }
+
+    fn column_kinds(&mut self, kinds: &[CellKind]) {
+        self.kinds = kinds.to_vec();
+    }
+}
+
+// One column of the batch being built
+struct ColumnBuilder {
+    name: String,
+    values: Values,
+}
+
+enum Values {
+    Text(StringBuilder),
+    Number(Float64Builder),
+    Integer(Int64Builder),
+    Date(Date32Builder),
+}
+
+impl ColumnBuilder {
+    fn new(name: &str, data_type: &DataType) -> Self {
+        let values = match data_type {
+            DataType::Float64 => Values::Number(Float64Builder::new()),
+            DataType::Int64 => Values::Integer(Int64Builder::new()),
+            DataType::Date32 => Values::Date(Date32Builder::new()),
+            _ => Values::Text(StringBuilder::new()),
+        };
+        ColumnBuilder {
+            name: name.to_string(),
+            values,
+        }
+    }
+
+    fn append(&mut self, cell: &str, date_format: &str) -> Result<(), DataAnalysisError> {
+        let bad = |what: &str| format!("{} '{}' isn't {}", self.name, cell, what);
+        if cell.is_empty() {
+            match &mut self.values {
+                Values::Text(values) => values.append_null(),
+                Values::Number(values) => values.append_null(),
+                Values::Integer(values) => values.append_null(),
+                Values::Date(values) => values.append_null(),
+            }
+            return Ok(());
+        }
+        match &mut self.values {
+            Values::Text(values) => values.append_value(cell),
+            Values::Number(values) => {
+                values.append_value(cell.parse().map_err(|_| bad("a number"))?)
+            }
+            Values::Integer(values) => {
+                values.append_value(cell.parse().map_err(|_| bad("a whole number"))?)
+            }
+            Values::Date(values) => {
+                let date = NaiveDate::parse_from_str(cell, date_format)
+                    .map_err(|_| bad(&format!("a {} date", date_format)))?;
+                let days = i32::try_from(epoch_days(date)).map_err(|_| bad("a Date32 date"))?;
+                values.append_value(days);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match &mut self.values {
+            Values::Text(values) => Arc::new(values.finish()),
+            Values::Number(values) => Arc::new(values.finish()),
+            Values::Integer(values) => Arc::new(values.finish()),
+            Values::Date(values) => Arc::new(values.finish()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::{CleanPipeline, Column, Step};
+    use crate::fields::ParseOptions;
+    use crate::output::{DateColumns, MovingAverage, OutputOptions};
+    use crate::parsing::MoneyPrecision;
+    use arrow::array::{Array, Date32Array, Float64Array, Int64Array, StringArray};
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    const INPUT: &str = "Country,Units Sold,Sales,Date\n\
+        France,10,£100.00,2020-01-01\n\
+        Mexico,20,£200.00,2020-01-02\n\
+        France,30,£300.00,2020-01-03\n";
+
+    // Cleans INPUT into the sink `sink` makes over a temporary file, in
+    // batches of two, giving the file
+    fn clean_into(name: &str, sink: fn(File, usize, &str) -> ArrowSink) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("data_analysis_{}_{}", std::process::id(), name));
+        let options = ParseOptions::default();
+        let output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        let mut sink = sink(File::create(&path).unwrap(), 2, output.date_format());
+        CleanPipeline::new("sales", &options)
+            .column(Column::text("country", "Country"))
+            .column(Column::count("units_sold", "Units Sold"))
+            .column(Column::total("sales", "Sales"))
+            .step(Step::MovingAverage {
+                average: MovingAverage {
+                    window: 2,
+                    min_periods: 2,
+                },
+                field: "sales",
+            })
+            .run_into(&output, INPUT.as_bytes(), &mut sink)
+            .unwrap();
+        path
+    }
+
+    // The batches read back are INPUT's rows, typed, with the moving
+    // average's first cell null
+    fn check(batches: &[RecordBatch]) {
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), batches).unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+
+        let country = column("Country");
+        let country = country.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(country.value(1), "Mexico");
+        let units = column("Units Sold");
+        let units = units.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(units.value(2), 30);
+        let sales = column("Sales");
+        let sales = sales.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(sales.value(0), 100.0);
+        let average = column("Sales_MA_2");
+        let average = average.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert!(average.is_null(0));
+        assert_eq!(average.value(1), 150.0);
+        assert_eq!(average.value(2), 250.0);
+        let date = column("Date_ISO");
+        let date = date.as_any().downcast_ref::<Date32Array>().unwrap();
+        assert_eq!(date.value_as_date(2), NaiveDate::from_ymd_opt(2020, 1, 3));
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn parquet_reads_back_typed_with_nulls() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let path = clean_into("typed.parquet", |file, size, format| {
+            ArrowSink::parquet(file, size, format)
+        });
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        check(&batches);
+    }
+
+    #[test]
+    #[cfg(feature = "arrow-ipc")]
+    fn arrow_ipc_reads_back_typed_with_nulls() {
+        use arrow::ipc::reader::FileReader;
+
+        let path = clean_into("typed.arrow", |file, size, format| {
+            ArrowSink::arrow_ipc(file, size, format)
+        });
+        let reader = FileReader::try_new(File::open(&path).unwrap(), None).unwrap();
+        assert_eq!(reader.num_batches(), 2);
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        check(&batches);
+    }
+}
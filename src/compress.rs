@@ -0,0 +1,269 @@
+//! Gzip for the exports read and the cleaned files written, by way of a
+//! ".gz" on the end of the path or --compress.
+
+use crate::error::DataAnalysisError;
+use crate::sink::{CellKind, RowSink};
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// The gzip level unless --compress-level says otherwise, zlib's own.
+pub const DEFAULT_LEVEL: u32 = 6;
+
+/// How the cleaned files are compressed, besides any ".gz" path, which is
+/// always gzipped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compress {
+    #[default]
+    None,
+    Gzip,
+}
+
+impl FromStr for Compress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compress::None),
+            "gzip" | "gz" => Ok(Compress::Gzip),
+            other => Err(format!(
+                "unknown compression '{}' (expected none or gzip)",
+                other
+            )),
+        }
+    }
+}
+
+/// Whether `path` ends in ".gz".
+pub fn is_gzipped(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// `path` without a ".gz" on the end, so "sales.csv.gz" as "sales.csv".
+pub fn without_gz(path: &Path) -> PathBuf {
+    match is_gzipped(path) {
+        true => path.with_extension(""),
+        false => path.to_path_buf(),
+    }
+}
+
+/// Adds ".gz" to `path` unless it's there already.
+pub fn with_gz(path: &Path) -> PathBuf {
+    match is_gzipped(path) {
+        true => path.to_path_buf(),
+        false => {
+            let mut name = path.as_os_str().to_os_string();
+            name.push(".gz");
+            PathBuf::from(name)
+        }
+    }
+}
+
+/// Opens the file at `path` to read, gunzipping it when it ends in ".gz".
+pub fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let file = File::open(path)?;
+    Ok(match is_gzipped(path) {
+        // A file can be several gzip members one after another
+        true => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+        false => Box::new(file),
+    })
+}
+
+// The gzip stream a sink writes through, shared with the GzipSink that
+// finishes it
+struct SharedGzip<W: Write>(Arc<Mutex<Option<GzEncoder<W>>>>);
+
+impl<W: Write> Write for SharedGzip<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.with(|encoder| encoder.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.with(|encoder| encoder.flush())
+    }
+}
+
+impl<W: Write> SharedGzip<W> {
+    fn with<T>(&self, f: impl FnOnce(&mut GzEncoder<W>) -> io::Result<T>) -> io::Result<T> {
+        let mut encoder = self
+            .0
+            .lock()
+            .map_err(|_| io::Error::other("the gzip stream was poisoned"))?;
+        match encoder.as_mut() {
+            Some(encoder) => f(encoder),
+            None => Err(io::Error::other("the gzip stream is already finished")),
+        }
+    }
+}
+
+/// Another sink writing through gzip at `level`, 0 to 9. `finish` finishes
+/// that sink and then the gzip stream, so the trailer is written, or the
+/// run fails saying why, before a [`crate::sink::FileSink`] around it puts
+/// the file in place.
+pub struct GzipSink<W: Write> {
+    sink: Option<Box<dyn RowSink>>,
+    encoder: Arc<Mutex<Option<GzEncoder<W>>>>,
+}
+
+impl<W: Write + Send + 'static> GzipSink<W> {
+    pub fn new(
+        writer: W,
+        level: u32,
+        sink: impl FnOnce(Box<dyn Write + Send>) -> Box<dyn RowSink>,
+    ) -> Self {
+        let encoder = Arc::new(Mutex::new(Some(GzEncoder::new(
+            writer,
+            Compression::new(level),
+        ))));
+        GzipSink {
+            sink: Some(sink(Box::new(SharedGzip(encoder.clone())))),
+            encoder,
+        }
+    }
+}
+
+impl<W: Write> GzipSink<W> {
+    fn sink(&mut self) -> Result<&mut Box<dyn RowSink>, DataAnalysisError> {
+        self.sink
+            .as_mut()
+            .ok_or_else(|| "the gzip stream is already finished".into())
+    }
+}
+
+impl<W: Write> RowSink for GzipSink<W> {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        self.sink()?.write_header(header)
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        self.sink()?.write_row(cells)
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        let Some(mut sink) = self.sink.take() else {
+            return Ok(());
+        };
+        sink.finish()?;
+        drop(sink);
+        let encoder = self
+            .encoder
+            .lock()
+            .map_err(|_| io::Error::other("the gzip stream was poisoned"))?
+            .take();
+        if let Some(encoder) = encoder {
+            encoder.finish()?.flush()?;
+        }
+        Ok(())
+    }
+
+    fn column_kinds(&mut self, kinds: &[CellKind]) {
+        if let Some(sink) = &mut self.sink {
+            sink.column_kinds(kinds);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::{CleanPipeline, Column};
+    use crate::fields::ParseOptions;
+    use crate::output::{DateColumns, OutputFormat, OutputOptions};
+    use crate::parsing::{Money, MoneyPrecision};
+
+    fn temp(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("data_analysis_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn gunzip(path: &Path) -> String {
+        let mut text = String::new();
+        open(path).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    fn pipeline(options: &ParseOptions) -> CleanPipeline<'_> {
+        CleanPipeline::new("sales", options)
+            .column(Column::text("country", "Country"))
+            .column(Column::total("sales", "Sales"))
+    }
+
+    #[test]
+    fn a_kept_partial_file_is_still_a_whole_gzip_stream() {
+        let (input, cleaned) = (temp("partial.csv"), temp("partial_cleaned.csv.gz"));
+        std::fs::write(
+            &input,
+            "Country,Sales,Date\nFrance,£10.00,13/01/2020\nMexico,lots,14/01/2020\n",
+        )
+        .unwrap();
+        let options = ParseOptions::default();
+        let mut output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        output.max_drop_rate = Money::ZERO;
+        output.keep_partial = true;
+        let result = pipeline(&options).run(&output, &input, &cleaned);
+        assert!(
+            matches!(result, Err(DataAnalysisError::DropRate(_))),
+            "{:?}",
+            result
+        );
+        // MultiGzDecoder fails on a missing trailer or a bad CRC
+        assert_eq!(
+            gunzip(&cleaned),
+            "Country,Sales,Date_ISO\nFrance,10.00,2020-01-13\n"
+        );
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&cleaned).unwrap();
+    }
+
+    #[test]
+    fn gzipped_exports_are_read() {
+        let input = temp("export.csv.gz");
+        let mut encoder = GzEncoder::new(File::create(&input).unwrap(), Compression::fast());
+        encoder
+            .write_all(b"Country,Sales,Date\nFrance,\xc2\xa310.00,13/01/2020\n")
+            .unwrap();
+        encoder.finish().unwrap();
+        let options = ParseOptions::default();
+        let rows: Vec<_> = pipeline(&options)
+            .rows_from_path(&input)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        std::fs::remove_file(&input).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].money("sales").unwrap(), Money::new(1000, 2));
+    }
+
+    #[test]
+    fn each_format_keeps_the_gz() {
+        let mut output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        output.formats = vec![OutputFormat::Csv, OutputFormat::Jsonl];
+        let paths = output.output_paths(Path::new("sales.csv.gz")).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                (OutputFormat::Csv, PathBuf::from("sales.csv.gz")),
+                (OutputFormat::Jsonl, PathBuf::from("sales.jsonl.gz")),
+            ]
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("sales.jsonl.gz")),
+            Some(OutputFormat::Jsonl)
+        );
+        assert_eq!(output.gzip_level(Path::new("sales.csv")), None);
+        output.compress = Compress::Gzip;
+        assert_eq!(output.extension(), "csv.gz");
+        assert_eq!(
+            output.gzip_level(Path::new("sales.csv")),
+            Some(DEFAULT_LEVEL)
+        );
+    }
+}
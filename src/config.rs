@@ -0,0 +1,427 @@
+use crate::dates::check_date_format;
+use crate::error::DataAnalysisError;
+use csv::StringRecord;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Settings read from a `--config` TOML file:
+///
+/// ```toml
+/// delimiter = ";"
+/// missing_values = ["", "-", "N/A"]
+///
+/// [forecast]
+/// input = "Data/uk/Part_D_Forcasting.csv"
+/// date_formats = ["%d/%m/%Y"]
+///
+/// [forecast.columns]
+/// country = "Country"   # by header
+/// units_sold = 5        # or by position, counting from 0
+///
+/// [profile.de]
+/// delimiter = ";"
+/// currency_symbols = ["€"]
+/// [profile.de.forecast]
+/// input = "Data/de/Part_D_Forcasting.csv"
+/// ```
+///
+/// A `[profile.<name>]` picked with --profile has the same settings as the
+/// top level and wins over it. Sections and columns that aren't mentioned
+/// keep the built-in layout. Flags on the command line and DATA_ANALYSIS_*
+/// environment variables win over the file.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub settings: Settings,
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, DataAnalysisError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| DataAnalysisError::io("read", path, e))?;
+        let in_file = |e: &dyn fmt::Display| format!("{}: {}", path.display(), e);
+        let mut table: toml::Table = toml::from_str(&text).map_err(|e| in_file(&e))?;
+        // Taken out first so the rest can be checked for unknown keys
+        let profiles = match table.remove("profile") {
+            Some(profiles) => profiles.try_into().map_err(|e| in_file(&e))?,
+            None => BTreeMap::new(),
+        };
+        let config = Config {
+            settings: table.try_into().map_err(|e| in_file(&e))?,
+            profiles,
+        };
+        for settings in std::iter::once(&config.settings).chain(config.profiles.values()) {
+            settings.check().map_err(|e| in_file(&e))?;
+        }
+        Ok(config)
+    }
+
+    /// The top-level settings, with a profile's on top when one is named.
+    pub fn settings(&self, profile: Option<&str>) -> Result<Settings, DataAnalysisError> {
+        let Some(name) = profile else {
+            return Ok(self.settings.clone());
+        };
+        match self.profiles.get(name) {
+            Some(profile) => Ok(Settings::resolve([profile.clone(), self.settings.clone()])),
+            None if self.profiles.is_empty() => {
+                Err(format!("no profile '{}': the config has no profiles", name).into())
+            }
+            None => {
+                let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                Err(format!(
+                    "no profile '{}' (the config has {})",
+                    name,
+                    names.join(", ")
+                )
+                .into())
+            }
+        }
+    }
+}
+
+/// What the config file, or one of its profiles, sets.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub delimiter: Option<Delimiter>,
+    pub date_formats: Option<Vec<String>>,
+    pub missing_values: Option<Vec<String>>,
+    pub currency_symbols: Option<Vec<String>>,
+    #[serde(default)]
+    pub dashboard: DatasetConfig,
+    #[serde(default)]
+    pub timeseries: DatasetConfig,
+    #[serde(default)]
+    pub forecast: DatasetConfig,
+}
+
+impl Settings {
+    /// Puts layers of settings together, each winning over the ones after it.
+    /// Within a layer a dataset's own section wins over the top level.
+    pub fn resolve<const N: usize>(layers: [Settings; N]) -> Settings {
+        layers
+            .into_iter()
+            .map(Settings::flattened)
+            .reduce(|settings, below| settings.or(&below))
+            .unwrap_or_default()
+    }
+
+    /// Copies the top-level settings into each dataset's section, under any
+    /// it sets for itself.
+    fn flattened(self) -> Settings {
+        let top = DatasetConfig {
+            delimiter: self.delimiter,
+            date_formats: self.date_formats.clone(),
+            missing_values: self.missing_values.clone(),
+            currency_symbols: self.currency_symbols.clone(),
+            ..DatasetConfig::default()
+        };
+        Settings {
+            dashboard: self.dashboard.or(&top),
+            timeseries: self.timeseries.or(&top),
+            forecast: self.forecast.or(&top),
+            ..self
+        }
+    }
+
+    fn or(self, base: &Settings) -> Settings {
+        Settings {
+            delimiter: self.delimiter.or(base.delimiter),
+            date_formats: self.date_formats.or_else(|| base.date_formats.clone()),
+            missing_values: self.missing_values.or_else(|| base.missing_values.clone()),
+            currency_symbols: self
+                .currency_symbols
+                .or_else(|| base.currency_symbols.clone()),
+            dashboard: self.dashboard.or(&base.dashboard),
+            timeseries: self.timeseries.or(&base.timeseries),
+            forecast: self.forecast.or(&base.forecast),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        let datasets = [&self.dashboard, &self.timeseries, &self.forecast];
+        let formats = datasets
+            .iter()
+            .flat_map(|dataset| dataset.date_formats.iter().flatten())
+            .chain(self.date_formats.iter().flatten());
+        for format in formats {
+            check_date_format(format)?;
+        }
+        Ok(())
+    }
+}
+
+/// One dataset's section, such as `[forecast]`. Besides its paths and
+/// columns it can set anything the top level does, for itself.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatasetConfig {
+    pub input: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    /// Folder for the cleaned file when no output is given, set by --data-dir
+    #[serde(skip)]
+    pub output_dir: Option<PathBuf>,
+    pub delimiter: Option<Delimiter>,
+    /// Field name, such as `units_sold`, to the column holding it
+    #[serde(default)]
+    pub columns: BTreeMap<String, ColumnRef>,
+    pub date_formats: Option<Vec<String>>,
+    pub missing_values: Option<Vec<String>>,
+    pub currency_symbols: Option<Vec<String>>,
+}
+
+impl DatasetConfig {
+    /// The input set, or `file` in `data_dir`.
+    pub fn input_or(&self, data_dir: &Path, file: &str) -> PathBuf {
+        self.input.clone().unwrap_or_else(|| data_dir.join(file))
+    }
+
+    /// The output set, or `file` in the --data-dir folder or `data_dir`.
+    pub fn output_or(&self, data_dir: &Path, file: &str) -> PathBuf {
+        self.output
+            .clone()
+            .unwrap_or_else(|| self.output_dir.as_deref().unwrap_or(data_dir).join(file))
+    }
+
+    fn or(self, base: &DatasetConfig) -> DatasetConfig {
+        let mut columns = base.columns.clone();
+        columns.extend(self.columns);
+        DatasetConfig {
+            input: self.input.or_else(|| base.input.clone()),
+            output: self.output.or_else(|| base.output.clone()),
+            output_dir: self.output_dir.or_else(|| base.output_dir.clone()),
+            delimiter: self.delimiter.or(base.delimiter),
+            columns,
+            date_formats: self.date_formats.or_else(|| base.date_formats.clone()),
+            missing_values: self.missing_values.or_else(|| base.missing_values.clone()),
+            currency_symbols: self
+                .currency_symbols
+                .or_else(|| base.currency_symbols.clone()),
+        }
+    }
+}
+
+/// A single-byte CSV field separator, given as the character itself or as
+/// \t for tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Delimiter(pub u8);
+
+impl FromStr for Delimiter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "\\t" | "tab" => Ok(Delimiter(b'\t')),
+            _ if s.len() == 1 && s.is_ascii() => Ok(Delimiter(s.as_bytes()[0])),
+            _ => Err(format!(
+                "a delimiter is one ASCII character such as ';' or \\t, not '{}'",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Delimiter {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A column picked out by its header or its position.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Index(usize),
+    Header(String),
+}
+
+impl fmt::Display for ColumnRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnRef::Index(index) => write!(f, "column {}", index),
+            ColumnRef::Header(header) => write!(f, "'{}'", header),
+        }
+    }
+}
+
+/// Where each field a pipeline reads sits in one file, found once from the
+/// headers (trimmed, any case) so added or reordered columns don't shift it.
+/// The config can point a field at another header or a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMap {
+    indices: Vec<(&'static str, usize)>,
+}
+
+impl ColumnMap {
+    /// `defaults` gives the header each field is usually under. Fails on a
+    /// field the dataset doesn't have or a column the file doesn't have,
+    /// naming the dataset and listing the headers that were there.
+    pub fn resolve(
+        dataset: &str,
+        headers: &StringRecord,
+        defaults: &[(&'static str, &str)],
+        mapping: &BTreeMap<String, ColumnRef>,
+    ) -> Result<Self, DataAnalysisError> {
+        if let Some(field) = mapping
+            .keys()
+            .find(|field| defaults.iter().all(|(name, _)| name != field))
+        {
+            let known: Vec<&str> = defaults.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "{}: unknown field '{}' in columns (expected one of {})",
+                dataset,
+                field,
+                known.join(", ")
+            )
+            .into());
+        }
+
+        let mut indices = Vec::with_capacity(defaults.len());
+        for &(field, header) in defaults {
+            let column = mapping
+                .get(field)
+                .cloned()
+                .unwrap_or_else(|| ColumnRef::Header(header.to_string()));
+            let index = match &column {
+                ColumnRef::Index(index) if *index < headers.len() => Some(*index),
+                ColumnRef::Index(_) => None,
+                ColumnRef::Header(header) => find_header(headers, header).map_err(|e| {
+                    format!(
+                        "{}: {}, so {} could be either; point it at one by position in [{}.columns]",
+                        dataset, e, field, dataset
+                    )
+                })?,
+            };
+            let index = index.ok_or_else(|| {
+                let found: Vec<&str> = headers.iter().map(str::trim).collect();
+                format!(
+                    "{}: {} for {} isn't in the file (its columns are {})",
+                    dataset,
+                    column,
+                    field,
+                    found.join(", ")
+                )
+            })?;
+            indices.push((field, index));
+        }
+        Ok(ColumnMap { indices })
+    }
+
+    /// The position of `field`. One the map doesn't know points past the
+    /// end of the row, so it reads as a blank cell.
+    pub fn at(&self, field: &str) -> usize {
+        self.indices
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map_or(usize::MAX, |(_, index)| *index)
+    }
+}
+
+/// Where `name` is in `headers`, trimmed and in any case. Fails when more
+/// than one header matches, such as "Sales" and "sales ", as rows would be
+/// read from whichever came first.
+pub fn find_header(headers: &StringRecord, name: &str) -> Result<Option<usize>, String> {
+    let found: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| h.trim().eq_ignore_ascii_case(name.trim()))
+        .map(|(position, _)| position)
+        .collect();
+    match found.as_slice() {
+        [] => Ok(None),
+        [position] => Ok(Some(*position)),
+        positions => {
+            let found: Vec<String> = positions
+                .iter()
+                .map(|&position| {
+                    format!("'{}' at {}", headers.get(position).unwrap_or(""), position)
+                })
+                .collect();
+            Err(format!(
+                "the header has '{}' more than once ({})",
+                name.trim(),
+                found.join(", ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(input: &str, output: &str) -> DatasetConfig {
+        DatasetConfig {
+            input: (!input.is_empty()).then(|| PathBuf::from(input)),
+            output: (!output.is_empty()).then(|| PathBuf::from(output)),
+            ..DatasetConfig::default()
+        }
+    }
+
+    #[test]
+    fn flags_win_over_the_environment_over_the_config_over_defaults() {
+        let flags = Settings {
+            forecast: paths("flag.csv", ""),
+            ..Settings::default()
+        };
+        let env = Settings {
+            delimiter: Some(Delimiter(b';')),
+            forecast: paths("env.csv", "env_cleaned.csv"),
+            ..Settings::default()
+        };
+        let config = Settings {
+            delimiter: Some(Delimiter(b',')),
+            date_formats: Some(vec!["%Y-%m-%d".into()]),
+            forecast: paths("config.csv", "config_cleaned.csv"),
+            dashboard: paths("dashboard.csv", ""),
+            ..Settings::default()
+        };
+        let settings = Settings::resolve([flags, env, config]);
+        let forecast = &settings.forecast;
+        assert_eq!(forecast.input, Some(PathBuf::from("flag.csv")));
+        assert_eq!(forecast.output, Some(PathBuf::from("env_cleaned.csv")));
+        assert_eq!(forecast.delimiter, Some(Delimiter(b';')));
+        assert_eq!(forecast.date_formats, Some(vec!["%Y-%m-%d".into()]));
+        assert_eq!(
+            settings.dashboard.input,
+            Some(PathBuf::from("dashboard.csv"))
+        );
+        assert_eq!(settings.timeseries.delimiter, Some(Delimiter(b';')));
+        // Nothing sets the timeseries files, so the built-in names are used
+        assert_eq!(
+            settings
+                .timeseries
+                .input_or(Path::new("Data"), "Part_C_Timeseries.csv"),
+            Path::new("Data/Part_C_Timeseries.csv")
+        );
+    }
+
+    #[test]
+    fn a_dataset_section_only_wins_within_its_layer() {
+        let env = Settings {
+            delimiter: Some(Delimiter(b';')),
+            ..Settings::default()
+        };
+        let config = Settings {
+            delimiter: Some(Delimiter(b',')),
+            forecast: DatasetConfig {
+                delimiter: Some(Delimiter(b'\t')),
+                missing_values: Some(vec!["n/a".into()]),
+                ..DatasetConfig::default()
+            },
+            ..Settings::default()
+        };
+        let settings = Settings::resolve([env, config.clone()]);
+        assert_eq!(settings.forecast.delimiter, Some(Delimiter(b';')));
+        assert_eq!(settings.forecast.missing_values, Some(vec!["n/a".into()]));
+        let settings = Settings::resolve([config]);
+        assert_eq!(settings.forecast.delimiter, Some(Delimiter(b'\t')));
+        assert_eq!(settings.dashboard.delimiter, Some(Delimiter(b',')));
+    }
+}
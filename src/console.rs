@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much the pipelines say on stderr. Errors are always shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors only
+    Quiet,
+    /// Progress, rejected values and the run summary
+    Normal,
+    /// Also rows dropped for routine reasons and a running row count
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Data rows between progress lines at --verbose
+pub const PROGRESS_ROWS: usize = 1000;
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn shows(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+/// Like `eprintln!`, unless --quiet.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::console::shows($crate::console::Verbosity::Normal) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Like `eprintln!`, only with --verbose.
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        if $crate::console::shows($crate::console::Verbosity::Verbose) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {detail, info};
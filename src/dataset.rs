@@ -0,0 +1,203 @@
+use crate::clean::reader_builder;
+use crate::dates::parse_date;
+use crate::error::DataAnalysisError;
+use crate::parsing::Money;
+use crate::sink::{FileSink, RowSink};
+use chrono::NaiveDate;
+use csv::WriterBuilder;
+use rust_decimal::prelude::ToPrimitive;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// What a [`Dataset`] column holds, worked out from its cells when loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    Text,
+    /// Every cell that isn't blank is a plain number
+    Number,
+    /// Every cell that isn't blank is a date
+    Date,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub kind: ColumnKind,
+}
+
+/// One row of a [`Dataset`], its cells as they were written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub cells: Vec<String>,
+}
+
+impl Row {
+    /// The cell at `index`, blank past the end of the row.
+    pub fn get(&self, index: usize) -> &str {
+        self.cells.get(index).map_or("", String::as_str)
+    }
+}
+
+/// A cleaned file held in memory, to filter, sort and pull columns out of:
+///
+/// ```no_run
+/// # use data_analysis::dataset::Dataset;
+/// # fn run() -> Result<(), data_analysis::error::DataAnalysisError> {
+/// let mut dataset = Dataset::load("Data/Part_C_Timeseries_Cleaned.csv", b',')?;
+/// let country = dataset.index("Country")?;
+/// dataset = dataset.filter(|row| row.get(country) == "France");
+/// dataset.sort_by_date()?;
+/// let sales = dataset.column_f64("Sales Parsed")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Cells are kept as text, so writing it back out changes nothing else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dataset {
+    pub schema: Vec<ColumnDef>,
+    pub rows: Vec<Row>,
+}
+
+impl Dataset {
+    pub fn load(path: impl AsRef<Path>, delimiter: u8) -> Result<Self, DataAnalysisError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| DataAnalysisError::io("read", path, e))?;
+        Dataset::from_reader(file, delimiter)
+    }
+
+    pub fn from_reader(reader: impl Read, delimiter: u8) -> Result<Self, DataAnalysisError> {
+        let mut rdr = reader_builder(delimiter).from_reader(reader);
+        let headers: Vec<String> = rdr
+            .headers()
+            .map_err(DataAnalysisError::from)?
+            .iter()
+            .map(str::to_string)
+            .collect();
+        let rows = rdr
+            .records()
+            .map(|record| {
+                let record = record.map_err(DataAnalysisError::from)?;
+                if record.len() != headers.len() {
+                    let line = record.position().map_or(0, |p| p.line());
+                    return Err(format!(
+                        "row {}: column count mismatch: expected {}, got {}",
+                        line,
+                        headers.len(),
+                        record.len()
+                    )
+                    .into());
+                }
+                Ok(Row {
+                    cells: record.iter().map(str::to_string).collect(),
+                })
+            })
+            .collect::<Result<Vec<_>, DataAnalysisError>>()?;
+        let schema = headers
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| ColumnDef {
+                name,
+                kind: kind_of(rows.iter().map(|row| row.get(index))),
+            })
+            .collect();
+        Ok(Dataset { schema, rows })
+    }
+
+    /// The position of the column headed `name`, in any case.
+    pub fn index(&self, name: &str) -> Result<usize, String> {
+        self.schema
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.schema.iter().map(|c| c.name.as_str()).collect();
+                format!("no '{}' column (the file has {})", name, names.join(", "))
+            })
+    }
+
+    /// Just the rows `keep` says yes to.
+    pub fn filter(mut self, keep: impl FnMut(&Row) -> bool) -> Self {
+        self.rows.retain(keep);
+        self
+    }
+
+    /// Sorts the rows by the first date column, rows without a date last.
+    pub fn sort_by_date(&mut self) -> Result<(), String> {
+        let index = self
+            .schema
+            .iter()
+            .position(|column| column.kind == ColumnKind::Date)
+            .ok_or("no date column to sort by")?;
+        self.rows
+            .sort_by_cached_key(|row| date(row.get(index)).map_or((1, NaiveDate::MIN), |d| (0, d)));
+        Ok(())
+    }
+
+    /// A number column's values as `f64`, for charting and statistics, `None`
+    /// for a blank cell.
+    pub fn column_f64(&self, name: &str) -> Result<Vec<Option<f64>>, String> {
+        Ok(self
+            .numbers(name)?
+            .into_iter()
+            .map(|value| value.and_then(|value| value.to_f64()))
+            .collect())
+    }
+
+    /// A number column's values as exact [`Money`], `None` for a blank cell,
+    /// for sums that mustn't pick up `f64` rounding.
+    pub fn numbers(&self, name: &str) -> Result<Vec<Option<Money>>, String> {
+        let index = self.index(name)?;
+        if self.schema[index].kind != ColumnKind::Number {
+            return Err(format!(
+                "'{}' isn't a number column",
+                self.schema[index].name
+            ));
+        }
+        Ok(self
+            .rows
+            .iter()
+            .map(|row| Money::from_str(row.get(index)).ok())
+            .collect())
+    }
+
+    pub fn write_csv(
+        &self,
+        path: impl AsRef<Path>,
+        delimiter: u8,
+    ) -> Result<(), DataAnalysisError> {
+        let mut sink = FileSink::create(path.as_ref(), WriterBuilder::new().delimiter(delimiter))?;
+        let header: Vec<&str> = self
+            .schema
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect();
+        sink.write_header(&header)?;
+        for row in &self.rows {
+            let cells: Vec<&str> = row.cells.iter().map(String::as_str).collect();
+            sink.write_row(&cells)?;
+        }
+        sink.finish()
+    }
+}
+
+fn date(cell: &str) -> Option<NaiveDate> {
+    parse_date(cell).ok()
+}
+
+// Number or Date when every cell that isn't blank reads as one, and there's
+// at least one
+fn kind_of<'a>(cells: impl Iterator<Item = &'a str> + Clone) -> ColumnKind {
+    let mut filled = cells.filter(|cell| !cell.is_empty()).peekable();
+    if filled.peek().is_none() {
+        return ColumnKind::Text;
+    }
+    if filled.clone().all(|cell| Money::from_str(cell).is_ok()) {
+        ColumnKind::Number
+    } else if filled.all(|cell| date(cell).is_some()) {
+        ColumnKind::Date
+    } else {
+        ColumnKind::Text
+    }
+}
@@ -0,0 +1,656 @@
+use crate::error::DataAnalysisError;
+use chrono::{Datelike, Month, Months, NaiveDate, NaiveTime};
+use csv::StringRecord;
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// ISO 8601, which is also what the cleaned files are written in. It's always
+/// tried before any other format, so "2014-01-02" is never a 20th month.
+pub const ISO_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Date formats tried in order (after ISO) unless configured otherwise.
+pub const DEFAULT_DATE_FORMATS: &[&str] =
+    &["%d/%m/%Y", "%m/%d/%Y", "%d-%b-%Y", "%d/%m/%y", "%m/%d/%y"];
+
+/// Fails unless `format` can write a date and read the same date back, which
+/// catches typos like "%d/%m/%Q" and formats missing the day, month or year.
+pub fn check_date_format(format: &str) -> Result<(), String> {
+    let probe = NaiveDate::from_ymd_opt(2014, 1, 31).unwrap_or_default();
+    let mut written = String::new();
+    write!(written, "{}", probe.format(format))
+        .map_err(|_| format!("bad date format '{}'", format))?;
+    match NaiveDate::parse_from_str(&written, format) {
+        Ok(date) if date == probe => Ok(()),
+        _ => Err(format!(
+            "date format '{}' can't read back its own dates (2014-01-31 came out as '{}')",
+            format, written
+        )),
+    }
+}
+
+/// Two-digit years below this are 2000s, the rest 1900s: with the default,
+/// "14" is 2014 and "85" is 1985.
+pub const DEFAULT_YEAR_PIVOT: i32 = 70;
+
+/// How many date cells [`DateParser::detect`] looks at by default.
+pub const DEFAULT_DATE_SAMPLE: usize = 100;
+
+/// Whether "03/04/2014" is the 3rd of April or the 4th of March.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    DayFirst,
+    MonthFirst,
+}
+
+impl FromStr for DateOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "day-first" => Ok(DateOrder::DayFirst),
+            "month-first" => Ok(DateOrder::MonthFirst),
+            other => Err(format!(
+                "unknown date order '{}' (expected day-first or month-first)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DateOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DateOrder::DayFirst => write!(f, "day-first"),
+            DateOrder::MonthFirst => write!(f, "month-first"),
+        }
+    }
+}
+
+// Which order a format puts the day and month in, if it leads with either;
+// "%Y-%m-%d" leads with the year so it's never ambiguous
+fn format_order(format: &str) -> Option<DateOrder> {
+    let position = |field| format.find(field).unwrap_or(usize::MAX);
+    let (day, month, year) = (
+        position("%d"),
+        position("%m"),
+        position("%Y").min(position("%y")),
+    );
+    if day == usize::MAX || month == usize::MAX || year < day.min(month) {
+        return None;
+    }
+    Some(if day < month {
+        DateOrder::DayFirst
+    } else {
+        DateOrder::MonthFirst
+    })
+}
+
+/// Numbers in a date cell read as Excel serials when they fall in this range
+/// (roughly 1982 to 2064), so a stray quantity isn't taken for a date.
+pub const EXCEL_SERIAL_RANGE: std::ops::RangeInclusive<u32> = 30_000..=60_000;
+
+// Excel's day 1 is 1900-01-01, but it also counts a 29 February 1900 that
+// never happened, so from day 61 on the real epoch is a day earlier
+fn from_excel_serial(serial: u32) -> Option<NaiveDate> {
+    let epoch = if serial >= 61 {
+        NaiveDate::from_ymd_opt(1899, 12, 30)?
+    } else {
+        NaiveDate::from_ymd_opt(1899, 12, 31)?
+    };
+    epoch.checked_add_days(chrono::Days::new(u64::from(serial)))
+}
+
+// Time-of-day formats accepted after a date, once any timezone is removed
+const TIME_FORMATS: &[&str] = &["%H:%M:%S%.f", "%H:%M"];
+
+// A time of day, ignoring a trailing timezone: "13:45:00Z", "13:45+01:00",
+// "00:00:00 +0000"
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    let s = s.trim();
+    let s = s.strip_suffix(['Z', 'z']).unwrap_or_else(|| {
+        let offset = s.rfind(['+', '-']).filter(|&i| {
+            let tz = &s[i + 1..];
+            i > 0
+                && matches!(tz.len(), 2 | 4 | 5)
+                && tz.chars().all(|c| c.is_ascii_digit() || c == ':')
+        });
+        offset.map_or(s, |i| &s[..i])
+    });
+    let s = s.trim_end();
+    TIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveTime::parse_from_str(s, f).ok())
+}
+
+// Splits "01/09/2014 00:00:00" or "2014-09-01T13:45:00Z" into the date part
+// and its time of day
+fn split_time(s: &str) -> Option<(&str, NaiveTime)> {
+    s.match_indices([' ', 'T'])
+        .find_map(|(i, _)| Some((s[..i].trim_end(), parse_time(&s[i + 1..])?)))
+}
+
+/// Why a date cell couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateErrorReason {
+    NoMatchingFormat(Vec<String>),
+    /// A number too small or large to be an Excel date
+    SerialOutOfRange,
+}
+
+impl fmt::Display for DateErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DateErrorReason::NoMatchingFormat(formats) => {
+                write!(f, "matches none of {}", formats.join(", "))
+            }
+            DateErrorReason::SerialOutOfRange => write!(
+                f,
+                "a number outside the Excel date range {}-{}",
+                EXCEL_SERIAL_RANGE.start(),
+                EXCEL_SERIAL_RANGE.end()
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateParseError {
+    pub input: String,
+    pub reason: DateErrorReason,
+}
+
+impl fmt::Display for DateParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' - {}", self.input, self.reason)
+    }
+}
+
+impl Error for DateParseError {}
+
+/// A date cell read by [`DateParser::parse`], and how it was read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParsedDate {
+    pub date: NaiveDate,
+    /// The year was two digits and placed in a century by the pivot
+    pub two_digit_year: bool,
+    /// The cell had a time of day other than midnight, which was dropped
+    pub has_time: bool,
+}
+
+/// Reads date cells by trying a list of formats in order. With detection on,
+/// [`DateParser::detect`] moves the format most of a file uses to the front,
+/// so an ambiguous "03/04/2014" is read the way the rest of the file is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateParser {
+    formats: Vec<String>,
+    /// Date cells sampled to find the dominant format; zero turns detection off
+    sample: usize,
+    order: Option<DateOrder>,
+    /// The row and cell the order was inferred from, if it wasn't configured
+    order_evidence: Option<(u64, String)>,
+    /// Read numbers such as "41640" as Excel serial dates
+    excel_serials: bool,
+    year_pivot: i32,
+}
+
+impl Default for DateParser {
+    fn default() -> Self {
+        DateParser {
+            formats: DEFAULT_DATE_FORMATS.iter().map(|f| f.to_string()).collect(),
+            sample: DEFAULT_DATE_SAMPLE,
+            order: None,
+            order_evidence: None,
+            excel_serials: true,
+            year_pivot: DEFAULT_YEAR_PIVOT,
+        }
+    }
+}
+
+impl DateParser {
+    pub fn new() -> Self {
+        DateParser::default()
+    }
+
+    /// chrono `strftime` formats, tried in this order after ISO. Check
+    /// formats from users with [`check_date_format`] first.
+    pub fn with_formats<S: AsRef<str>>(mut self, formats: &[S]) -> Self {
+        self.formats = formats
+            .iter()
+            .map(|f| f.as_ref().trim().to_string())
+            .filter(|f| !f.is_empty())
+            .collect();
+        self
+    }
+
+    pub fn with_detection(mut self, sample: usize) -> Self {
+        self.sample = sample;
+        self
+    }
+
+    pub fn with_excel_serials(mut self, excel_serials: bool) -> Self {
+        self.excel_serials = excel_serials;
+        self
+    }
+
+    /// Two-digit years below `pivot` are read as 20xx, the rest as 19xx.
+    pub fn with_year_pivot(mut self, pivot: i32) -> Self {
+        self.year_pivot = pivot;
+        self
+    }
+
+    /// Reads day/month dates this way round instead of inferring it.
+    pub fn with_order(mut self, order: DateOrder) -> Self {
+        self.order = Some(order);
+        self.order_evidence = None;
+        self
+    }
+
+    /// How day/month dates are being read and why, for the run summary.
+    pub fn order_note(&self) -> Option<String> {
+        let order = self.order?;
+        Some(match &self.order_evidence {
+            Some((line, value)) => format!("{}, inferred from '{}' on row {}", order, value, line),
+            None => format!("{}, as configured", order),
+        })
+    }
+
+    // The formats in use once the day/month order is known
+    fn active_formats(&self) -> impl Iterator<Item = &String> {
+        self.formats
+            .iter()
+            .filter(|f| format_order(f).is_none_or(|o| self.order.is_none_or(|order| o == order)))
+    }
+
+    // Every format `parse` tries, ISO first
+    fn tried_formats(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(ISO_DATE_FORMAT).chain(
+            self.active_formats()
+                .map(String::as_str)
+                .filter(|f| *f != ISO_DATE_FORMAT),
+        )
+    }
+
+    // `s` read with one format. "%Y" wants a real four-digit year, since
+    // chrono would happily read "05/06/14" as the year 14, and "%y" years
+    // go through our pivot rather than chrono's
+    fn read_with(&self, s: &str, format: &str) -> Option<ParsedDate> {
+        let date = NaiveDate::parse_from_str(s, format).ok()?;
+        if !format.contains("%y") {
+            return (date.year() >= 100).then_some(ParsedDate {
+                date,
+                two_digit_year: false,
+                has_time: false,
+            });
+        }
+        let short = date.year().rem_euclid(100);
+        let century = if short < self.year_pivot { 2000 } else { 1900 };
+        Some(ParsedDate {
+            date: date.with_year(century + short)?,
+            two_digit_year: true,
+            has_time: false,
+        })
+    }
+
+    // `s` read by the formats with this day/month order
+    fn read_as(&self, s: &str, order: DateOrder) -> Option<NaiveDate> {
+        self.formats
+            .iter()
+            .filter(|f| format_order(f) == Some(order))
+            .find_map(|f| self.read_with(s, f))
+            .map(|parsed| parsed.date)
+    }
+
+    pub fn parse(&self, s: &str) -> Result<ParsedDate, DateParseError> {
+        let s = s.trim();
+        let fail = |reason| DateParseError {
+            input: s.to_string(),
+            reason,
+        };
+        if let Some(parsed) = self
+            .tried_formats()
+            .find_map(|format| self.read_with(s, format))
+        {
+            return Ok(parsed);
+        }
+        // Datetimes keep just the date
+        if let Some((date, time)) = split_time(s)
+            && let Some(parsed) = self
+                .tried_formats()
+                .find_map(|format| self.read_with(date, format))
+        {
+            return Ok(ParsedDate {
+                has_time: time != NaiveTime::MIN,
+                ..parsed
+            });
+        }
+
+        // "41640", or "41640.5" with a time of day that's dropped
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let is_serial = !whole.is_empty()
+            && s.chars().filter(|c| *c == '.').count() <= 1
+            && s.chars().all(|c| c.is_ascii_digit() || c == '.');
+        if self.excel_serials && is_serial {
+            return whole
+                .parse()
+                .ok()
+                .filter(|serial| EXCEL_SERIAL_RANGE.contains(serial))
+                .and_then(from_excel_serial)
+                .map(|date| ParsedDate {
+                    date,
+                    two_digit_year: false,
+                    has_time: fraction.chars().any(|c| c != '0'),
+                })
+                .ok_or_else(|| fail(DateErrorReason::SerialOutOfRange));
+        }
+        Err(fail(DateErrorReason::NoMatchingFormat(
+            self.tried_formats().map(str::to_string).collect(),
+        )))
+    }
+
+    /// Whether `value` is enough for [`DateParser::detect`] to settle the
+    /// day/month order, or the order needs no settling as it was configured.
+    pub fn settles_order(&self, value: &str) -> bool {
+        let value = value.trim();
+        let value = split_time(value).map_or(value, |(date, _)| date);
+        self.order.is_some()
+            || self.read_as(value, DateOrder::DayFirst).is_some()
+                != self.read_as(value, DateOrder::MonthFirst).is_some()
+    }
+
+    /// Date cells [`DateParser::detect`] counts formats over.
+    pub fn sample(&self) -> usize {
+        self.sample
+    }
+
+    /// A parser for one file's date column.
+    ///
+    /// Unless configured, the day/month order is locked from the first value
+    /// that only reads one way ("25/02/2019"), and formats the other way round
+    /// are dropped. A column where every value reads both ways is an error
+    /// asking for the order to be given. Then the format that reads the most
+    /// of the first few `values` goes first; the rest keep their order. Each
+    /// value comes with its line, for saying where the order came from.
+    pub fn detect<'a>(
+        &self,
+        values: impl IntoIterator<Item = (u64, &'a str)>,
+    ) -> Result<DateParser, DataAnalysisError> {
+        // Only the date part of a datetime says anything about the format
+        let lines: Vec<(u64, &str)> = values
+            .into_iter()
+            .map(|(line, v)| {
+                (
+                    line,
+                    split_time(v.trim()).map_or(v.trim(), |(date, _)| date),
+                )
+            })
+            .filter(|(_, v)| !v.is_empty())
+            .collect();
+        let values: Vec<&str> = lines.iter().map(|(_, v)| *v).collect();
+        let mut detected = self.clone();
+
+        if detected.order.is_none() {
+            let mut ambiguous = None;
+            for (line, value) in &lines {
+                let day_first = self.read_as(value, DateOrder::DayFirst);
+                let month_first = self.read_as(value, DateOrder::MonthFirst);
+                match (day_first, month_first) {
+                    (Some(a), Some(b)) => {
+                        // "11/11/2018" is the same date either way
+                        if a != b {
+                            ambiguous.get_or_insert((*line, *value, a, b));
+                        }
+                        continue;
+                    }
+                    (Some(_), None) => detected.order = Some(DateOrder::DayFirst),
+                    (None, Some(_)) => detected.order = Some(DateOrder::MonthFirst),
+                    (None, None) => continue,
+                }
+                detected.order_evidence = Some((*line, value.to_string()));
+                break;
+            }
+            if detected.order.is_none()
+                && let Some((line, value, day_first, month_first)) = ambiguous
+            {
+                return Err(format!(
+                    "ambiguous dates: every day/month date reads both ways, e.g. '{}' on \
+                     row {} is {} day-first or {} month-first; pass --date-order \
+                     day-first or month-first",
+                    value,
+                    line,
+                    day_first.format("%-d %B %Y"),
+                    month_first.format("%-d %B %Y")
+                )
+                .into());
+            }
+        }
+
+        let active: Vec<&String> = detected.active_formats().collect();
+        let mut counts = vec![0; detected.formats.len()];
+        for value in values.iter().take(self.sample) {
+            for (count, format) in counts.iter_mut().zip(&detected.formats) {
+                if active.contains(&format) && self.read_with(value, format).is_some() {
+                    *count += 1;
+                }
+            }
+        }
+
+        // Earliest format wins a tie, so an all-ambiguous file keeps the configured order
+        if let Some((best, _)) = counts
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, count)| **count)
+            .filter(|(_, count)| **count > 0)
+        {
+            let format = detected.formats.remove(best);
+            detected.formats.insert(0, format);
+        }
+        Ok(detected)
+    }
+}
+
+/// Where a file repeats its dates as Month Number, Month Name and Year
+/// columns, if it does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CalendarColumns {
+    pub month_number: Option<usize>,
+    pub month_name: Option<usize>,
+    pub year: Option<usize>,
+}
+
+impl CalendarColumns {
+    pub fn from_headers(headers: &StringRecord) -> Self {
+        let find = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+        };
+        CalendarColumns {
+            month_number: find("Month Number"),
+            month_name: find("Month Name"),
+            year: find("Year"),
+        }
+    }
+
+    /// What one row's calendar columns say. Cells that don't hold a month or
+    /// year are ignored.
+    pub fn read(&self, record: &StringRecord) -> CalendarMonth {
+        let cell = |index: Option<usize>| {
+            let value = record.get(index?)?.trim();
+            (!value.is_empty()).then_some(value)
+        };
+        let number = cell(self.month_number);
+        let name = cell(self.month_name);
+        let year = cell(self.year);
+
+        let described: Vec<String> = [
+            ("Month Number", number),
+            ("Month Name", name),
+            ("Year", year),
+        ]
+        .iter()
+        .filter_map(|(column, value)| Some(format!("{} is '{}'", column, (*value)?)))
+        .collect();
+        CalendarMonth {
+            year: year.and_then(|y| y.parse().ok()),
+            month: number
+                .and_then(|n| n.parse().ok())
+                .filter(|n| (1..=12).contains(n))
+                .or_else(|| Some(name?.parse::<Month>().ok()?.number_from_month())),
+            described: described.join(", "),
+        }
+    }
+}
+
+/// Which side wins when a row's Date disagrees with its Month/Year columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reconcile {
+    PreferDate,
+    /// Use the first of the Month/Year columns' month
+    PreferMonthYear,
+    /// Leave the row out
+    #[default]
+    Reject,
+}
+
+impl FromStr for Reconcile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "prefer-date" => Ok(Reconcile::PreferDate),
+            "prefer-month-year" => Ok(Reconcile::PreferMonthYear),
+            "reject" => Ok(Reconcile::Reject),
+            other => Err(format!(
+                "unknown date reconcile policy '{}' (expected prefer-date, prefer-month-year or reject)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Reconcile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reconcile::PreferDate => write!(f, "kept Date"),
+            Reconcile::PreferMonthYear => write!(f, "used Month/Year"),
+            Reconcile::Reject => write!(f, "rejected"),
+        }
+    }
+}
+
+/// The month and year a row's calendar columns give.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarMonth {
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    /// The cells as written, e.g. "Month Name is 'April', Year is '2014'"
+    pub described: String,
+}
+
+impl CalendarMonth {
+    /// The first of the month, standing in for a missing or unreadable date.
+    pub fn first_day(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year?, self.month?, 1)
+    }
+
+    /// The first of the calendar columns' month, taking whatever they leave
+    /// out from `date`.
+    pub fn first_day_near(&self, date: NaiveDate) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(
+            self.year.unwrap_or(date.year()),
+            self.month.unwrap_or(date.month()),
+            1,
+        )
+    }
+
+    pub fn contradicts(&self, date: NaiveDate) -> bool {
+        self.year.is_some_and(|y| y != date.year()) || self.month.is_some_and(|m| m != date.month())
+    }
+}
+
+/// An inclusive window of dates; an open end lets everything through on
+/// that side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+/// Moves every date to the same day of its month, for monthly data stamped
+/// on whatever day each source system likes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalizeDates {
+    #[default]
+    None,
+    FirstOfMonth,
+    EndOfMonth,
+}
+
+impl NormalizeDates {
+    pub fn apply(&self, date: NaiveDate) -> NaiveDate {
+        let first = date.with_day(1).unwrap_or(date);
+        match self {
+            NormalizeDates::None => date,
+            NormalizeDates::FirstOfMonth => first,
+            // The day before the 1st of next month, so 29 February in a leap year
+            NormalizeDates::EndOfMonth => first
+                .checked_add_months(Months::new(1))
+                .and_then(|next| next.pred_opt())
+                .unwrap_or(date),
+        }
+    }
+}
+
+impl FromStr for NormalizeDates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(NormalizeDates::None),
+            "first-of-month" => Ok(NormalizeDates::FirstOfMonth),
+            "end-of-month" => Ok(NormalizeDates::EndOfMonth),
+            other => Err(format!(
+                "unknown date normalization '{}' (expected first-of-month, end-of-month or none)",
+                other
+            )),
+        }
+    }
+}
+
+/// Parses a date cell with the default [`DateParser`].
+pub fn parse_date(s: &str) -> Result<NaiveDate, DateParseError> {
+    DateParser::default().parse(s).map(|parsed| parsed.date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ambiguous_dates_name_an_example() {
+        let error = DateParser::new()
+            .detect([(2, "01/02/2020"), (3, "03/04/2020")])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "ambiguous dates: every day/month date reads both ways, e.g. '01/02/2020' on row 2 \
+             is 1 February 2020 day-first or 2 January 2020 month-first; pass --date-order \
+             day-first or month-first"
+        );
+        let parser = DateParser::new()
+            .detect([(2, "01/02/2020"), (3, "25/04/2020")])
+            .unwrap();
+        assert_eq!(
+            parser.parse("01/02/2020").unwrap().date,
+            NaiveDate::from_ymd_opt(2020, 2, 1).unwrap()
+        );
+    }
+}
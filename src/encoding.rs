@@ -0,0 +1,168 @@
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// The character set an export is written in. Everything is read as UTF-8
+/// after [`Decoder`] has been through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// What Excel on Windows saves "CSV" as, Latin-1 plus € and curly quotes
+    Windows1252,
+    Latin1,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "windows-1252" | "cp1252" => Ok(Encoding::Windows1252),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            other => Err(format!(
+                "unknown encoding '{}' (expected utf-8, windows-1252 or latin-1)",
+                other
+            )),
+        }
+    }
+}
+
+// Windows-1252's 0x80 to 0x9F. The five it leaves out stay Latin-1's
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    fn char(self, byte: u8) -> char {
+        match (self, byte) {
+            (Encoding::Windows1252, 0x80..=0x9F) => WINDOWS_1252_HIGH[usize::from(byte - 0x80)],
+            _ => char::from(byte),
+        }
+    }
+}
+
+// What Excel puts at the start of a UTF-8 CSV
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Reads `inner` as UTF-8, converting it from its encoding on the way and
+/// leaving out a BOM at the start. A file with one is read as UTF-8 whatever
+/// its encoding was said to be. UTF-8 passes straight through, so it's still
+/// checked by whoever reads it.
+pub struct Decoder<R> {
+    inner: R,
+    encoding: Encoding,
+    started: bool,
+    decoded: Vec<u8>,
+    start: usize,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R, encoding: Encoding) -> Self {
+        Decoder {
+            inner,
+            encoding,
+            started: false,
+            decoded: Vec::new(),
+            start: 0,
+        }
+    }
+
+    fn decode(&mut self, raw: &[u8]) {
+        self.decoded = match self.encoding {
+            Encoding::Utf8 => raw.to_vec(),
+            encoding => raw
+                .iter()
+                .map(|&b| encoding.char(b))
+                .collect::<String>()
+                .into_bytes(),
+        };
+        self.start = 0;
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if !self.started {
+            self.started = true;
+            let mut first = Vec::with_capacity(BOM.len());
+            self.inner
+                .by_ref()
+                .take(BOM.len() as u64)
+                .read_to_end(&mut first)?;
+            match first == BOM {
+                true => self.encoding = Encoding::Utf8,
+                false => self.decode(&first),
+            }
+        }
+        if self.start == self.decoded.len() {
+            if self.encoding == Encoding::Utf8 {
+                return self.inner.read(out);
+            }
+            let mut raw = [0; 8192];
+            let read = self.inner.read(&mut raw)?;
+            self.decode(&raw[..read]);
+        }
+        let n = out.len().min(self.decoded.len() - self.start);
+        out[..n].copy_from_slice(&self.decoded[self.start..self.start + n]);
+        self.start += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::{CleanPipeline, Column};
+    use crate::fields::ParseOptions;
+
+    // An export as Excel saves it, BOM first
+    const EXPORT: &[u8] = b"\xEF\xBB\xBFSegment,Country,Date\nGovernment,France,13/01/2020\n";
+
+    fn decoded(raw: &[u8], encoding: Encoding) -> String {
+        let mut text = String::new();
+        Decoder::new(raw, encoding)
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn a_bom_is_left_out() {
+        assert_eq!(
+            decoded(EXPORT, Encoding::Utf8),
+            "Segment,Country,Date\nGovernment,France,13/01/2020\n"
+        );
+        // It says the file is UTF-8, so "£" isn't read as Windows-1252
+        assert_eq!(
+            decoded(b"\xEF\xBB\xBF\xC2\xA35", Encoding::Windows1252),
+            "£5"
+        );
+        assert_eq!(decoded(b"\xA35", Encoding::Windows1252), "£5");
+        assert_eq!(decoded(b"ab", Encoding::Utf8), "ab");
+        assert_eq!(decoded(b"", Encoding::Utf8), "");
+    }
+
+    #[test]
+    fn the_first_header_and_row_read_past_a_bom() {
+        // Saved from Excel, so it starts with a BOM
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("Data/Part_B_Dashboard_bom.csv");
+        let raw = std::fs::read(&path).unwrap();
+        assert!(raw.starts_with(b"\xEF\xBB\xBFSegment,"));
+        assert!(decoded(&raw, Encoding::Utf8).starts_with("Segment,Country,"));
+
+        let options = ParseOptions::default();
+        let rows: Vec<_> = CleanPipeline::new("sales", &options)
+            .column(Column::text("segment", "Segment"))
+            .column(Column::text("country", "Country"))
+            .rows_from_path(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].text("segment").unwrap(), "Government");
+        assert_eq!(rows[0].text("country").unwrap(), "Canada");
+    }
+}
@@ -0,0 +1,188 @@
+use crate::summary::{DropRateExceeded, RowError, TooManyErrors};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What the library's functions fail with. The variants are the failures a
+/// caller may want to tell apart, such as a missing input from a malformed
+/// one; anything else is a plain message.
+#[derive(Debug, thiserror::Error)]
+pub enum DataAnalysisError {
+    /// A file or folder that couldn't be read, written, created or removed
+    #[error("can't {action} {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        /// "read", "write", "create" or "remove"
+        action: &'static str,
+        source: io::Error,
+    },
+    /// The export a pipeline reads that isn't there or can't be read
+    #[error(
+        "can't read the {pipeline} export {}: {}",
+        path.display(),
+        unreadable(source, missing_folder.as_deref())
+    )]
+    Input {
+        pipeline: String,
+        /// In full, as running from the wrong folder is the usual cause
+        path: PathBuf,
+        source: io::Error,
+        /// Set when the folder it should be in isn't there either
+        missing_folder: Option<PathBuf>,
+    },
+    /// The CSV itself is broken, such as a bad quote or invalid UTF-8
+    #[error("{}", bad_csv(*line, source))]
+    Csv { line: u64, source: csv::Error },
+    /// A date a strict run can't read. `value` is `None` when it's missing
+    #[error("row {line}: {}", cell(column, value, reason))]
+    DateParse {
+        line: u64,
+        column: String,
+        value: Option<String>,
+        reason: String,
+    },
+    /// A number a strict run can't read. `value` is `None` when it's missing
+    #[error("row {line}: {}", cell(column, value, reason))]
+    Money {
+        line: u64,
+        column: String,
+        value: Option<String>,
+        reason: String,
+    },
+    /// Output that couldn't be written, such as standard output closing early
+    #[error("can't write the output: {0}")]
+    Write(#[from] io::Error),
+    /// JSON that couldn't be read or written
+    #[error("bad JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// Arrow arrays that couldn't be built or written
+    #[cfg(any(feature = "parquet", feature = "arrow-ipc"))]
+    #[error("can't write the Arrow data: {0}")]
+    Arrow(#[from] arrow::error::ArrowError),
+    /// A Parquet file that couldn't be written
+    #[cfg(feature = "parquet")]
+    #[error("can't write the Parquet file: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    /// A SQLite database that couldn't be opened or written
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    /// A workbook that couldn't be written
+    #[cfg(feature = "xlsx")]
+    #[error("can't write the workbook: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+    /// No rows to work on
+    #[error("no rows to work on")]
+    EmptyDataset,
+    /// More rows dropped than --max-drop-rate allows
+    #[error(transparent)]
+    DropRate(#[from] DropRateExceeded),
+    /// A run stopped by --max-errors
+    #[error(transparent)]
+    TooManyErrors(#[from] TooManyErrors),
+    /// Bad options, config or layout, such as a missing column, told as is
+    #[error("{0}")]
+    Message(String),
+}
+
+impl DataAnalysisError {
+    pub fn io(action: &'static str, path: &Path, source: io::Error) -> Self {
+        DataAnalysisError::Io {
+            path: path.to_path_buf(),
+            action,
+            source,
+        }
+    }
+
+    /// The bad value a date or money error is about, for a run that goes on.
+    pub fn row_error(&self) -> Option<RowError> {
+        match self {
+            DataAnalysisError::DateParse {
+                line,
+                column,
+                value,
+                reason,
+            }
+            | DataAnalysisError::Money {
+                line,
+                column,
+                value,
+                reason,
+            } => Some(RowError {
+                line: *line,
+                column: column.clone(),
+                value: value.clone(),
+                reason: reason.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// `path`, the export `pipeline` reads, couldn't be opened.
+    pub fn input(pipeline: &str, path: &Path, source: io::Error) -> Self {
+        let missing_folder = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty() && !dir.exists());
+        DataAnalysisError::Input {
+            pipeline: pipeline.to_string(),
+            path: std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+            source,
+            missing_folder: missing_folder.map(Path::to_path_buf),
+        }
+    }
+}
+
+impl From<csv::Error> for DataAnalysisError {
+    fn from(source: csv::Error) -> Self {
+        let line = source.position().map_or(0, |p| p.line());
+        DataAnalysisError::Csv { line, source }
+    }
+}
+
+impl From<String> for DataAnalysisError {
+    fn from(message: String) -> Self {
+        DataAnalysisError::Message(message)
+    }
+}
+
+impl From<&str> for DataAnalysisError {
+    fn from(message: &str) -> Self {
+        DataAnalysisError::Message(message.to_string())
+    }
+}
+
+// A bad cell as RowError shows it
+fn cell(column: &str, value: &Option<String>, reason: &str) -> String {
+    match value {
+        Some(value) => format!("{} '{}' - {}", column, value, reason),
+        None => format!("{} {}", column, reason),
+    }
+}
+
+// Why the export couldn't be read, with what to do about it
+fn unreadable(source: &io::Error, missing_folder: Option<&Path>) -> String {
+    match (source.kind(), missing_folder) {
+        (io::ErrorKind::NotFound, Some(dir)) => format!(
+            "there's no {} folder; run from the folder holding it or give the path with --input or --data-dir",
+            dir.display()
+        ),
+        (io::ErrorKind::NotFound, None) => {
+            "it isn't there; give the path with --input or --data-dir".to_string()
+        }
+        (io::ErrorKind::IsADirectory, _) => "that's a folder, not a file".to_string(),
+        (io::ErrorKind::PermissionDenied, _) => "no permission to read it".to_string(),
+        _ => source.to_string(),
+    }
+}
+
+fn bad_csv(line: u64, source: &csv::Error) -> String {
+    let mut message = match line {
+        0 => format!("bad CSV: {}", source),
+        line => format!("row {}: bad CSV: {}", line, source),
+    };
+    if let csv::ErrorKind::Utf8 { .. } = source.kind() {
+        message.push_str(
+            "; if the export isn't UTF-8, say what it is with --encoding, e.g. windows-1252",
+        );
+    }
+    message
+}
@@ -0,0 +1,754 @@
+use crate::config::ColumnRef;
+use crate::console::{detail, info};
+use crate::dates::{
+    CalendarColumns, CalendarMonth, DateParser, DateRange, NormalizeDates, Reconcile,
+};
+use crate::encoding::Encoding;
+use crate::error::DataAnalysisError;
+use crate::filters::RowFilter;
+use crate::parsing::{CountRules, Currency, Money, MoneyParseError, MoneyParser, ParsedMoney};
+use crate::rates::ExchangeRates;
+use crate::summary::{DropReason, RunSummary};
+use chrono::{Datelike, Days, Local, NaiveDate};
+use csv::{Reader, StringRecord};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// What a pipeline does with a numeric field it can't use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Abort the run, naming the row and column
+    Strict,
+    /// Drop the row, though a missing total such as Discounts is zero
+    #[default]
+    SkipRow,
+    /// Carry on with zero in that column
+    DefaultZero,
+    /// Carry on with the cell blank, a gap rather than a made-up zero
+    KeepBlank,
+}
+
+impl FromStr for ParseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(ParseMode::Strict),
+            "skip-row" => Ok(ParseMode::SkipRow),
+            "default-zero" => Ok(ParseMode::DefaultZero),
+            "keep-blank" => Ok(ParseMode::KeepBlank),
+            other => Err(format!(
+                "unknown parse mode '{}' (expected strict, skip-row, default-zero or keep-blank)",
+                other
+            )),
+        }
+    }
+}
+
+/// A `--column-mode` such as `sales=default-zero`: one field's parse mode,
+/// winning over the run's and the pipeline's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMode {
+    pub field: String,
+    pub mode: ParseMode,
+}
+
+impl FromStr for ColumnMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, mode) = s
+            .split_once('=')
+            .ok_or_else(|| format!("column mode '{}' needs field=mode, e.g. sales=skip-row", s))?;
+        Ok(ColumnMode {
+            field: field.trim().to_ascii_lowercase(),
+            mode: mode.trim().parse()?,
+        })
+    }
+}
+
+/// What a pipeline does with a negative number in a column, which is a
+/// return in Units Sold and a typo in a price but fine in Profit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegativePolicy {
+    Allow,
+    /// Drop the row, or abort the run under strict
+    Reject,
+    ClampToZero,
+    AbsoluteValue,
+}
+
+impl FromStr for NegativePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(NegativePolicy::Allow),
+            "reject" => Ok(NegativePolicy::Reject),
+            "clamp-to-zero" => Ok(NegativePolicy::ClampToZero),
+            "absolute-value" => Ok(NegativePolicy::AbsoluteValue),
+            other => Err(format!(
+                "unknown negative policy '{}' (expected allow, reject, clamp-to-zero or absolute-value)",
+                other
+            )),
+        }
+    }
+}
+
+/// A `--negatives` such as `discounts=reject`: one field's negative policy,
+/// winning over the pipeline's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnNegatives {
+    pub field: String,
+    pub policy: NegativePolicy,
+}
+
+impl FromStr for ColumnNegatives {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, policy) = s.split_once('=').ok_or_else(|| {
+            format!(
+                "negative policy '{}' needs field=policy, e.g. units_sold=allow",
+                s
+            )
+        })?;
+        Ok(ColumnNegatives {
+            field: field.trim().to_ascii_lowercase(),
+            policy: policy.trim().parse()?,
+        })
+    }
+}
+
+/// The stretch of data rows a pipeline reads, from --offset and --limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowSlice {
+    /// Data rows skipped after the header
+    pub offset: usize,
+    /// Data rows read after those, or all the rest
+    pub limit: Option<usize>,
+}
+
+impl RowSlice {
+    pub fn is_whole(&self) -> bool {
+        self.offset == 0 && self.limit.is_none()
+    }
+
+    /// The records in the slice, each read as it's asked for. Rows before it
+    /// are skipped without being parsed and reading stops at its end.
+    pub fn records<R: std::io::Read>(
+        &self,
+        reader: Reader<R>,
+    ) -> impl Iterator<Item = Result<StringRecord, csv::Error>> + use<R> {
+        reader
+            .into_records()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+    }
+}
+
+impl fmt::Display for RowSlice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.limit {
+            Some(0) => write!(f, "no data rows"),
+            Some(limit) => write!(
+                f,
+                "data rows {} to {}",
+                self.offset + 1,
+                self.offset + limit
+            ),
+            None => write!(f, "data rows {} on", self.offset + 1),
+        }
+    }
+}
+
+/// How a pipeline reads its fields.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+    /// Parse modes for single fields, such as `sales`. Fields a pipeline
+    /// doesn't read are ignored, as not every pipeline in a run has them
+    pub column_modes: BTreeMap<String, ParseMode>,
+    pub money: MoneyParser,
+    pub counts: CountRules,
+    pub dates: DateParser,
+    /// Rows dated outside this are left out
+    pub date_range: DateRange,
+    /// What to do when a Date disagrees with the Month/Year columns
+    pub reconcile: Reconcile,
+    /// Applied to every date once it's read
+    pub normalize_dates: NormalizeDates,
+    /// Fields moved away from their usual columns by --config
+    pub columns: BTreeMap<String, ColumnRef>,
+    /// Field separator, b',' unless the export uses something else
+    pub delimiter: u8,
+    /// Only these rows are read, filtered and counted
+    pub rows: RowSlice,
+    /// Rows have to match all of these to be kept
+    pub filters: Vec<RowFilter>,
+    pub case_sensitive_filters: bool,
+    /// Converts money columns into one base currency when set
+    pub rates: Option<ExchangeRates>,
+    /// Refuse an export whose header isn't its pipeline's usual one
+    pub check_headers: bool,
+    /// What the export is written in
+    pub encoding: Encoding,
+    /// Negative policies for single fields, such as `units_sold`
+    pub negatives: BTreeMap<String, NegativePolicy>,
+    /// Stop reading once this many rows have had a bad value or the wrong
+    /// number of fields
+    pub max_errors: Option<usize>,
+}
+
+impl Default for ParseOptions {
+    /// What the pipelines do when given no flags.
+    fn default() -> Self {
+        ParseOptions {
+            mode: ParseMode::default(),
+            column_modes: BTreeMap::new(),
+            money: MoneyParser::default(),
+            counts: CountRules::default(),
+            dates: DateParser::default(),
+            date_range: DateRange::default(),
+            reconcile: Reconcile::default(),
+            normalize_dates: NormalizeDates::default(),
+            columns: BTreeMap::new(),
+            delimiter: b',',
+            rows: RowSlice::default(),
+            filters: Vec::new(),
+            case_sensitive_filters: false,
+            rates: None,
+            check_headers: true,
+            encoding: Encoding::default(),
+            negatives: BTreeMap::new(),
+            max_errors: None,
+        }
+    }
+}
+
+/// Reads the fields of one record, applying the pipeline's [`ParseOptions`]
+/// and tallying rejected values in the run summary.
+///
+/// The numeric getters return `Ok(Some(value))` to carry on, `Ok(None)` when
+/// the row should be skipped and `Err` when the run should stop.
+pub struct RowFields<'a> {
+    record: &'a StringRecord,
+    options: &'a ParseOptions,
+    summary: &'a mut RunSummary,
+    mode: ParseMode,
+    /// What a missing value gets, which can be gentler than a bad one
+    missing_mode: ParseMode,
+    dropped: Option<String>,
+}
+
+impl<'a> RowFields<'a> {
+    pub fn new(
+        record: &'a StringRecord,
+        options: &'a ParseOptions,
+        summary: &'a mut RunSummary,
+    ) -> Self {
+        RowFields {
+            record,
+            options,
+            summary,
+            mode: options.mode,
+            missing_mode: options.mode,
+            dropped: None,
+        }
+    }
+
+    /// Reads the fields after this with `mode` instead of the options' one.
+    pub fn set_mode(&mut self, mode: ParseMode) {
+        self.mode = mode;
+        self.missing_mode = mode;
+    }
+
+    /// Reads missing values in the fields after this with `mode`, until the
+    /// next [`RowFields::set_mode`].
+    pub fn set_missing_mode(&mut self, mode: ParseMode) {
+        self.missing_mode = mode;
+    }
+
+    pub fn summary(&mut self) -> &mut RunSummary {
+        self.summary
+    }
+
+    /// Notes why the row is being left out, for [`RowFields::drop_reason`].
+    pub fn set_drop_reason(&mut self, reason: String) {
+        self.dropped = Some(reason);
+    }
+
+    /// Why the row is being left out: the last problem met on it, such as
+    /// "Sale Price 'S23' - not a number".
+    pub fn drop_reason(&mut self) -> Option<String> {
+        self.dropped.take()
+    }
+
+    pub fn line(&self) -> u64 {
+        self.record.position().map(|p| p.line()).unwrap_or(0)
+    }
+
+    pub fn str(&self, index: usize) -> String {
+        self.record.get(index).unwrap_or("").trim().to_string()
+    }
+
+    /// Whether the record has `expected` fields. One that's short or long is
+    /// skipped with a warning, or stops a strict run, since its cells could
+    /// have shifted into the wrong columns.
+    pub fn check_width(&mut self, expected: usize) -> Result<bool, DataAnalysisError> {
+        let found = self.record.len();
+        if found == expected {
+            return Ok(true);
+        }
+        let reason = format!(
+            "column count mismatch: expected {}, got {}",
+            expected, found
+        );
+        if self.options.mode == ParseMode::Strict {
+            return Err(format!("row {}: {}", self.line(), reason).into());
+        }
+        detail!("row {}: {}", self.line(), reason);
+        self.set_drop_reason(reason);
+        self.summary.count_wrong_width(self.line());
+        Ok(false)
+    }
+
+    /// Whether the cell is one of the missing-value markers, counting it if so.
+    pub fn is_missing(&mut self, index: usize) -> bool {
+        let raw = self.record.get(index).unwrap_or("");
+        match self.options.money.missing().matching(raw) {
+            Some(marker) => {
+                self.summary.count_missing(marker);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Applies the parse mode to a missing or unparseable value
+    fn fallback<T: Default>(
+        &mut self,
+        column: &str,
+        error: Option<MoneyParseError>,
+    ) -> Result<Option<T>, DataAnalysisError> {
+        let bad = error.is_some();
+        let mode = match bad {
+            true => self.mode,
+            false => self.missing_mode,
+        };
+        let problem = DataAnalysisError::Money {
+            line: self.line(),
+            column: column.to_string(),
+            value: error.as_ref().map(|e| e.input.clone()),
+            reason: error.map_or("missing".to_string(), |e| e.reason.to_string()),
+        };
+        match mode {
+            ParseMode::Strict => Err(problem),
+            mode => {
+                // Each is listed under --verbose; the summary counts them, and
+                // blank cells are routine, so only bad ones count as rejected
+                detail!("{}", problem);
+                if bad {
+                    self.summary.reject(column);
+                    if let Some(error) = problem.row_error() {
+                        self.summary.record_error(error);
+                    }
+                }
+                if mode == ParseMode::SkipRow {
+                    if let Some(error) = problem.row_error() {
+                        self.set_drop_reason(error.to_string());
+                    }
+                    self.summary
+                        .count_drop(DropReason::BadNumber, Some(column), 1);
+                }
+                if mode == ParseMode::KeepBlank {
+                    self.summary.blanked += 1;
+                }
+                Ok((mode == ParseMode::DefaultZero).then(T::default))
+            }
+        }
+    }
+
+    pub fn parsed<T: Default>(
+        &mut self,
+        index: usize,
+        column: &str,
+        parse: impl FnOnce(&str) -> Result<Option<T>, MoneyParseError>,
+    ) -> Result<Option<T>, DataAnalysisError> {
+        match parse(self.record.get(index).unwrap_or("")) {
+            Ok(Some(value)) => Ok(Some(value)),
+            Ok(None) => {
+                self.is_missing(index);
+                self.fallback(column, None)
+            }
+            Err(e) => self.fallback(column, Some(e)),
+        }
+    }
+
+    /// For optional columns: a blank cell is just `None`, and a bad value is
+    /// blanked with a warning unless the mode is strict.
+    pub fn optional<T>(
+        &mut self,
+        index: usize,
+        column: &str,
+        parse: impl FnOnce(&str) -> Result<Option<T>, MoneyParseError>,
+    ) -> Result<Option<T>, DataAnalysisError> {
+        match parse(self.record.get(index).unwrap_or("")) {
+            Ok(Some(value)) => Ok(Some(value)),
+            Ok(None) => {
+                self.is_missing(index);
+                Ok(None)
+            }
+            Err(e) => {
+                let problem = DataAnalysisError::Money {
+                    line: self.line(),
+                    column: column.to_string(),
+                    value: Some(e.input),
+                    reason: e.reason.to_string(),
+                };
+                self.reject(column, problem)?;
+                Ok(None)
+            }
+        }
+    }
+
+    // Stops a strict run over a bad value; otherwise warns and tallies it
+    fn reject(
+        &mut self,
+        column: &str,
+        problem: DataAnalysisError,
+    ) -> Result<(), DataAnalysisError> {
+        if self.mode == ParseMode::Strict {
+            return Err(problem);
+        }
+        detail!("{}", problem);
+        self.summary.reject(column);
+        if let Some(error) = problem.row_error() {
+            self.set_drop_reason(error.to_string());
+            self.summary.record_error(error);
+        }
+        Ok(())
+    }
+
+    pub fn amount(
+        &mut self,
+        index: usize,
+        column: &str,
+    ) -> Result<Option<Decimal>, DataAnalysisError> {
+        Ok(self.money(index, column)?.map(|m| m.amount))
+    }
+
+    pub fn money(
+        &mut self,
+        index: usize,
+        column: &str,
+    ) -> Result<Option<ParsedMoney>, DataAnalysisError> {
+        let options = self.options;
+        let value = self.parsed(index, column, |s| options.money.parse_with_currency(s))?;
+        let Some(mut value) = value else {
+            return Ok(None);
+        };
+        let Some(currency) = value.currency else {
+            return Ok(Some(value));
+        };
+        let line = self.line();
+        self.summary.count_currency(column, currency, line);
+
+        // Amounts without a symbol are taken to be in the base currency already
+        if let Some(rates) = &options.rates {
+            match rates.to_base(value.amount, currency) {
+                Ok(amount) => value.amount = amount,
+                Err(reason) => {
+                    let error = MoneyParseError {
+                        input: self.str(index),
+                        reason,
+                    };
+                    return self.fallback(column, Some(error));
+                }
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// Negative counts are let through, for the column's [`NegativePolicy`]
+    /// to settle.
+    pub fn count(&mut self, index: usize, column: &str) -> Result<Option<i64>, DataAnalysisError> {
+        let options = self.options;
+        let rules = CountRules {
+            allow_negative: true,
+            ..options.counts
+        };
+        self.parsed(index, column, |s| options.money.parse_count(s, rules))
+    }
+
+    /// `value` as `policy` has it, or `None` when the row is to be left out.
+    /// Fails under strict on a negative `policy` rejects.
+    pub fn negative(
+        &mut self,
+        index: usize,
+        column: &str,
+        value: Money,
+        policy: NegativePolicy,
+    ) -> Result<Option<Money>, DataAnalysisError> {
+        if !value.is_sign_negative() || value.is_zero() || policy == NegativePolicy::Allow {
+            return Ok(Some(value));
+        }
+        if policy != NegativePolicy::Reject {
+            *self
+                .summary
+                .negatives_changed
+                .entry(column.to_string())
+                .or_default() += 1;
+            return Ok(Some(match policy {
+                NegativePolicy::ClampToZero => Money::ZERO,
+                _ => value.abs(),
+            }));
+        }
+        let problem = DataAnalysisError::Money {
+            line: self.line(),
+            column: column.to_string(),
+            value: Some(self.str(index)),
+            reason: "negative, which its column rejects".to_string(),
+        };
+        if self.mode == ParseMode::Strict {
+            return Err(problem);
+        }
+        detail!("{}", problem);
+        if let Some(error) = problem.row_error() {
+            self.set_drop_reason(error.to_string());
+        }
+        self.summary
+            .count_drop(DropReason::Negative, Some(column), 1);
+        Ok(None)
+    }
+
+    /// Dates are never defaulted: a missing date skips the row, and so does
+    /// a bad one (with a warning) unless the mode is strict. When the file has
+    /// Month/Year columns they stand in for a missing or bad date, and a date
+    /// that disagrees with them is rejected.
+    pub fn date(
+        &mut self,
+        index: usize,
+        column: &str,
+        dates: &DateParser,
+        calendar: &CalendarColumns,
+    ) -> Result<Option<NaiveDate>, DataAnalysisError> {
+        let date = self.read_date(index, column, dates, calendar)?;
+        if date.is_none() {
+            self.summary
+                .count_drop(DropReason::BadDate, Some(column), 1);
+        }
+        Ok(date.map(|d| self.options.normalize_dates.apply(d)))
+    }
+
+    fn read_date(
+        &mut self,
+        index: usize,
+        column: &str,
+        dates: &DateParser,
+        calendar: &CalendarColumns,
+    ) -> Result<Option<NaiveDate>, DataAnalysisError> {
+        let month = calendar.read(self.record);
+        if self.is_missing(index) {
+            let rebuilt = self.rebuilt_date(&month);
+            if rebuilt.is_none() {
+                detail!("row {}: {} missing", self.line(), column);
+                self.set_drop_reason(format!("{} missing", column));
+            }
+            return Ok(rebuilt);
+        }
+        match dates.parse(&self.str(index)) {
+            Ok(parsed) => {
+                if parsed.has_time {
+                    self.summary.timed_dates += 1;
+                }
+                if parsed.two_digit_year {
+                    self.summary.two_digit_years += 1;
+                    // "05/06/68" pivoting to 2068 is more likely 1968
+                    let horizon = Local::now().date_naive() + Days::new(365);
+                    if parsed.date > horizon {
+                        info!(
+                            "row {}: {} '{}' - two-digit year read as {}, more than a year ahead",
+                            self.line(),
+                            column,
+                            self.str(index),
+                            parsed.date.year()
+                        );
+                        self.summary.suspicious_dates += 1;
+                    }
+                }
+                if !month.contradicts(parsed.date) {
+                    return Ok(Some(parsed.date));
+                }
+                let other = month.first_day_near(parsed.date);
+                let policy = match (self.options.reconcile, other) {
+                    // A month or year that doesn't exist can't win
+                    (Reconcile::PreferMonthYear, None) => Reconcile::Reject,
+                    (policy, _) => policy,
+                };
+                self.summary.count_date_conflict(policy);
+                match (policy, other) {
+                    (Reconcile::PreferDate, _) => Ok(Some(parsed.date)),
+                    (Reconcile::PreferMonthYear, Some(other)) => Ok(Some(other)),
+                    _ => {
+                        let reason = format!(
+                            "is {} but {} ({} vs {})",
+                            parsed.date.format("%B %Y"),
+                            month.described,
+                            parsed.date,
+                            other.map(|d| d.to_string()).unwrap_or_default()
+                        );
+                        let problem = self.date_error(index, column, reason);
+                        self.reject(column, problem)?;
+                        Ok(None)
+                    }
+                }
+            }
+            Err(e) => match self.rebuilt_date(&month) {
+                Some(date) => Ok(Some(date)),
+                None => {
+                    let problem = self.date_error(index, column, e.reason.to_string());
+                    self.reject(column, problem)?;
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    fn date_error(&self, index: usize, column: &str, reason: String) -> DataAnalysisError {
+        DataAnalysisError::DateParse {
+            line: self.line(),
+            column: column.to_string(),
+            value: Some(self.str(index)),
+            reason,
+        }
+    }
+
+    // The first of the month from the calendar columns, if they give one
+    fn rebuilt_date(&mut self, month: &CalendarMonth) -> Option<NaiveDate> {
+        let date = month.first_day()?;
+        self.summary.rebuilt_dates += 1;
+        Some(date)
+    }
+
+    /// The currency the given money columns agree on, warning when they don't.
+    pub fn currency(&self, columns: &[(&str, ParsedMoney)]) -> Option<Currency> {
+        let mut found: Option<(&str, Currency)> = None;
+        for (column, value) in columns {
+            let Some(currency) = value.currency else {
+                continue;
+            };
+            match found {
+                None => found = Some((column, currency)),
+                Some((first_column, first)) if first != currency => {
+                    info!(
+                        "row {}: mixed currencies - {} is {} but {} is {}",
+                        self.line(),
+                        first_column,
+                        first,
+                        column,
+                        currency
+                    );
+                    break;
+                }
+                Some(_) => {}
+            }
+        }
+        found.map(|(_, currency)| currency)
+    }
+}
+
+/// One record read by header name, for code working on an export outside a
+/// pipeline. Cells are parsed as a run with `options` would parse them, but
+/// nothing is defaulted or tallied: a missing or bad value is an error naming
+/// the row, the column and what was there.
+pub struct RecordView<'a> {
+    headers: &'a StringRecord,
+    record: &'a StringRecord,
+    options: &'a ParseOptions,
+}
+
+impl<'a> RecordView<'a> {
+    pub fn new(
+        headers: &'a StringRecord,
+        record: &'a StringRecord,
+        options: &'a ParseOptions,
+    ) -> Self {
+        RecordView {
+            headers,
+            record,
+            options,
+        }
+    }
+
+    pub fn line(&self) -> u64 {
+        self.record.position().map_or(0, |p| p.line())
+    }
+
+    /// The trimmed cell under `column`, found in any case.
+    pub fn str(&self, column: &str) -> Result<&'a str, DataAnalysisError> {
+        self.cell(column).map(|(_, cell)| cell)
+    }
+
+    pub fn money(&self, column: &str) -> Result<Money, DataAnalysisError> {
+        let (header, cell) = self.cell(column)?;
+        self.required(header, self.options.money.parse(cell))
+    }
+
+    pub fn count(&self, column: &str) -> Result<i64, DataAnalysisError> {
+        let (header, cell) = self.cell(column)?;
+        let parsed = self.options.money.parse_count(cell, self.options.counts);
+        self.required(header, parsed)
+    }
+
+    /// Read with `dates`, such as the options' parser once it has been
+    /// through [`DateParser::detect`].
+    pub fn date(&self, column: &str, dates: &DateParser) -> Result<NaiveDate, DataAnalysisError> {
+        let (header, cell) = self.cell(column)?;
+        let problem = |value: Option<&str>, reason: String| DataAnalysisError::DateParse {
+            line: self.line(),
+            column: header.to_string(),
+            value: value.map(str::to_string),
+            reason,
+        };
+        if self.options.money.missing().is_missing(cell) {
+            return Err(problem(None, "missing".to_string()));
+        }
+        dates
+            .parse(cell)
+            .map(|parsed| self.options.normalize_dates.apply(parsed.date))
+            .map_err(|e| problem(Some(cell), e.reason.to_string()))
+    }
+
+    // The column's header as the file has it, and its cell
+    fn cell(&self, column: &str) -> Result<(&'a str, &'a str), DataAnalysisError> {
+        let index = self
+            .headers
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(column.trim()))
+            .ok_or_else(|| format!("row {}: no '{}' column", self.line(), column))?;
+        let header = self.headers.get(index).unwrap_or("").trim();
+        Ok((header, self.record.get(index).unwrap_or("").trim()))
+    }
+
+    fn required<T>(
+        &self,
+        column: &str,
+        parsed: Result<Option<T>, MoneyParseError>,
+    ) -> Result<T, DataAnalysisError> {
+        let problem = |value, reason| DataAnalysisError::Money {
+            line: self.line(),
+            column: column.to_string(),
+            value,
+            reason,
+        };
+        match parsed {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) => Err(problem(None, "missing".to_string())),
+            Err(e) => Err(problem(Some(e.input), e.reason.to_string())),
+        }
+    }
+}
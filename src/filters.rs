@@ -0,0 +1,89 @@
+use crate::config::find_header;
+use crate::error::DataAnalysisError;
+use csv::StringRecord;
+use std::str::FromStr;
+
+/// A `--filter` condition such as `Country=France` or `Segment!=Government`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowFilter {
+    pub column: String,
+    pub value: String,
+    /// `!=` rather than `=`
+    pub negated: bool,
+}
+
+impl FromStr for RowFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, value, negated) = match s.split_once("!=") {
+            Some((column, value)) => (column, value, true),
+            None => match s.split_once('=') {
+                Some((column, value)) => (column, value, false),
+                None => {
+                    return Err(format!(
+                        "filter '{}' needs Column=Value or Column!=Value",
+                        s
+                    ));
+                }
+            },
+        };
+        if column.trim().is_empty() {
+            return Err(format!("filter '{}' has no column", s));
+        }
+        Ok(RowFilter {
+            column: column.trim().to_string(),
+            value: value.trim().to_string(),
+            negated,
+        })
+    }
+}
+
+/// Every `--filter` for one file, with its columns found in the headers.
+/// A row has to pass all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RowFilters {
+    filters: Vec<(usize, RowFilter)>,
+    case_sensitive: bool,
+}
+
+impl RowFilters {
+    /// Fails on a column the file doesn't have, before any rows are read.
+    pub fn resolve(
+        filters: &[RowFilter],
+        case_sensitive: bool,
+        headers: &StringRecord,
+    ) -> Result<Self, DataAnalysisError> {
+        let filters = filters
+            .iter()
+            .map(|filter| {
+                find_header(headers, &filter.column)
+                    .map_err(|e| format!("can't filter on '{}': {}", filter.column, e))?
+                    .map(|index| (index, filter.clone()))
+                    .ok_or_else(|| {
+                        let found: Vec<&str> = headers.iter().map(str::trim).collect();
+                        format!(
+                            "no '{}' column to filter on (the file has {})",
+                            filter.column,
+                            found.join(", ")
+                        )
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(RowFilters {
+            filters,
+            case_sensitive,
+        })
+    }
+
+    pub fn matches(&self, record: &StringRecord) -> bool {
+        self.filters.iter().all(|(index, filter)| {
+            let cell = record.get(*index).unwrap_or("").trim();
+            let equal = match self.case_sensitive {
+                true => cell == filter.value,
+                false => cell.to_lowercase() == filter.value.to_lowercase(),
+            };
+            equal != filter.negated
+        })
+    }
+}
@@ -0,0 +1,33 @@
+//! Cleans the Financial Sample sales exports: money, counts and dates are
+//! parsed and checked, bad rows reported and the rest written out as CSV.
+//!
+//! [`pipelines`] has one function per export. The other modules are the
+//! pieces they're built from, for use on their own.
+
+pub mod clean;
+#[cfg(any(feature = "parquet", feature = "arrow-ipc"))]
+pub mod columnar;
+pub mod compress;
+pub mod config;
+pub mod console;
+pub mod dataset;
+pub mod dates;
+pub mod encoding;
+pub mod error;
+pub mod fields;
+pub mod filters;
+pub mod outliers;
+pub mod output;
+pub mod parsing;
+pub mod pipelines;
+pub mod profile;
+pub mod rates;
+pub mod report;
+pub mod rows;
+pub mod sink;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod summary;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
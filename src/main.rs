@@ -1,373 +1,276 @@
-use chrono::NaiveDate;
-use csv::{ReaderBuilder, WriterBuilder};
-use std::error::Error;
-
-#[derive(Debug)]
-struct DashboardRow {
-    country: String,
-    product: String,
-    units_sold: i64,
-    manufacturing_price: f64,
-    sale_price: f64,
-    date: NaiveDate,
-}
-fn parse_money(s: &str) -> Option<f64> {
-    // Remove $, £, commas
-    let cleaned_string = s.replace(&['$', '£', ','][..], "");
-    let clean = cleaned_string.trim();
-    if clean.is_empty() || clean == "null" {
-        None
-    } else {
-        clean.parse::<f64>().ok()
+// A build without any pipeline feature still compiles, though it has
+// nothing to run, so what's only used for running one goes unused
+#![cfg_attr(
+    not(any(feature = "dashboard", feature = "timeseries", feature = "forecasting")),
+    allow(unused_variables)
+)]
+
+mod cli;
+
+use clap::Parser;
+use cli::{Cli, Command, DATA_DIR};
+#[cfg(feature = "dashboard")]
+use cli::{DASHBOARD_INPUT, DASHBOARD_OUTPUT};
+#[cfg(feature = "forecasting")]
+use cli::{FORECAST_INPUT, FORECAST_OUTPUT};
+#[cfg(feature = "timeseries")]
+use cli::{TIMESERIES_INPUT, TIMESERIES_OUTPUT};
+use data_analysis::config::DatasetConfig;
+use data_analysis::console::{Verbosity, set_verbosity};
+use data_analysis::error::DataAnalysisError;
+use data_analysis::fields::ParseOptions;
+#[cfg(feature = "dashboard")]
+use data_analysis::pipelines::clean_dashboard;
+#[cfg(feature = "forecasting")]
+use data_analysis::pipelines::clean_forecasting;
+#[cfg(feature = "timeseries")]
+use data_analysis::pipelines::clean_timeseries;
+use data_analysis::report::{html, markdown, write_report};
+use data_analysis::summary::{
+    DropRateExceeded, PipelineRun, RunReport, RunSummary, failed_summary, print_runs, write_reports,
+};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    set_verbosity(match (cli.clean.quiet, cli.clean.verbose) {
+        (true, _) => Verbosity::Quiet,
+        (_, true) => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    });
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            if let DataAnalysisError::DropRate(_) = err {
+                ExitCode::from(DropRateExceeded::EXIT_CODE)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
     }
 }
 
-fn clean_dashboard_csv() -> Result<(), Box<dyn Error>> {
-    println!("Opening file");
-
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(true)
-        .trim(csv::Trim::All)
-        .from_path("Data/Part_B_Dashboard_file.csv")?;
-
-    let mut records = Vec::new();
-
-    for result in rdr.records() {
-        let record = result?;
-
-        if record
-            .iter()
-            .all(|s| s.trim().is_empty() || s.trim() == "null")
-        {
-            continue;
-        }
-
-        let country = record.get(1).unwrap_or("").trim().to_string();
-        let product = record.get(2).unwrap_or("").trim().to_string();
-
-        let units_sold_str = record.get(4).unwrap_or("").trim();
-        let units_sold = match parse_money(units_sold_str) {
-            Some(val) => val.floor() as i64,
-            None => continue,
+fn run(cli: &Cli) -> Result<(), DataAnalysisError> {
+    if let (Some(Command::All(_)), Some(_)) = (&cli.command, &cli.clean.append) {
+        return Err(
+            "--append merges into one pipeline's cleaned file, so name the pipeline instead of all"
+                .into(),
+        );
+    }
+    if let (Some(Command::All(_)), Some(_)) = (&cli.command, &cli.clean.table) {
+        return Err(
+            "--table names one pipeline's table, so name the pipeline instead of all, which writes a table for each"
+                .into(),
+        );
+    }
+    let base = cli.clean.parse_options()?;
+    let output = cli.clean.output_options()?;
+    let settings = cli.settings()?;
+    let options = |dataset: &DatasetConfig| {
+        let mut options = ParseOptions {
+            columns: dataset.columns.clone(),
+            ..base.clone()
         };
-
-        let manufacturing_price_raw = record.get(5).unwrap_or("").trim();
-        if manufacturing_price_raw.is_empty() || manufacturing_price_raw == "null" {
-            continue;
+        if let Some(delimiter) = dataset.delimiter {
+            options.delimiter = delimiter.0;
+        }
+        if let Some(formats) = &dataset.date_formats {
+            options.dates = options.dates.with_formats(formats);
         }
-        let manufacturing_price = match parse_money(manufacturing_price_raw) {
-            Some(val) => val,
-            None => continue,
+        if let Some(missing) = &dataset.missing_values {
+            options.money = options.money.with_null_sentinels(missing);
+        }
+        if let Some(symbols) = &dataset.currency_symbols {
+            options.money = options.money.with_symbols(symbols);
+        }
+        options
+    };
+    // An output given for the pipeline wins over --output-template
+    let paths = |dataset: &DatasetConfig, input: &str, file: &str| {
+        let input = dataset.input_or(Path::new(DATA_DIR), input);
+        let output = match &dataset.output {
+            Some(path) => path.clone(),
+            None => output.templated_output(&input).unwrap_or_else(|| {
+                dataset
+                    .output_or(Path::new(DATA_DIR), file)
+                    .with_extension(output.extension())
+            }),
         };
-
-        let sale_price_str = record.get(6).unwrap_or("").trim();
-        let sale_price = match parse_money(sale_price_str) {
-            Some(val) => val,
-            None => continue,
+        (input, output)
+    };
+    let (options, output, settings) = (&options, &output, &settings);
+
+    #[cfg(feature = "dashboard")]
+    let (dashboard, dashboard_output) = {
+        let outliers = cli.clean.outlier_rule()?;
+        let (input, path) = paths(&settings.dashboard, DASHBOARD_INPUT, DASHBOARD_OUTPUT);
+        let shown = (input.clone(), path.clone());
+        let run = move || {
+            clean_dashboard(
+                &options(&settings.dashboard),
+                output,
+                outliers,
+                &input,
+                &path,
+            )
         };
-
-        let date_str = record.get(12).unwrap_or("").trim();
-        if date_str.is_empty() || date_str == "null" {
-            continue;
+        (run, shown)
+    };
+    #[cfg(feature = "timeseries")]
+    let (timeseries, timeseries_output) = {
+        let percent_mode = cli.clean.percent_mode;
+        let (input, path) = paths(&settings.timeseries, TIMESERIES_INPUT, TIMESERIES_OUTPUT);
+        let shown = (input.clone(), path.clone());
+        let run = move || {
+            clean_timeseries(
+                &options(&settings.timeseries),
+                percent_mode,
+                output,
+                &input,
+                &path,
+            )
+        };
+        (run, shown)
+    };
+    #[cfg(feature = "forecasting")]
+    let (forecast, forecast_output) = {
+        let moving_average = cli.clean.moving_average()?;
+        let (input, path) = paths(&settings.forecast, FORECAST_INPUT, FORECAST_OUTPUT);
+        let shown = (input.clone(), path.clone());
+        let run = move || {
+            clean_forecasting(
+                &options(&settings.forecast),
+                output,
+                moving_average,
+                &input,
+                &path,
+            )
+        };
+        (run, shown)
+    };
+
+    let single = |name: &str, pipeline: Pipeline, (input, output): &(PathBuf, PathBuf)| {
+        let result = report(guarded(pipeline));
+        let run = RunReport::new(name, input, output, &result);
+        if let Some(path) = &cli.clean.summary_out {
+            write_reports(path, &run)?;
         }
-        let date = NaiveDate::parse_from_str(date_str, "%d/%m/%Y")?;
-
-        records.push(DashboardRow {
-            country,
-            product,
-            units_sold,
-            manufacturing_price,
-            sale_price,
-            date,
-        });
+        write_documents(cli, &[run])?;
+        result.map(drop)
+    };
+    let fail_fast = match &cli.command {
+        #[cfg(feature = "dashboard")]
+        Some(Command::Dashboard(_)) => return single("dashboard", &dashboard, &dashboard_output),
+        #[cfg(feature = "timeseries")]
+        Some(Command::Timeseries(_)) => {
+            return single("timeseries", &timeseries, &timeseries_output);
+        }
+        #[cfg(feature = "forecasting")]
+        Some(Command::Forecast(_)) | None => {
+            return single("forecast", &forecast, &forecast_output);
+        }
+        #[cfg(not(feature = "forecasting"))]
+        None => {
+            return Err("this build has no forecast pipeline to run by default; name one".into());
+        }
+        Some(Command::All(all)) => all.fail_fast,
+    };
+
+    let pipelines: &[(&str, Pipeline, (PathBuf, PathBuf))] = &[
+        #[cfg(feature = "dashboard")]
+        ("dashboard", &dashboard, dashboard_output),
+        #[cfg(feature = "timeseries")]
+        ("timeseries", &timeseries, timeseries_output),
+        #[cfg(feature = "forecasting")]
+        ("forecast", &forecast, forecast_output),
+    ];
+    if pipelines.is_empty() {
+        return Err(
+            "this build has no pipelines; enable the dashboard, timeseries or forecasting feature"
+                .into(),
+        );
     }
-
-    println!("Calculating outlier bounds");
-
-    let sale_prices: Vec<f64> = records.iter().map(|r| r.sale_price).collect();
-    let mut sorted_prices = sale_prices.clone();
-    sorted_prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-    let q1 = sorted_prices[(sorted_prices.len() as f64 * 0.25).floor() as usize];
-    let q3 = sorted_prices[(sorted_prices.len() as f64 * 0.75).floor() as usize];
-    let iqr = q3 - q1;
-    let lower = q1 - 1.5 * iqr;
-    let upper = q3 + 1.5 * iqr;
-
-    println!("Filtering out outliers");
-
-    let filtered: Vec<&DashboardRow> = records
+    let mut results = Vec::new();
+    for (name, pipeline, paths) in pipelines {
+        let started = Instant::now();
+        let result = report(guarded(*pipeline));
+        if let Err(e) = &result {
+            eprintln!("error: {}: {}", name, e);
+        }
+        let failed = result.is_err();
+        results.push((*name, paths, started.elapsed(), result));
+        if failed && fail_fast {
+            break;
+        }
+    }
+    let reports: Vec<RunReport> = results
         .iter()
-        .filter(|r| r.sale_price >= lower && r.sale_price <= upper)
+        .map(|(name, (input, output), _, result)| RunReport::new(name, input, output, result))
         .collect();
-
-    println!("Saving cleaned CSV");
-
-    let mut wtr = WriterBuilder::new()
-        .has_headers(true)
-        .from_path("Data/Part_B_Dashboard_Cleaned.csv")?;
-
-    // Write header
-    wtr.write_record([
-        "Country",
-        "Product",
-        "Units Sold",
-        "Manufacturing Price Parsed",
-        "Sale Price Parsed",
-        "Date_ISO",
-    ])?;
-
-    for r in filtered {
-        wtr.write_record([
-            &r.country,
-            &r.product,
-            &r.units_sold.to_string(),
-            &r.manufacturing_price.to_string(),
-            &r.sale_price.to_string(),
-            &r.date.format("%Y-%m-%d").to_string(),
-        ])?;
+    if let Some(path) = &cli.clean.summary_out {
+        write_reports(path, &reports)?;
     }
+    write_documents(cli, &reports)?;
+    drop(reports);
+    let runs: Vec<PipelineRun> = results
+        .into_iter()
+        .map(|(name, (_, output), duration, result)| PipelineRun {
+            name,
+            output: output.clone(),
+            duration,
+            outcome: result
+                .map(|summary| (summary.rows_read, summary.rows_written))
+                .map_err(|e| e.to_string()),
+        })
+        .collect();
+    print_runs(&runs);
 
-    wtr.flush()?;
-    println!("Done!");
-
-    Ok(())
-}
-
-fn clean_timeseries_csv() -> Result<(), Box<dyn Error>> {
-    println!("Opening timeseries file");
-
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(true)
-        .trim(csv::Trim::All)
-        .from_path("Data/Part_C_Timeseries.csv")?;
-
-    let mut wtr = WriterBuilder::new()
-        .has_headers(true)
-        .from_path("Data/Part_C_Timeseries_Cleaned.csv")?;
-
-    wtr.write_record([
-        "Segment",
-        "Country",
-        "Product",
-        "Discount Band",
-        "Units Sold",
-        "Manufacturing Price Parsed",
-        "Sale Price Parsed",
-        "Budget Parsed",
-        "Discounts Parsed",
-        "Sales Parsed",
-        "COGS Parsed",
-        "Profit Parsed",
-        "Date_ISO",
-    ])?;
-    for result in rdr.records() {
-        let record = result?;
-
-        if record
-            .iter()
-            .all(|s| s.trim().is_empty() || s.trim() == "null")
-        {
-            continue;
-        }
-
-        let segment = record.get(0).unwrap_or("").trim().to_string();
-        let country = record.get(1).unwrap_or("").trim().to_string();
-        let product = record.get(2).unwrap_or("").trim().to_string();
-        let discount_band = record.get(3).unwrap_or("").trim().to_string();
-
-        let units_sold = parse_money(record.get(4).unwrap_or("").trim())
-            .map(|v| v.floor() as i64)
-            .unwrap_or(0);
-
-        let manufacturing_price = parse_money(record.get(5).unwrap_or("").trim()).unwrap_or(0.0);
-        let sale_price = parse_money(record.get(6).unwrap_or("").trim()).unwrap_or(0.0);
-        let budget = parse_money(record.get(7).unwrap_or("").trim()).unwrap_or(0.0);
-        let discounts = parse_money(record.get(8).unwrap_or("").trim()).unwrap_or(0.0);
-        let sales = parse_money(record.get(9).unwrap_or("").trim()).unwrap_or(0.0);
-        let cogs = parse_money(record.get(10).unwrap_or("").trim()).unwrap_or(0.0);
-        let profit = parse_money(record.get(11).unwrap_or("").trim()).unwrap_or(0.0);
-
-        let date_str = record.get(12).unwrap_or("").trim();
-        if date_str.is_empty() || date_str == "null" {
-            continue;
-        }
-        let date = NaiveDate::parse_from_str(date_str, "%d/%m/%Y")?;
-
-        wtr.write_record([
-            &segment,
-            &country,
-            &product,
-            &discount_band,
-            &units_sold.to_string(),
-            &manufacturing_price.to_string(),
-            &sale_price.to_string(),
-            &budget.to_string(),
-            &discounts.to_string(),
-            &sales.to_string(),
-            &cogs.to_string(),
-            &profit.to_string(),
-            &date.format("%Y-%m-%d").to_string(),
-        ])?;
+    let failed = runs.iter().filter(|run| run.outcome.is_err()).count();
+    if failed > 0 {
+        return Err(format!("{} of {} pipelines failed", failed, pipelines.len()).into());
     }
-
-    wtr.flush()?;
-    println!("Timeseries CSV cleaned and saved!");
-
     Ok(())
 }
 
-fn clean_forcasting_csv() -> Result<(), Box<dyn Error>> {
-    use chrono::NaiveDate;
-    use csv::{ReaderBuilder, WriterBuilder};
-
-    println!("Opening forecasting file");
-
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(true)
-        .trim(csv::Trim::All)
-        .from_path("Data/Part_D_Forcasting.csv")?;
-
-    let mut wtr = WriterBuilder::new()
-        .has_headers(true)
-        .from_path("Data/Part_D_Forcasting_Cleaned.csv")?;
-
-    #[derive(Clone)]
-    struct Row {
-        segment: String,
-        country: String,
-        product: String,
-        discount_band: String,
-        units_sold: i64,
-        procurement: i64,
-        manufactured_price: i64,
-        sale_price: i64,
-        budget: i64,
-        discounts: i64,
-        sales: i64,
-        cogs: i64,
-        date: NaiveDate,
+// Writes --report-md and --report-html, if they were asked for
+fn write_documents(cli: &Cli, runs: &[RunReport]) -> Result<(), DataAnalysisError> {
+    if let Some(path) = &cli.clean.report_md {
+        write_report(path, &markdown(runs))?;
     }
-
-    let mut rows: Vec<Row> = Vec::new();
-
-    for result in rdr.records() {
-        let record = result?;
-
-        if record
-            .iter()
-            .all(|s| s.trim().is_empty() || s.trim() == "null")
-        {
-            continue;
-        }
-
-        let date_str = record.get(12).unwrap_or("").trim();
-        if date_str.is_empty() || date_str == "null" {
-            continue;
-        }
-
-        let date = NaiveDate::parse_from_str(date_str, "%d/%m/%Y")?;
-
-        rows.push(Row {
-            segment: record.get(0).unwrap_or("").trim().to_string(),
-            country: record.get(1).unwrap_or("").trim().to_string(),
-            product: record.get(2).unwrap_or("").trim().to_string(),
-            discount_band: record.get(3).unwrap_or("").trim().to_string(),
-
-            units_sold: parse_money(record.get(4).unwrap_or(""))
-                .map(|v| v.floor() as i64)
-                .unwrap_or(0),
-
-            procurement: parse_money(record.get(5).unwrap_or(""))
-                .map(|v| v.floor() as i64)
-                .unwrap_or(0),
-
-            manufactured_price: parse_money(record.get(6).unwrap_or(""))
-                .map(|v| v.floor() as i64)
-                .unwrap_or(0),
-
-            sale_price: parse_money(record.get(7).unwrap_or(""))
-                .map(|v| v.floor() as i64)
-                .unwrap_or(0),
-
-            budget: parse_money(record.get(8).unwrap_or(""))
-                .map(|v| v.floor() as i64)
-                .unwrap_or(0),
-
-            discounts: parse_money(record.get(9).unwrap_or(""))
-                .map(|v| v.floor() as i64)
-                .unwrap_or(0),
-
-            sales: parse_money(record.get(10).unwrap_or(""))
-                .map(|v| v.floor() as i64)
-                .unwrap_or(0),
-
-            cogs: parse_money(record.get(11).unwrap_or(""))
-                .map(|v| v.floor() as i64)
-                .unwrap_or(0),
-
-            date,
-        });
+    if let Some(path) = &cli.clean.report_html {
+        write_report(path, &html(runs))?;
     }
+    Ok(())
+}
 
-    rows.sort_by_key(|r| r.date);
-
-    let window = 3;
-    let mut sales_ma: Vec<Option<f64>> = Vec::with_capacity(rows.len());
-
-    for i in 0..rows.len() {
-        if i + 1 < window {
-            sales_ma.push(None);
-        } else {
-            let sum: i64 = rows[i + 1 - window..=i].iter().map(|r| r.sales).sum();
+type Pipeline<'a> = &'a dyn Fn() -> Result<RunSummary, DataAnalysisError>;
+
+// Runs a pipeline, turning a panic into an error so that `all` goes on to
+// the next one. Its cleaned file is removed as the panic unwinds
+fn guarded(pipeline: Pipeline) -> Result<RunSummary, DataAnalysisError> {
+    panic::catch_unwind(AssertUnwindSafe(pipeline)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "no message".to_string());
+        Err(format!("the pipeline panicked: {}", message).into())
+    })
+}
 
-            sales_ma.push(Some(sum as f64 / window as f64));
+// Prints the run's summary, which a run over --max-drop-rate still has
+fn report(result: Result<RunSummary, DataAnalysisError>) -> Result<RunSummary, DataAnalysisError> {
+    match &result {
+        Ok(summary) => summary.print(),
+        Err(e) => {
+            if let Some(summary) = failed_summary(e) {
+                summary.print();
+            }
         }
     }
-
-    wtr.write_record([
-        "Segment",
-        "Country",
-        "Product",
-        "Discount Band",
-        "Units Sold",
-        "Procurement",
-        "Manufacturing Price Parsed",
-        "Sale Price Parsed",
-        "Budget Parsed",
-        "Discounts Parsed",
-        "Sales Parsed",
-        "COGS Parsed",
-        "Sales_MA_3",
-        "Date_ISO",
-    ])?;
-
-    for (row, ma) in rows.iter().zip(sales_ma.iter()) {
-        wtr.write_record([
-            &row.segment,
-            &row.country,
-            &row.product,
-            &row.discount_band,
-            &row.units_sold.to_string(),
-            &row.procurement.to_string(),
-            &row.manufactured_price.to_string(),
-            &row.sale_price.to_string(),
-            &row.budget.to_string(),
-            &row.discounts.to_string(),
-            &row.sales.to_string(),
-            &row.cogs.to_string(),
-            &ma.map(|v| v.round().to_string()).unwrap_or_default(),
-            &row.date.format("%Y-%m-%d").to_string(),
-        ])?;
-    }
-
-    println!("Forecasting CSV cleaned, smoothed, and saved!");
-    Ok(())
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    //clean_dashboard_csv()?;
-    //clean_timeseries_csv()?;
-    clean_forcasting_csv()?;
-    Ok(())
+    result
 }
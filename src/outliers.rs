@@ -0,0 +1,188 @@
+use crate::parsing::Money;
+use crate::rows::Record;
+use crate::stats::iqr_bounds;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use std::fmt;
+use std::str::FromStr;
+
+/// Values needed before quartiles say anything about outliers; with fewer
+/// every row is kept.
+pub const MIN_VALUES: usize = 4;
+
+/// How the dashboard decides a row is an outlier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutlierMethod {
+    /// Outside `multiplier` interquartile ranges beyond the quartiles
+    #[default]
+    Iqr,
+    /// Keep every row
+    None,
+}
+
+impl FromStr for OutlierMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "iqr" => Ok(OutlierMethod::Iqr),
+            "none" => Ok(OutlierMethod::None),
+            other => Err(format!(
+                "unknown outlier method '{}' (expected iqr or none)",
+                other
+            )),
+        }
+    }
+}
+
+/// The dashboard column outliers are judged on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutlierColumn {
+    UnitsSold,
+    ManufacturingPrice,
+    #[default]
+    SalePrice,
+}
+
+impl FromStr for OutlierColumn {
+    type Err = String;
+
+    // "Sale Price", "sale_price" and "sale-price" all work
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .trim()
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '_' | '-'))
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "unitssold" => Ok(OutlierColumn::UnitsSold),
+            "manufacturingprice" => Ok(OutlierColumn::ManufacturingPrice),
+            "saleprice" => Ok(OutlierColumn::SalePrice),
+            _ => Err(format!(
+                "can't filter outliers on '{}' (expected Units Sold, Manufacturing Price or Sale Price)",
+                s
+            )),
+        }
+    }
+}
+
+impl OutlierColumn {
+    /// The name pipelines read it under.
+    pub fn field(&self) -> &'static str {
+        match self {
+            OutlierColumn::UnitsSold => "units_sold",
+            OutlierColumn::ManufacturingPrice => "manufacturing_price",
+            OutlierColumn::SalePrice => "sale_price",
+        }
+    }
+}
+
+impl fmt::Display for OutlierColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            OutlierColumn::UnitsSold => "Units Sold",
+            OutlierColumn::ManufacturingPrice => "Manufacturing Price",
+            OutlierColumn::SalePrice => "Sale Price",
+        })
+    }
+}
+
+/// Which rows a pipeline drops as outliers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutlierRule {
+    pub method: OutlierMethod,
+    /// The number column judged, such as `sale_price`
+    pub field: &'static str,
+    /// How many interquartile ranges past the quartiles still count as normal
+    pub multiplier: Money,
+}
+
+impl OutlierRule {
+    /// The lowest and highest values kept, inclusive. `None` keeps everything,
+    /// as there are fewer than [`MIN_VALUES`] to measure against or no
+    /// filtering was asked for.
+    pub fn bounds(&self, values: &[Money]) -> Option<(Money, Money)> {
+        if self.method == OutlierMethod::None || values.len() < MIN_VALUES {
+            return None;
+        }
+        let values: Vec<f64> = values.iter().filter_map(Money::to_f64).collect();
+        let (lower, upper) = iqr_bounds(&values, self.multiplier.to_f64()?)?;
+        Some((Money::from_f64(lower)?, Money::from_f64(upper)?))
+    }
+
+    /// The bounds over every row's `field`, which needn't be the rule's own,
+    /// such as `profit` for timeseries rows.
+    pub fn bounds_over<R: Record>(&self, rows: &[R], field: &str) -> Option<(Money, Money)> {
+        let values: Vec<Money> = rows.iter().filter_map(|r| r.number(field)).collect();
+        self.bounds(&values)
+    }
+}
+
+/// Drops the rows whose `field` is outside `bounds`, handing each to
+/// `dropped` first, and gives how many went. Rows without the field stay.
+pub fn drop_outside<R: Record>(
+    rows: &mut Vec<R>,
+    field: &str,
+    (lower, upper): (Money, Money),
+    mut dropped: impl FnMut(&R, Money),
+) -> usize {
+    let before = rows.len();
+    rows.retain(|r| {
+        let Some(value) = r.number(field) else {
+            return true;
+        };
+        let kept = (lower..=upper).contains(&value);
+        if !kept {
+            dropped(r, value);
+        }
+        kept
+    });
+    before - rows.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(method: OutlierMethod) -> OutlierRule {
+        OutlierRule {
+            method,
+            field: "sale_price",
+            multiplier: Money::new(15, 1),
+        }
+    }
+
+    fn money(values: &[i64]) -> Vec<Money> {
+        values.iter().copied().map(Money::from).collect()
+    }
+
+    #[test]
+    fn bounds_for_small_datasets() {
+        let iqr = rule(OutlierMethod::Iqr);
+        // Q1 17.5 and Q3 32.5, interpolated, so 22.5 either side
+        assert_eq!(
+            iqr.bounds(&money(&[40, 10, 30, 20])),
+            Some((Money::from(-5), Money::from(55)))
+        );
+        // Q1 2 and Q3 4, which 100 is far beyond
+        assert_eq!(
+            iqr.bounds(&money(&[1, 2, 3, 4, 100])),
+            Some((Money::from(-1), Money::from(7)))
+        );
+        assert_eq!(
+            iqr.bounds(&money(&[5, 5, 5, 5])),
+            Some((Money::from(5), Money::from(5)))
+        );
+    }
+
+    #[test]
+    fn too_few_values_keep_every_row() {
+        let iqr = rule(OutlierMethod::Iqr);
+        assert_eq!(iqr.bounds(&money(&[1, 2, 1000])), None);
+        assert_eq!(iqr.bounds(&[]), None);
+        assert_eq!(
+            rule(OutlierMethod::None).bounds(&money(&[1, 2, 3, 4, 100])),
+            None
+        );
+    }
+}
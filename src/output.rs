@@ -0,0 +1,718 @@
+use crate::compress::{Compress, DEFAULT_LEVEL, is_gzipped, with_gz, without_gz};
+use crate::dates::ISO_DATE_FORMAT;
+use crate::error::DataAnalysisError;
+use crate::parsing::{Money, MoneyPrecision};
+use crate::rows::Record;
+use crate::sink::CellKind;
+use crate::stats::{Window, moving_average};
+use chrono::{Datelike, NaiveDate};
+use csv::{Terminator, WriterBuilder};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// When cells in the cleaned CSV are quoted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// Only those holding the delimiter, a quote or a line break
+    #[default]
+    Necessary,
+    Always,
+    /// Never, so a cell holding the delimiter won't read back as one
+    Never,
+}
+
+impl FromStr for QuoteStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "necessary" => Ok(QuoteStyle::Necessary),
+            "always" => Ok(QuoteStyle::Always),
+            "never" => Ok(QuoteStyle::Never),
+            other => Err(format!(
+                "unknown quote style '{}' (expected necessary, always or never)",
+                other
+            )),
+        }
+    }
+}
+
+/// What ends each line of the cleaned CSV.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            other => Err(format!(
+                "unknown line ending '{}' (expected lf or crlf)",
+                other
+            )),
+        }
+    }
+}
+
+/// What the cleaned files are written as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// JSON Lines, an object per row
+    Jsonl,
+    /// Parquet, typed, with snappy compression
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// An Arrow IPC file, the File format rather than the stream
+    #[cfg(feature = "arrow-ipc")]
+    ArrowIpc,
+    /// A table in a SQLite database
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    /// An Excel workbook with the rows on one worksheet
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+}
+
+impl OutputFormat {
+    /// Every format this build can write.
+    pub const ALL: &[OutputFormat] = &[
+        OutputFormat::Csv,
+        OutputFormat::Jsonl,
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet,
+        #[cfg(feature = "arrow-ipc")]
+        OutputFormat::ArrowIpc,
+        #[cfg(feature = "sqlite")]
+        OutputFormat::Sqlite,
+        #[cfg(feature = "xlsx")]
+        OutputFormat::Xlsx,
+    ];
+
+    /// The format `path`'s extension names, if it names one, looking past
+    /// any ".gz".
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let path = without_gz(path);
+        let extension = path.extension()?.to_str()?;
+        OutputFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+
+    /// The extension the default cleaned file names get.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Jsonl => "jsonl",
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => "parquet",
+            #[cfg(feature = "arrow-ipc")]
+            OutputFormat::ArrowIpc => "arrow",
+            #[cfg(feature = "sqlite")]
+            OutputFormat::Sqlite => "sqlite",
+            #[cfg(feature = "xlsx")]
+            OutputFormat::Xlsx => "xlsx",
+        }
+    }
+
+    /// A database, written in place a table at a time rather than as a
+    /// whole file.
+    pub fn is_database(self) -> bool {
+        #[cfg(feature = "sqlite")]
+        if self == OutputFormat::Sqlite {
+            return true;
+        }
+        false
+    }
+
+    /// What --format calls it.
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "arrow-ipc")]
+            OutputFormat::ArrowIpc => "arrow-ipc",
+            format => format.extension(),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        match OutputFormat::ALL
+            .iter()
+            .find(|format| format.name() == name)
+        {
+            Some(format) => Ok(*format),
+            None => {
+                let names: Vec<&str> = OutputFormat::ALL.iter().map(|f| f.name()).collect();
+                Err(format!(
+                    "unknown output format '{}' (expected {})",
+                    s,
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// Fills in an --output-template: {stem} becomes `stem` and {date} becomes
+/// `date` as YYYY-MM-DD. Any other placeholder, or a brace left open, fails.
+pub fn expand_template(template: &str, stem: &str, date: NaiveDate) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        name.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('}') else {
+            return Err(format!("output template '{}' has an unclosed {{", template));
+        };
+        match &rest[open + 1..open + close] {
+            "stem" => name.push_str(stem),
+            "date" => name.push_str(&date.format(ISO_DATE_FORMAT).to_string()),
+            placeholder => {
+                return Err(format!(
+                    "output template '{}' has an unknown placeholder {{{}}} (expected {{stem}} or {{date}})",
+                    template, placeholder
+                ));
+            }
+        }
+        rest = &rest[open + close + 1..];
+    }
+    name.push_str(rest);
+    Ok(name)
+}
+
+/// Extra columns derived from each row's date. They're all off by default so
+/// existing consumers keep getting the same files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateColumns {
+    /// Month the fiscal year starts in (1 for calendar years, 7 for July);
+    /// `Some` adds Fiscal Year and Fiscal Quarter
+    pub fiscal_year_start: Option<u32>,
+    /// Adds ISO Year, ISO Week and Day of Week
+    pub calendar: bool,
+    /// Adds Date_Epoch_Days, a numeric date for models
+    pub epoch_days: bool,
+    /// Adds Date_Raw, the date cell as it was in the source file
+    pub raw: bool,
+}
+
+impl DateColumns {
+    pub fn headers(&self) -> Vec<&'static str> {
+        let mut headers = Vec::new();
+        if self.raw {
+            headers.push("Date_Raw");
+        }
+        if self.fiscal_year_start.is_some() {
+            headers.extend(["Fiscal Year", "Fiscal Quarter"]);
+        }
+        if self.calendar {
+            headers.extend(["ISO Year", "ISO Week", "Day of Week"]);
+        }
+        if self.epoch_days {
+            headers.push("Date_Epoch_Days");
+        }
+        headers
+    }
+
+    /// What each of [`DateColumns::headers`] holds.
+    pub fn kinds(&self) -> Vec<CellKind> {
+        let mut kinds = Vec::new();
+        if self.raw {
+            kinds.push(CellKind::Text);
+        }
+        if self.fiscal_year_start.is_some() {
+            kinds.extend([CellKind::Integer, CellKind::Text]);
+        }
+        if self.calendar {
+            kinds.extend([CellKind::Integer, CellKind::Integer, CellKind::Text]);
+        }
+        if self.epoch_days {
+            kinds.push(CellKind::Integer);
+        }
+        kinds
+    }
+
+    pub fn values(&self, date: NaiveDate, raw: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        if self.raw {
+            values.push(raw.to_string());
+        }
+        if let Some(start) = self.fiscal_year_start {
+            let (year, quarter) = fiscal_period(date, start);
+            values.extend([year.to_string(), format!("Q{}", quarter)]);
+        }
+        if self.calendar {
+            // 2014-12-29 is in week 1 of ISO year 2015
+            let week = date.iso_week();
+            values.extend([
+                week.year().to_string(),
+                week.week().to_string(),
+                date.format("%A").to_string(),
+            ]);
+        }
+        if self.epoch_days {
+            values.push(epoch_days(date).to_string());
+        }
+        values
+    }
+}
+
+/// Days since 1970-01-01, negative before it.
+pub fn epoch_days(date: NaiveDate) -> i64 {
+    // 719_163 days from 0001-01-01 (day 1 of the common era) to 1970-01-01
+    i64::from(date.num_days_from_ce()) - 719_163
+}
+
+/// The fiscal year and quarter `date` falls in when the year starts in month
+/// `start`. A fiscal year is named after the calendar year it ends in, so with
+/// a July start, 2014-07-01 is the first day of Q1 of fiscal 2015.
+pub fn fiscal_period(date: NaiveDate, start: u32) -> (i32, u32) {
+    let months_in = (date.month() + 12 - start) % 12;
+    let year = if start > 1 && date.month() >= start {
+        date.year() + 1
+    } else {
+        date.year()
+    };
+    (year, months_in / 3 + 1)
+}
+
+/// A trailing moving average over the rows in date order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovingAverage {
+    pub window: usize,
+    /// Leading rows with at least this many values get an average of what
+    /// there is; fewer stay blank
+    pub min_periods: usize,
+}
+
+impl MovingAverage {
+    /// The added column's header, such as Sales_MA_3 averaging Sales.
+    pub fn header(&self, source: &str) -> String {
+        format!("{}_MA_{}", source, self.window)
+    }
+
+    pub fn over(&self, values: &[Money]) -> Vec<Option<Money>> {
+        moving_average(values, self.window, self.min_periods)
+    }
+
+    /// The average of each row's `field` in their current order. A row
+    /// without one is a gap, not a zero, and doesn't count towards
+    /// `min_periods`.
+    pub fn over_rows<R: Record>(&self, rows: &[R], field: &str) -> Vec<Option<Money>> {
+        self.over_gaps(rows.iter().map(|r| r.number(field)))
+    }
+
+    /// The average at each of `values` in turn, where `None` is a gap.
+    pub fn over_gaps(&self, values: impl IntoIterator<Item = Option<Money>>) -> Vec<Option<Money>> {
+        let mut window = Window::new(self.window, self.min_periods);
+        values.into_iter().map(|value| window.push(value)).collect()
+    }
+}
+
+/// The cleaned columns picked by --select.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// `None` keeps every column
+    indices: Option<Vec<usize>>,
+}
+
+impl Selection {
+    /// The selected cells of a header or row, in the selected order.
+    pub fn apply<'a, T: AsRef<str>>(&self, row: &'a [T]) -> Vec<&'a str> {
+        match &self.indices {
+            None => row.iter().map(AsRef::as_ref).collect(),
+            Some(indices) => indices
+                .iter()
+                .filter_map(|&i| row.get(i))
+                .map(AsRef::as_ref)
+                .collect(),
+        }
+    }
+
+    /// The selected items of anything laid out like the header.
+    pub fn pick<T: Copy>(&self, row: &[T]) -> Vec<T> {
+        match &self.indices {
+            None => row.to_vec(),
+            Some(indices) => indices
+                .iter()
+                .filter_map(|&i| row.get(i).copied())
+                .collect(),
+        }
+    }
+}
+
+/// Rows in each record batch unless --batch-size says otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// How the cleaned files are written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputOptions {
+    pub precision: MoneyPrecision,
+    pub date_columns: DateColumns,
+    /// Run everything but leave the cleaned files alone
+    pub dry_run: bool,
+    /// Field separator in the cleaned files
+    pub delimiter: u8,
+    pub quote_style: QuoteStyle,
+    pub terminator: LineEnding,
+    /// Each written from the same pass, the first being the one shown
+    pub formats: Vec<OutputFormat>,
+    /// Rows in each record batch of a Parquet or Arrow IPC file
+    pub batch_size: usize,
+    /// Gzip every cleaned file, not only those whose path ends in ".gz"
+    pub compress: Compress,
+    /// The gzip level, 0 to 9
+    pub compress_level: u32,
+    /// The SQLite table or worksheet written; `None` names it after the
+    /// pipeline
+    pub table: Option<String>,
+    /// Percentage of rows that can be dropped before the run fails; 100
+    /// never fails
+    pub max_drop_rate: Money,
+    /// Leave the cleaned file in place when the run fails over dropped rows
+    pub keep_partial: bool,
+    /// Only these columns, in this order, by their cleaned header names
+    pub select: Option<Vec<String>>,
+    /// Replace a cleaned file that's already there instead of failing
+    pub overwrite: bool,
+    /// Also write the rows left out, with why, next to each cleaned file
+    pub rejects: bool,
+    /// Profile the cleaned columns into the run summary, for the reports
+    pub profile: bool,
+    /// A cleaned file to merge the rows into, each new row replacing any
+    /// there with the same Segment, Country, Product and date, of those the
+    /// cleaned file has
+    pub append: Option<PathBuf>,
+    /// chrono format for the date column; `None` writes ISO 8601
+    date_format: Option<String>,
+    /// --output-template and the day it fills in
+    output_template: Option<(String, NaiveDate)>,
+}
+
+impl OutputOptions {
+    pub fn new(precision: MoneyPrecision, date_columns: DateColumns) -> Self {
+        OutputOptions {
+            precision,
+            date_columns,
+            dry_run: false,
+            delimiter: b',',
+            quote_style: QuoteStyle::default(),
+            terminator: LineEnding::default(),
+            formats: vec![OutputFormat::default()],
+            batch_size: DEFAULT_BATCH_SIZE,
+            compress: Compress::None,
+            compress_level: DEFAULT_LEVEL,
+            table: None,
+            max_drop_rate: Money::ONE_HUNDRED,
+            keep_partial: false,
+            select: None,
+            overwrite: false,
+            rejects: false,
+            profile: false,
+            append: None,
+            date_format: None,
+            output_template: None,
+        }
+    }
+
+    /// Writes dates with `format` instead of ISO 8601, failing straight away
+    /// if chrono can't use it.
+    pub fn with_date_format(mut self, format: &str) -> Result<Self, DataAnalysisError> {
+        let probe = NaiveDate::from_ymd_opt(2014, 1, 31).unwrap_or_default();
+        let mut out = String::new();
+        write!(out, "{}", probe.format(format))
+            .map_err(|_| format!("bad output date format '{}'", format))?;
+        self.date_format = Some(format.to_string());
+        Ok(self)
+    }
+
+    /// Names cleaned files from `template` with `today` as {date}, failing
+    /// straight away on a placeholder it doesn't know.
+    pub fn with_output_template(
+        mut self,
+        template: &str,
+        today: NaiveDate,
+    ) -> Result<Self, DataAnalysisError> {
+        if template.trim().is_empty() {
+            return Err("--output-template is empty".into());
+        }
+        expand_template(template, "", today)?;
+        self.output_template = Some((template.to_string(), today));
+        Ok(self)
+    }
+
+    /// How the cleaned CSV and its rejects are written: delimiter, quoting
+    /// and line ending.
+    pub fn writer_builder(&self) -> WriterBuilder {
+        let mut builder = WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote_style(match self.quote_style {
+                QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+                QuoteStyle::Always => csv::QuoteStyle::Always,
+                QuoteStyle::Never => csv::QuoteStyle::Never,
+            })
+            .terminator(match self.terminator {
+                LineEnding::Lf => Terminator::Any(b'\n'),
+                LineEnding::Crlf => Terminator::CRLF,
+            });
+        builder
+    }
+
+    /// The extension the default cleaned file names get, the first format's,
+    /// with ".gz" after it under --compress gzip.
+    pub fn extension(&self) -> String {
+        let extension = self
+            .formats
+            .first()
+            .copied()
+            .unwrap_or_default()
+            .extension();
+        match self.compress {
+            Compress::Gzip => format!("{}.gz", extension),
+            Compress::None => extension.to_string(),
+        }
+    }
+
+    /// Where each format is written for `output`: there itself for a single
+    /// format, or for several, its name with each format's extension, and
+    /// ".gz" again after it if it had one.
+    pub fn output_paths(&self, output: &Path) -> Result<Vec<(OutputFormat, PathBuf)>, String> {
+        match self.formats.as_slice() {
+            [format] => Ok(vec![(*format, output.to_path_buf())]),
+            _ if output == Path::new("-") => {
+                Err("standard output can take one --format, not several".to_string())
+            }
+            formats => Ok(formats
+                .iter()
+                .map(|format| {
+                    let path = without_gz(output).with_extension(format.extension());
+                    match is_gzipped(output) {
+                        true => (*format, with_gz(&path)),
+                        false => (*format, path),
+                    }
+                })
+                .collect()),
+        }
+    }
+
+    /// The gzip level the cleaned file at `path` is written with, if it's
+    /// gzipped: under --compress gzip, or when the path ends in ".gz".
+    pub fn gzip_level(&self, path: &Path) -> Option<u32> {
+        (self.compress == Compress::Gzip || is_gzipped(path)).then_some(self.compress_level)
+    }
+
+    /// Where --output-template puts the cleaned file for `input`, beside it.
+    pub fn templated_output(&self, input: &Path) -> Option<PathBuf> {
+        let (template, today) = self.output_template.as_ref()?;
+        let stem = match input == Path::new("-") {
+            true => "stdin".into(),
+            false => input.file_stem().unwrap_or_default().to_string_lossy(),
+        };
+        let name = expand_template(template, &stem, *today).ok()?;
+        Some(input.with_file_name(name))
+    }
+
+    /// The columns to write out of a pipeline's full `header`, failing on
+    /// any --select name it doesn't produce.
+    pub fn selection(&self, header: &[&str]) -> Result<Selection, DataAnalysisError> {
+        let Some(select) = &self.select else {
+            return Ok(Selection { indices: None });
+        };
+        let indices = select
+            .iter()
+            .map(|name| {
+                header
+                    .iter()
+                    .position(|h| h.eq_ignore_ascii_case(name.trim()))
+                    .ok_or_else(|| {
+                        format!(
+                            "no '{}' column to select (this pipeline writes {})",
+                            name.trim(),
+                            header.join(", ")
+                        )
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Selection {
+            indices: Some(indices),
+        })
+    }
+
+    /// Date_ISO, unless the dates aren't ISO.
+    pub fn date_header(&self) -> &'static str {
+        match self.date_format {
+            Some(_) => "Date",
+            None => "Date_ISO",
+        }
+    }
+
+    /// The chrono format dates are written with.
+    pub fn date_format(&self) -> &str {
+        self.date_format.as_deref().unwrap_or(ISO_DATE_FORMAT)
+    }
+
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        date.format(self.date_format()).to_string()
+    }
+
+    /// A date cell as [`OutputOptions::format_date`] wrote it.
+    pub fn read_date(&self, cell: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(cell.trim(), self.date_format()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::{CleanPipeline, Column};
+    use crate::fields::ParseOptions;
+    use crate::parsing::MoneyPrecision;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn templates_fill_in_the_stem_and_date() {
+        let today = day(2024, 3, 9);
+        assert_eq!(
+            expand_template("{stem}_cleaned_{date}.csv", "Part_D_Forcasting", today),
+            Ok("Part_D_Forcasting_cleaned_2024-03-09.csv".into())
+        );
+        assert_eq!(
+            expand_template("{stem}-{stem}.csv", "a", today),
+            Ok("a-a.csv".into())
+        );
+        assert_eq!(
+            expand_template("cleaned.csv", "a", today),
+            Ok("cleaned.csv".into())
+        );
+    }
+
+    #[test]
+    fn templates_refuse_unknown_and_unclosed_placeholders() {
+        let today = day(2024, 3, 9);
+        let unknown = expand_template("{stem}_{time}.csv", "a", today).unwrap_err();
+        assert!(
+            unknown.contains("unknown placeholder {time}"),
+            "{}",
+            unknown
+        );
+        let empty = expand_template("{}.csv", "a", today).unwrap_err();
+        assert!(empty.contains("unknown placeholder {}"), "{}", empty);
+        let unclosed = expand_template("{stem_{date}.csv", "a", today).unwrap_err();
+        assert!(unclosed.contains("unknown placeholder"), "{}", unclosed);
+        let open = expand_template("{stem}_{date", "a", today).unwrap_err();
+        assert!(open.contains("unclosed {"), "{}", open);
+    }
+
+    #[test]
+    fn the_template_names_a_file_beside_the_export() {
+        let output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default())
+            .with_output_template("{stem}_cleaned_{date}.csv", day(2024, 3, 9))
+            .unwrap();
+        assert_eq!(
+            output.templated_output(Path::new("Data/Part_B_Dashboard_file.csv")),
+            Some(PathBuf::from(
+                "Data/Part_B_Dashboard_file_cleaned_2024-03-09.csv"
+            ))
+        );
+    }
+
+    #[test]
+    fn epoch_days_either_side_of_the_epoch() {
+        assert_eq!(epoch_days(day(1970, 1, 1)), 0);
+        assert_eq!(epoch_days(day(1970, 1, 2)), 1);
+        assert_eq!(epoch_days(day(1969, 12, 31)), -1);
+        assert_eq!(epoch_days(day(1, 1, 1)), -719_162);
+    }
+
+    #[test]
+    fn epoch_days_count_leap_days() {
+        assert_eq!(epoch_days(day(2000, 2, 29)), 11_016);
+        assert_eq!(epoch_days(day(2000, 3, 1)), 11_017);
+        // 1900 wasn't a leap year
+        assert_eq!(epoch_days(day(1900, 2, 28)), -25_509);
+        assert_eq!(epoch_days(day(1900, 3, 1)), -25_508);
+    }
+
+    #[test]
+    fn date_epoch_days_is_an_integer_column() {
+        let columns = DateColumns {
+            epoch_days: true,
+            ..DateColumns::default()
+        };
+        assert_eq!(columns.headers(), ["Date_Epoch_Days"]);
+        assert_eq!(columns.kinds(), [CellKind::Integer]);
+        assert_eq!(columns.values(day(1969, 7, 20), "20/07/1969"), ["-165"]);
+    }
+
+    // A product name holding the mainframe loader's delimiter
+    fn pipe_delimited(quote_style: QuoteStyle) -> String {
+        let options = ParseOptions::default();
+        let mut output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        output.delimiter = b'|';
+        output.quote_style = quote_style;
+        output.terminator = LineEnding::Crlf;
+        let mut cleaned = Vec::new();
+        CleanPipeline::new("sales", &options)
+            .column(Column::text("product", "Product"))
+            .column(Column::total("sales", "Sales"))
+            .run_on(
+                &output,
+                "Product,Sales,Date\n\"Velo|Pro\",£10.00,13/01/2020\nPaseo,£5.00,14/01/2020\n"
+                    .as_bytes(),
+                &mut cleaned,
+            )
+            .unwrap();
+        String::from_utf8(cleaned).unwrap()
+    }
+
+    #[test]
+    fn pipe_delimited_cells_are_quoted_and_read_back() {
+        let always = pipe_delimited(QuoteStyle::Always);
+        assert_eq!(
+            always,
+            "\"Product\"|\"Sales\"|\"Date_ISO\"\r\n\
+             \"Velo|Pro\"|\"10.00\"|\"2020-01-13\"\r\n\
+             \"Paseo\"|\"5.00\"|\"2020-01-14\"\r\n"
+        );
+        let necessary = pipe_delimited(QuoteStyle::Necessary);
+        assert_eq!(
+            necessary,
+            "Product|Sales|Date_ISO\r\n\
+             \"Velo|Pro\"|10.00|2020-01-13\r\n\
+             Paseo|5.00|2020-01-14\r\n"
+        );
+        for cleaned in [always, necessary] {
+            let products: Vec<String> = csv::ReaderBuilder::new()
+                .delimiter(b'|')
+                .from_reader(cleaned.as_bytes())
+                .records()
+                .map(|record| record.unwrap()[0].to_string())
+                .collect();
+            assert_eq!(products, ["Velo|Pro", "Paseo"]);
+        }
+    }
+
+    #[test]
+    fn never_quoting_splits_a_cell_holding_the_delimiter() {
+        let never = pipe_delimited(QuoteStyle::Never);
+        assert_eq!(
+            never.split("\r\n").nth(1),
+            Some("Velo|Pro|10.00|2020-01-13")
+        );
+    }
+}
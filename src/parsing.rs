@@ -0,0 +1,884 @@
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Money amounts are kept as exact decimals so cleaned output reconciles
+/// against the source figures (no 229.99999999999997).
+pub type Money = Decimal;
+
+/// Decimal places written for each group of money columns. Quantities such as
+/// Units Sold are always whole numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoneyPrecision {
+    /// Unit prices: Procurement, Manufacturing Price, Sale Price
+    pub prices: u32,
+    /// Totals: Budget, Discounts, Sales, COGS, Profit
+    pub totals: u32,
+}
+
+impl Default for MoneyPrecision {
+    fn default() -> Self {
+        MoneyPrecision {
+            prices: 2,
+            totals: 2,
+        }
+    }
+}
+
+/// Formats an amount for output with exactly `decimals` places, rounding
+/// half to even, so a column reads "3.00", "3.50", "230.00" throughout.
+pub fn format_money(value: Money, decimals: u32) -> String {
+    let mut rounded = value.round_dp_with_strategy(decimals, RoundingStrategy::MidpointNearestEven);
+    rounded.rescale(decimals);
+    rounded.to_string()
+}
+
+/// Cell values treated as "no value" unless configured otherwise. A lone dash
+/// is missing in money cells too, "£-" included, unless the parser is told to
+/// read it the accounting way, as zero.
+pub const DEFAULT_MISSING_VALUES: &[&str] =
+    &["", "null", "NULL", "N/A", "n/a", "-", "—", "nil", "NaN"];
+
+/// The markers a file uses for missing cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingValues(Vec<String>);
+
+impl MissingValues {
+    pub fn new<S: AsRef<str>>(values: &[S]) -> Self {
+        MissingValues(
+            values
+                .iter()
+                .map(|v| v.as_ref().trim().to_string())
+                .collect(),
+        )
+    }
+
+    /// The marker `s` matches, ignoring surrounding whitespace and currency
+    /// symbols (so "£-" is the "-" marker).
+    pub fn matching(&self, s: &str) -> Option<&str> {
+        let stripped: String = s.chars().filter(|c| !is_currency_symbol(*c)).collect();
+        let stripped = stripped.trim();
+        self.0
+            .iter()
+            .find(|m| m.as_str() == stripped)
+            .map(String::as_str)
+    }
+
+    pub fn is_missing(&self, s: &str) -> bool {
+        self.matching(s).is_some()
+    }
+}
+
+impl Default for MissingValues {
+    fn default() -> Self {
+        MissingValues::new(DEFAULT_MISSING_VALUES)
+    }
+}
+
+/// The decimal part a count can have by default: none, so a Units Sold of
+/// "$1,630.50" is rejected rather than quietly made a whole number.
+pub const DEFAULT_FRACTION_TOLERANCE: Decimal = Decimal::ZERO;
+
+/// What counts as a valid integer quantity for [`MoneyParser::parse_count`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountRules {
+    /// Largest decimal part that is dropped rather than rejected; zero
+    /// rejects anything that isn't a whole number
+    pub fraction_tolerance: Decimal,
+    /// Accept negative quantities (returns)
+    pub allow_negative: bool,
+    /// Largest believable quantity either way; anything beyond it is an error
+    pub max: Option<i64>,
+}
+
+impl Default for CountRules {
+    fn default() -> Self {
+        CountRules {
+            fraction_tolerance: DEFAULT_FRACTION_TOLERANCE,
+            allow_negative: false,
+            max: None,
+        }
+    }
+}
+
+/// Currency identified from the symbol or ISO code in a money cell. `$` and
+/// `¥` are taken to be USD and JPY, which is what our exports mean by them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Currency {
+    Usd,
+    Gbp,
+    Eur,
+    Jpy,
+    Inr,
+    /// Any other Unicode currency symbol
+    Other(char),
+    /// Any other ISO 4217 code, e.g. "CHF"
+    Code([u8; 3]),
+}
+
+// Active ISO 4217 codes, so a stray "ABC" isn't taken for a currency
+const ISO_CODES: &str = "AED AFN ALL AMD ANG AOA ARS AUD AWG AZN BAM BBD BDT BGN BHD BIF BMD BND \
+    BOB BRL BSD BTN BWP BYN BZD CAD CDF CHF CLP CNY COP CRC CUP CVE CZK DJF DKK DOP DZD EGP ERN \
+    ETB EUR FJD FKP GBP GEL GHS GIP GMD GNF GTQ GYD HKD HNL HTG HUF IDR ILS INR IQD IRR ISK JMD \
+    JOD JPY KES KGS KHR KMF KPW KRW KWD KYD KZT LAK LBP LKR LRD LSL LYD MAD MDL MGA MKD MMK MNT \
+    MOP MRU MUR MVR MWK MXN MYR MZN NAD NGN NIO NOK NPR NZD OMR PAB PEN PGK PHP PKR PLN PYG QAR \
+    RON RSD RUB RWF SAR SBD SCR SDG SEK SGD SHP SLE SOS SRD SSP STN SVC SYP SZL THB TJS TMT TND \
+    TOP TRY TTD TWD TZS UAH UGX USD UYU UZS VES VND VUV WST XAF XCD XOF XPF YER ZAR ZMW ZWL";
+
+impl Currency {
+    fn from_symbol(c: char) -> Currency {
+        match c {
+            '$' | '\u{FE69}' | '\u{FF04}' => Currency::Usd,
+            '£' | '\u{FFE1}' => Currency::Gbp,
+            '€' => Currency::Eur,
+            '¥' | '\u{FFE5}' => Currency::Jpy,
+            '₹' => Currency::Inr,
+            other => Currency::Other(other),
+        }
+    }
+
+    /// The currency for a three-letter ISO 4217 code, in either case.
+    fn from_code(code: &str) -> Option<Currency> {
+        let code = code.to_ascii_uppercase();
+        if code.len() != 3 || !ISO_CODES.split_whitespace().any(|c| c == code) {
+            return None;
+        }
+        Some(match code.as_str() {
+            "USD" => Currency::Usd,
+            "GBP" => Currency::Gbp,
+            "EUR" => Currency::Eur,
+            "JPY" => Currency::Jpy,
+            "INR" => Currency::Inr,
+            _ => {
+                let bytes = code.as_bytes();
+                Currency::Code([bytes[0], bytes[1], bytes[2]])
+            }
+        })
+    }
+}
+
+// "NaN", "-inf", "Infinity" and the like, in any case
+fn is_not_finite(s: &str) -> bool {
+    let unsigned = s.strip_prefix(['-', '+']).unwrap_or(s);
+    ["nan", "inf", "infinity"]
+        .iter()
+        .any(|word| unsigned.eq_ignore_ascii_case(word))
+}
+
+// Splits an ISO code off either end of a money cell: "1200 USD", "USD1200",
+// "usd 1,200". The code has to stand on its own, so "1200 USDX" keeps its
+// letters and fails later.
+fn split_currency_code(s: &str) -> (&str, Option<Currency>) {
+    let standalone = |c: Option<char>| c.is_none_or(|c| !c.is_alphabetic());
+    if s.len() > 3 && s.is_char_boundary(s.len() - 3) {
+        let (rest, code) = s.split_at(s.len() - 3);
+        if standalone(rest.chars().last())
+            && let Some(currency) = Currency::from_code(code)
+        {
+            return (rest.trim_end(), Some(currency));
+        }
+    }
+    if s.len() > 3 && s.is_char_boundary(3) {
+        let (code, rest) = s.split_at(3);
+        if standalone(rest.chars().next())
+            && let Some(currency) = Currency::from_code(code)
+        {
+            return (rest.trim_start(), Some(currency));
+        }
+    }
+    (s, None)
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Currency::from_code(s.trim()).ok_or_else(|| {
+            format!(
+                "unknown currency '{}' (expected an ISO 4217 code such as USD)",
+                s.trim()
+            )
+        })
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Currency::Usd => write!(f, "USD"),
+            Currency::Gbp => write!(f, "GBP"),
+            Currency::Eur => write!(f, "EUR"),
+            Currency::Jpy => write!(f, "JPY"),
+            Currency::Inr => write!(f, "INR"),
+            Currency::Other(symbol) => write!(f, "{}", symbol),
+            Currency::Code(code) => write!(f, "{}", String::from_utf8_lossy(code)),
+        }
+    }
+}
+
+/// A money amount along with the currency its symbol indicated, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParsedMoney {
+    pub amount: Money,
+    pub currency: Option<Currency>,
+}
+
+/// How to read a percentage that has no % sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PercentMode {
+    /// "0.125" is already a fraction, but anything above 1 ("12.5") is a percentage
+    #[default]
+    Auto,
+    /// "12.5" means 12.5%
+    Percent,
+    /// "0.125" means 12.5%
+    Fraction,
+}
+
+/// Which characters a file uses for thousands grouping and the decimal point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberFormat {
+    /// "1,234.56"
+    #[default]
+    Anglo,
+    /// "1.234,56"
+    Continental,
+}
+
+impl FromStr for NumberFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "anglo" => Ok(NumberFormat::Anglo),
+            "continental" => Ok(NumberFormat::Continental),
+            other => Err(format!(
+                "unknown number format '{}' (expected anglo or continental)",
+                other
+            )),
+        }
+    }
+}
+
+impl FromStr for PercentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(PercentMode::Auto),
+            "percent" => Ok(PercentMode::Percent),
+            "fraction" => Ok(PercentMode::Fraction),
+            other => Err(format!(
+                "unknown percent mode '{}' (expected auto, percent or fraction)",
+                other
+            )),
+        }
+    }
+}
+
+impl NumberFormat {
+    fn separators(self) -> (char, char) {
+        // (thousands, decimal)
+        match self {
+            NumberFormat::Anglo => (',', '.'),
+            NumberFormat::Continental => ('.', ','),
+        }
+    }
+}
+
+// Unicode general category Sc (Symbol, currency)
+fn is_currency_symbol(c: char) -> bool {
+    matches!(
+        c,
+        '$' | '\u{A2}'..='\u{A5}'
+            | '\u{58F}'
+            | '\u{60B}'
+            | '\u{7FE}'..='\u{7FF}'
+            | '\u{9F2}'..='\u{9F3}'
+            | '\u{9FB}'
+            | '\u{AF1}'
+            | '\u{BF9}'
+            | '\u{E3F}'
+            | '\u{17DB}'
+            | '\u{20A0}'..='\u{20C0}'
+            | '\u{A838}'
+            | '\u{FDFC}'
+            | '\u{FE69}'
+            | '\u{FF04}'
+            | '\u{FFE0}'..='\u{FFE1}'
+            | '\u{FFE5}'..='\u{FFE6}'
+            | '\u{11FDD}'..='\u{11FE0}'
+            | '\u{1E2FF}'
+            | '\u{1ECB0}'
+    )
+}
+
+/// Why a money or percent field couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoneyErrorReason {
+    /// Nothing left once symbols and brackets were removed, e.g. "$" or "()"
+    Empty,
+    UnexpectedChar(char),
+    MultipleDecimalPoints,
+    /// Thousands separators in the wrong place, e.g. "12,34" or "1.234,56" as Anglo
+    MisplacedSeparator,
+    UnbalancedParentheses,
+    /// More than one way of saying negative, e.g. "(-123)" or "-123-"
+    AmbiguousSign,
+    Overflow,
+    /// A percentage above 100%
+    AboveHundredPercent,
+    /// Two different currencies in one value, e.g. "$£5" or "$5 GBP"
+    MixedCurrency,
+    /// A quantity with a decimal part beyond the allowed tolerance
+    Fractional,
+    /// A negative quantity where returns aren't allowed
+    Negative,
+    /// Bigger than the configured sanity ceiling, e.g. a stray "9000000000000"
+    AboveCeiling(Decimal),
+    /// A currency the exchange rates table doesn't cover
+    NoExchangeRate(Currency),
+    /// "NaN" or "inf", which spreadsheets and float formatting write
+    NotFinite,
+}
+
+impl fmt::Display for MoneyErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoneyErrorReason::Empty => write!(f, "no digits"),
+            MoneyErrorReason::UnexpectedChar(c) => write!(f, "unexpected character '{}'", c),
+            MoneyErrorReason::MultipleDecimalPoints => write!(f, "more than one decimal point"),
+            MoneyErrorReason::MisplacedSeparator => write!(f, "misplaced thousands separator"),
+            MoneyErrorReason::UnbalancedParentheses => write!(f, "unbalanced parentheses"),
+            MoneyErrorReason::AmbiguousSign => write!(f, "more than one sign"),
+            MoneyErrorReason::Overflow => write!(f, "value out of range"),
+            MoneyErrorReason::AboveHundredPercent => write!(f, "more than 100%"),
+            MoneyErrorReason::MixedCurrency => write!(f, "more than one currency"),
+            MoneyErrorReason::Fractional => write!(f, "not a whole number"),
+            MoneyErrorReason::Negative => write!(f, "negative quantity"),
+            MoneyErrorReason::AboveCeiling(max) => write!(f, "larger than {}", max),
+            MoneyErrorReason::NoExchangeRate(currency) => {
+                write!(f, "no exchange rate for {}", currency)
+            }
+            MoneyErrorReason::NotFinite => write!(f, "not a finite number"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoneyParseError {
+    pub input: String,
+    pub reason: MoneyErrorReason,
+}
+
+impl fmt::Display for MoneyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' - {}", self.input, self.reason)
+    }
+}
+
+impl Error for MoneyParseError {}
+
+// Rewrites a number with the given separators as plain "1234.56", refusing
+// separators that don't make sense for them (e.g. "1.234,56" read with a "."
+// decimal point, "1.234.567,89.0", or "12,34" / "12 34" where the grouping
+// isn't in threes).
+fn normalize_number(s: &str, thousands: char, decimal: char) -> Result<String, MoneyErrorReason> {
+    let (sign, digits) = match s.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    let mut parts = digits.split(decimal);
+    let integer = parts.next().unwrap_or("");
+    let fraction = parts.next();
+    if parts.next().is_some() {
+        return Err(MoneyErrorReason::MultipleDecimalPoints);
+    }
+    if integer.is_empty() && fraction.is_none_or(str::is_empty) {
+        return Err(MoneyErrorReason::Empty);
+    }
+    if fraction.is_some_and(|f| f.contains(thousands)) {
+        // Thousands separator after the decimal point
+        return Err(MoneyErrorReason::MisplacedSeparator);
+    }
+
+    // French exports group with spaces ("1 234 567,89"), usually no-break ones
+    let groups: Vec<&str> = integer
+        .split([thousands, ' ', '\u{A0}', '\u{202F}'])
+        .collect();
+    if let Some(c) = groups
+        .iter()
+        .chain(fraction.iter())
+        .flat_map(|g| g.chars())
+        .find(|c| !c.is_ascii_digit())
+    {
+        return Err(MoneyErrorReason::UnexpectedChar(c));
+    }
+    if groups.len() > 1
+        && (groups[0].is_empty() || groups[0].len() > 3 || groups[1..].iter().any(|g| g.len() != 3))
+    {
+        return Err(MoneyErrorReason::MisplacedSeparator);
+    }
+
+    let mut out = format!("{}{}", sign, groups.concat());
+    if let Some(fraction) = fraction {
+        out.push('.');
+        out.push_str(fraction);
+    }
+    Ok(out)
+}
+
+// The currency a configured symbol stands for, such as "US$" or "kr"
+fn symbol_currency(symbol: &str) -> Currency {
+    let c = symbol
+        .chars()
+        .find(|c| is_currency_symbol(*c))
+        .or_else(|| symbol.chars().next())
+        .unwrap_or('$');
+    Currency::from_symbol(c)
+}
+
+/// Reads money, percent and count cells the way one dataset writes them:
+/// which currency symbols it uses, its separators and its missing-value
+/// markers. Build it once per file and reuse it for every row.
+///
+/// ```ignore
+/// let parser = MoneyParser::new()
+///     .with_symbols(&["€"])
+///     .with_decimal_separator(',')
+///     .with_thousands_separator('.')
+///     .with_null_sentinels(&["", "-"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoneyParser {
+    /// `None` strips any Unicode currency symbol
+    symbols: Option<Vec<String>>,
+    thousands: char,
+    decimal: char,
+    missing: MissingValues,
+    /// Largest believable amount either way
+    ceiling: Option<Money>,
+    /// Read a lone dash as zero rather than missing
+    dash_is_zero: bool,
+}
+
+impl Default for MoneyParser {
+    fn default() -> Self {
+        let (thousands, decimal) = NumberFormat::default().separators();
+        MoneyParser {
+            symbols: None,
+            thousands,
+            decimal,
+            missing: MissingValues::default(),
+            ceiling: None,
+            dash_is_zero: false,
+        }
+    }
+}
+
+impl MoneyParser {
+    /// Anglo separators, any currency symbol and the default missing markers.
+    pub fn new() -> Self {
+        MoneyParser::default()
+    }
+
+    /// Only strip these symbols; any other currency sign is an error.
+    pub fn with_symbols<S: AsRef<str>>(mut self, symbols: &[S]) -> Self {
+        let mut symbols: Vec<String> = symbols
+            .iter()
+            .map(|s| s.as_ref().trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        // Longest first, so "US$" is tried before "$"
+        symbols.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        self.symbols = Some(symbols);
+        self
+    }
+
+    pub fn with_decimal_separator(mut self, decimal: char) -> Self {
+        self.decimal = decimal;
+        self
+    }
+
+    pub fn with_thousands_separator(mut self, thousands: char) -> Self {
+        self.thousands = thousands;
+        self
+    }
+
+    /// Sets both separators from a named format.
+    pub fn with_number_format(self, format: NumberFormat) -> Self {
+        let (thousands, decimal) = format.separators();
+        self.with_thousands_separator(thousands)
+            .with_decimal_separator(decimal)
+    }
+
+    pub fn with_null_sentinels<S: AsRef<str>>(mut self, sentinels: &[S]) -> Self {
+        self.missing = MissingValues::new(sentinels);
+        self
+    }
+
+    /// Rejects amounts bigger than `ceiling` (in either direction) instead
+    /// of writing them out.
+    pub fn with_ceiling(mut self, ceiling: Money) -> Self {
+        self.ceiling = Some(ceiling.abs());
+        self
+    }
+
+    /// Reads a lone dash, with or without a symbol ("£-"), as zero the way
+    /// accounting exports write it, instead of as a missing value.
+    pub fn with_dash_as_zero(mut self, dash_is_zero: bool) -> Self {
+        self.dash_is_zero = dash_is_zero;
+        self
+    }
+
+    pub fn missing(&self) -> &MissingValues {
+        &self.missing
+    }
+
+    // The currency symbol at the start of `s`, if any, and its length
+    fn symbol_at(&self, s: &str) -> Option<(Currency, usize)> {
+        match &self.symbols {
+            None => s
+                .chars()
+                .next()
+                .filter(|c| is_currency_symbol(*c))
+                .map(|c| (Currency::from_symbol(c), c.len_utf8())),
+            Some(symbols) => symbols
+                .iter()
+                .find(|symbol| s.starts_with(symbol.as_str()))
+                .map(|symbol| (symbol_currency(symbol), symbol.len())),
+        }
+    }
+
+    /// Parses a money cell. `Ok(None)` means the cell was one of the missing
+    /// markers; anything else that isn't a number is an error saying why. A
+    /// lone dash is missing too, unless [`MoneyParser::with_dash_as_zero`].
+    pub fn parse(&self, s: &str) -> Result<Option<Money>, MoneyParseError> {
+        Ok(self.parse_with_currency(s)?.map(|m| m.amount))
+    }
+
+    /// Like [`MoneyParser::parse`], but also reports which currency symbol or
+    /// ISO code was stripped.
+    pub fn parse_with_currency(&self, s: &str) -> Result<Option<ParsedMoney>, MoneyParseError> {
+        let fail = |reason| MoneyParseError {
+            input: s.trim().to_string(),
+            reason,
+        };
+
+        // Remove currency symbols ($, £, €, ¥, ₹, ...), remembering which one it
+        // was. They go before or after the number, inside or outside a sign or
+        // brackets ("-$5", "$(5)", "5 €"), so "1$234" is an error, not 1234
+        let at_edge = |text: &str, signs: &[char]| {
+            text.chars()
+                .all(|c| c.is_whitespace() || signs.contains(&c) || is_currency_symbol(c))
+        };
+        let mut currency = None;
+        let mut cleaned_string = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            let Some((found, len)) = self.symbol_at(rest) else {
+                cleaned_string.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            };
+            let before = &s[..s.len() - rest.len()];
+            if !at_edge(before, &['-', '+', '(']) && !at_edge(&rest[len..], &['-', ')']) {
+                return Err(fail(MoneyErrorReason::UnexpectedChar(c)));
+            }
+            if currency.is_some_and(|seen| seen != found) {
+                return Err(fail(MoneyErrorReason::MixedCurrency));
+            }
+            currency = Some(found);
+            rest = &rest[len..];
+        }
+        let clean = cleaned_string.trim();
+        if self.dash_is_zero && clean == "-" {
+            return Ok(Some(ParsedMoney {
+                amount: Money::ZERO,
+                currency,
+            }));
+        }
+        if self.missing.matching(clean).is_some() {
+            return Ok(None);
+        }
+        // "NaN" is a missing marker by default; otherwise it and "inf" are
+        // bad values, so they never reach the outlier bounds
+        if is_not_finite(clean) {
+            return Err(fail(MoneyErrorReason::NotFinite));
+        }
+        let (clean, code) = split_currency_code(clean);
+        if let Some(code) = code {
+            if currency.is_some_and(|seen| seen != code) {
+                return Err(fail(MoneyErrorReason::MixedCurrency));
+            }
+            currency = Some(code);
+        }
+
+        // Accounting negatives: "(1,000.00)", "$(3,241.50)"
+        let (clean, negative) = match clean.strip_prefix('(') {
+            Some(inner) => (
+                inner
+                    .strip_suffix(')')
+                    .ok_or_else(|| fail(MoneyErrorReason::UnbalancedParentheses))?
+                    .trim(),
+                true,
+            ),
+            None if clean.ends_with(')') => {
+                return Err(fail(MoneyErrorReason::UnbalancedParentheses));
+            }
+            None => (clean, false),
+        };
+        if negative && clean.starts_with(['-', '+']) {
+            return Err(fail(MoneyErrorReason::AmbiguousSign));
+        }
+
+        // ERP credit entries put the minus last: "4,250.00-"
+        let (clean, negative) = match clean.strip_suffix('-') {
+            Some(_) if negative || clean.starts_with(['-', '+']) => {
+                return Err(fail(MoneyErrorReason::AmbiguousSign));
+            }
+            Some(rest) => (rest.trim_end(), true),
+            None => (clean, negative),
+        };
+
+        // Rounded figures: "1.2K", "$3.5M", "2B"
+        let (clean, multiplier) = match clean.chars().last().map(|c| c.to_ascii_uppercase()) {
+            Some('K') => (&clean[..clean.len() - 1], 1_000),
+            Some('M') => (&clean[..clean.len() - 1], 1_000_000),
+            Some('B') => (&clean[..clean.len() - 1], 1_000_000_000),
+            _ => (clean, 1),
+        };
+        let clean = clean.trim_end();
+
+        let value = normalize_number(clean, self.thousands, self.decimal)
+            .map_err(fail)?
+            .parse::<Money>()
+            .ok()
+            .and_then(|v| v.checked_mul(Money::from(multiplier)))
+            .ok_or_else(|| fail(MoneyErrorReason::Overflow))?;
+        if let Some(ceiling) = self.ceiling
+            && value.abs() > ceiling
+        {
+            return Err(fail(MoneyErrorReason::AboveCeiling(ceiling)));
+        }
+        Ok(Some(ParsedMoney {
+            amount: if negative { -value } else { value },
+            currency,
+        }))
+    }
+
+    /// Parses a percentage cell into a fraction, so "12%", "12.5 %" and "0.125"
+    /// all come back as 0.12 / 0.125. Values over 100% are rejected.
+    pub fn parse_percent(
+        &self,
+        s: &str,
+        mode: PercentMode,
+    ) -> Result<Option<Decimal>, MoneyParseError> {
+        let fail = |reason| MoneyParseError {
+            input: s.trim().to_string(),
+            reason,
+        };
+
+        let clean = s.trim();
+        if self.missing.matching(clean).is_some() {
+            return Ok(None);
+        }
+
+        let (clean, has_sign) = match clean.strip_suffix('%') {
+            Some(rest) => (rest.trim_end(), true),
+            None => (clean, false),
+        };
+        let value = normalize_number(clean, self.thousands, self.decimal)
+            .map_err(fail)?
+            .parse::<Decimal>()
+            .map_err(|_| fail(MoneyErrorReason::Overflow))?;
+
+        let is_percent = match mode {
+            _ if has_sign => true,
+            PercentMode::Auto => value.abs() > Decimal::ONE,
+            PercentMode::Percent => true,
+            PercentMode::Fraction => false,
+        };
+        let fraction = if is_percent {
+            value / Decimal::ONE_HUNDRED
+        } else {
+            value
+        };
+
+        if fraction > Decimal::ONE {
+            return Err(fail(MoneyErrorReason::AboveHundredPercent));
+        }
+        Ok(Some(fraction))
+    }
+
+    /// Parses an integer quantity such as Units Sold. Thousands separators and
+    /// currency symbols are tolerated. A decimal part within
+    /// `rules.fraction_tolerance` is dropped, so with 0.5 the exports'
+    /// "1,630.50" is 1630, or rounded up when that's nearer ("1.999" is 2);
+    /// anything further from a whole number, by default any fraction, is an
+    /// error.
+    pub fn parse_count(&self, s: &str, rules: CountRules) -> Result<Option<i64>, MoneyParseError> {
+        let fail = |reason| MoneyParseError {
+            input: s.trim().to_string(),
+            reason,
+        };
+
+        let Some(value) = self.parse(s)? else {
+            return Ok(None);
+        };
+        if value.is_sign_negative() && !value.is_zero() && !rules.allow_negative {
+            return Err(fail(MoneyErrorReason::Negative));
+        }
+
+        let whole = [value.trunc(), value.round()]
+            .into_iter()
+            .find(|whole| (value - whole).abs() <= rules.fraction_tolerance)
+            .ok_or_else(|| fail(MoneyErrorReason::Fractional))?;
+        let count = whole
+            .to_i64()
+            .ok_or_else(|| fail(MoneyErrorReason::Overflow))?;
+        if let Some(max) = rules.max
+            && count.unsigned_abs() > max.unsigned_abs()
+        {
+            return Err(fail(MoneyErrorReason::AboveCeiling(Decimal::from(max))));
+        }
+        Ok(Some(count))
+    }
+}
+
+/// Parses a money cell with the default [`MoneyParser`].
+pub fn parse_money(s: &str) -> Result<Option<Money>, MoneyParseError> {
+    MoneyParser::default().parse(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(s: &str) -> Result<Option<Money>, MoneyErrorReason> {
+        parse_money(s).map_err(|e| e.reason)
+    }
+
+    #[test]
+    fn continental_numbers() {
+        let parser = MoneyParser::new().with_number_format(NumberFormat::Continental);
+        assert_eq!(parser.parse("1.234,56"), Ok(Some(Money::new(123456, 2))));
+        assert_eq!(
+            parser.parse("€ 1.234.567,8"),
+            Ok(Some(Money::new(12345678, 1)))
+        );
+        let wrong = MoneyParser::new()
+            .parse("1.234.567,89")
+            .map_err(|e| e.reason);
+        assert_eq!(wrong, Err(MoneyErrorReason::MultipleDecimalPoints));
+    }
+
+    #[test]
+    fn parentheses_are_negative() {
+        assert_eq!(money("(123.45)"), Ok(Some(Money::new(-12345, 2))));
+        assert_eq!(money("$(123.45)"), Ok(Some(Money::new(-12345, 2))));
+        assert_eq!(money("£(1,000)"), Ok(Some(Money::from(-1000))));
+        assert_eq!(money(" $(3,241.50) "), Ok(Some(Money::new(-324150, 2))));
+        assert_eq!(
+            money("123.45)"),
+            Err(MoneyErrorReason::UnbalancedParentheses)
+        );
+        assert_eq!(
+            money("(123.45"),
+            Err(MoneyErrorReason::UnbalancedParentheses)
+        );
+        assert_eq!(money("()"), Err(MoneyErrorReason::Empty));
+    }
+
+    #[test]
+    fn a_trailing_minus_is_negative() {
+        assert_eq!(money("4,250.00-"), Ok(Some(Money::new(-425000, 2))));
+        assert_eq!(money(" 123.45 - "), Ok(Some(Money::new(-12345, 2))));
+        assert_eq!(money("$5-"), Ok(Some(Money::from(-5))));
+        assert_eq!(money("-123-"), Err(MoneyErrorReason::AmbiguousSign));
+        assert_eq!(money("(-123)"), Err(MoneyErrorReason::AmbiguousSign));
+        assert_eq!(money("(123-)"), Err(MoneyErrorReason::AmbiguousSign));
+        assert_eq!(
+            money("(123)-"),
+            Err(MoneyErrorReason::UnbalancedParentheses)
+        );
+    }
+
+    #[test]
+    fn nan_is_missing_and_infinity_a_bad_value() {
+        assert_eq!(money("NaN"), Ok(None));
+        assert_eq!(money("inf"), Err(MoneyErrorReason::NotFinite));
+        assert_eq!(money("-Infinity"), Err(MoneyErrorReason::NotFinite));
+        assert_eq!(money("£inf"), Err(MoneyErrorReason::NotFinite));
+        let unmarked = MoneyParser::new()
+            .with_null_sentinels(&["null"])
+            .parse("nan")
+            .map_err(|e| e.reason);
+        assert_eq!(unmarked, Err(MoneyErrorReason::NotFinite));
+    }
+
+    #[test]
+    fn currency_symbols_only_at_either_end() {
+        assert_eq!(money("$1,234"), Ok(Some(Money::from(1234))));
+        assert_eq!(money("1,234 €"), Ok(Some(Money::from(1234))));
+        assert_eq!(money("-$5"), Ok(Some(Money::from(-5))));
+        assert_eq!(money("$(5)"), Ok(Some(Money::from(-5))));
+        assert_eq!(money("(5£)"), Ok(Some(Money::from(-5))));
+        assert_eq!(money("1$234"), Err(MoneyErrorReason::UnexpectedChar('$')));
+        assert_eq!(money("12£3.50"), Err(MoneyErrorReason::UnexpectedChar('£')));
+        assert_eq!(money("$£5"), Err(MoneyErrorReason::MixedCurrency));
+    }
+
+    #[test]
+    fn dash_is_missing_unless_read_as_zero() {
+        assert_eq!(money("-"), Ok(None));
+        assert_eq!(MoneyParser::new().parse_with_currency("£-"), Ok(None));
+        assert_eq!(money("N/A"), Ok(None));
+        assert_eq!(money("—"), Ok(None));
+        assert!(MissingValues::default().is_missing("-"));
+
+        let zero = ParsedMoney {
+            amount: Money::ZERO,
+            currency: Some(Currency::Gbp),
+        };
+        let parser = MoneyParser::new().with_dash_as_zero(true);
+        assert_eq!(parser.parse_with_currency("£-"), Ok(Some(zero)));
+        assert_eq!(
+            parser.parse_with_currency(" $ - "),
+            Ok(Some(ParsedMoney {
+                currency: Some(Currency::Usd),
+                ..zero
+            }))
+        );
+        assert_eq!(parser.parse("-"), Ok(Some(Money::ZERO)));
+        assert_eq!(parser.parse("—"), Ok(None));
+    }
+
+    #[test]
+    fn counts_drop_a_tolerated_fraction() {
+        let parser = MoneyParser::new();
+        let count = |s, rules| parser.parse_count(s, rules).map_err(|e| e.reason);
+        let rules = CountRules::default();
+        assert_eq!(count("$1,630.50", rules), Err(MoneyErrorReason::Fractional));
+        assert_eq!(count("1,618", rules), Ok(Some(1618)));
+        assert_eq!(count("1,618.00", rules), Ok(Some(1618)));
+        assert_eq!(count("1,523.75", rules), Err(MoneyErrorReason::Fractional));
+        assert_eq!(count("-3", rules), Err(MoneyErrorReason::Negative));
+        let half = CountRules {
+            fraction_tolerance: Decimal::new(5, 1),
+            ..rules
+        };
+        assert_eq!(count("$1,630.50", half), Ok(Some(1630)));
+        assert_eq!(count("1,523.75", half), Ok(Some(1524)));
+        let close = CountRules {
+            fraction_tolerance: Decimal::new(1, 2),
+            ..rules
+        };
+        assert_eq!(count("1.999", close), Ok(Some(2)));
+        assert_eq!(count("12.004", close), Ok(Some(12)));
+        assert_eq!(count("1,523.75", close), Err(MoneyErrorReason::Fractional));
+    }
+}
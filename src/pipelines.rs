@@ -0,0 +1,73 @@
+//! One submodule per export, each behind the cargo feature of the same
+//! name, all on by default.
+
+use crate::clean::{CleanPipeline, CleanRow, CleanRows};
+use crate::error::DataAnalysisError;
+use crate::summary::RunSummary;
+use std::marker::PhantomData;
+use std::path::Path;
+
+#[cfg(feature = "dashboard")]
+mod dashboard;
+#[cfg(feature = "forecasting")]
+mod forecasting;
+#[cfg(feature = "timeseries")]
+mod timeseries;
+
+#[cfg(feature = "dashboard")]
+pub use dashboard::{DASHBOARD_HEADERS, DashboardReader, clean_dashboard, dashboard};
+#[cfg(feature = "forecasting")]
+pub use forecasting::{FORECAST_HEADERS, ForecastReader, clean_forecasting, forecasting};
+#[cfg(feature = "timeseries")]
+pub use timeseries::{TIMESERIES_HEADERS, TimeseriesReader, clean_timeseries, timeseries};
+
+/// An export's rows as `T`, parsed and skipped by the same rules as its
+/// clean function but given one at a time instead of written out:
+///
+/// ```no_run
+/// # #[cfg(feature = "dashboard")]
+/// # fn run(options: &data_analysis::fields::ParseOptions) -> Result<(), data_analysis::error::DataAnalysisError> {
+/// # use data_analysis::pipelines::DashboardReader;
+/// for row in DashboardReader::from_path(options, "Data/Part_B_Dashboard_file.csv")? {
+///     println!("{}", row?.country);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Outliers aren't dropped and forecasting rows aren't sorted, as that
+/// takes every row.
+pub struct RowReader<'a, T> {
+    rows: CleanRows<'a>,
+    row: PhantomData<T>,
+}
+
+impl<T> RowReader<'_, T> {
+    /// What's been read and skipped so far, complete once the rows run out.
+    pub fn summary(&self) -> &RunSummary {
+        self.rows.summary()
+    }
+}
+
+impl<'a, T> RowReader<'a, T> {
+    /// The rows of any pipeline, such as one built with
+    /// [`CleanPipeline::new`], from the file at `input_path`.
+    pub fn open(
+        pipeline: CleanPipeline<'a>,
+        input_path: impl AsRef<Path>,
+    ) -> Result<Self, DataAnalysisError> {
+        Ok(RowReader {
+            rows: pipeline.rows_from_path(input_path)?,
+            row: PhantomData,
+        })
+    }
+}
+
+impl<T: TryFrom<CleanRow, Error = DataAnalysisError>> Iterator for RowReader<'_, T> {
+    type Item = Result<T, DataAnalysisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.rows.next()?;
+        Some(row.and_then(|row| T::try_from(row)))
+    }
+}
@@ -0,0 +1,139 @@
+use super::RowReader;
+use crate::clean::{CleanPipeline, Column, Step};
+use crate::error::DataAnalysisError;
+use crate::fields::ParseOptions;
+use crate::outliers::OutlierRule;
+use crate::output::OutputOptions;
+use crate::rows::DashboardRow;
+use crate::summary::RunSummary;
+use std::path::Path;
+
+/// The dashboard export's header.
+pub const DASHBOARD_HEADERS: &[&str] = &[
+    "Segment",
+    "Country",
+    "Product",
+    "Discount Band",
+    "Units Sold",
+    "Manufacturing Price",
+    "Sale Price",
+    "Budget",
+    "Discounts",
+    "Sales",
+    "COGS",
+    "Profit",
+    "Date",
+];
+
+/// Part B: cleans the dashboard export at `input_path` into `output_path`,
+/// dropping rows that `outliers` finds out of bounds.
+pub fn clean_dashboard(
+    options: &ParseOptions,
+    output: &OutputOptions,
+    outliers: OutlierRule,
+    input_path: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+) -> Result<RunSummary, DataAnalysisError> {
+    dashboard(options)
+        .step(Step::Outliers(outliers))
+        .run(output, input_path, output_path)
+}
+
+/// The dashboard export's columns and checks, without the outlier step.
+pub fn dashboard(options: &ParseOptions) -> CleanPipeline<'_> {
+    CleanPipeline::new("dashboard", options)
+        .schema(DASHBOARD_HEADERS)
+        .column(Column::text("country", "Country"))
+        .column(Column::text("product", "Product"))
+        .column(Column::count("units_sold", "Units Sold"))
+        .column(
+            Column::price("manufacturing_price", "Manufacturing Price")
+                .output("Manufacturing Price Parsed"),
+        )
+        .column(Column::price("sale_price", "Sale Price").output("Sale Price Parsed"))
+        .with_currency()
+}
+
+pub type DashboardReader<'a> = RowReader<'a, DashboardRow>;
+
+impl<'a> DashboardReader<'a> {
+    pub fn from_path(
+        options: &'a ParseOptions,
+        input_path: impl AsRef<Path>,
+    ) -> Result<Self, DataAnalysisError> {
+        RowReader::open(dashboard(options), input_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outliers::OutlierMethod;
+    use crate::output::DateColumns;
+    use crate::parsing::{Money, MoneyPrecision};
+    use crate::summary::DropReason;
+
+    const IQR: OutlierRule = OutlierRule {
+        method: OutlierMethod::Iqr,
+        field: "sale_price",
+        multiplier: Money::from_parts(15, 0, 0, false, 1),
+    };
+
+    const HEADER: &str = "Segment,Country,Product,Discount Band,Units Sold,Manufacturing Price,\
+                          Sale Price,Budget,Discounts,Sales,COGS,Profit,Date\n";
+
+    fn clean(rows: &str) -> (RunSummary, String) {
+        let options = ParseOptions::default();
+        let output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        let mut cleaned = Vec::new();
+        let summary = dashboard(&options)
+            .step(Step::Outliers(IQR))
+            .run_on(
+                &output,
+                format!("{}{}", HEADER, rows).as_bytes(),
+                &mut cleaned,
+            )
+            .unwrap();
+        (summary, String::from_utf8(cleaned).unwrap())
+    }
+
+    #[test]
+    fn no_rows_left_for_the_outliers_is_an_empty_file() {
+        let (summary, cleaned) = clean(
+            "Midmarket,France,Paseo,None,10,£5.00,£15.00,£150.00,£0.00,£150.00,£50.00,£100.00,2020/13/45\n\
+             Midmarket,Mexico,Paseo,None,20,£5.00,£15.00,£300.00,£0.00,£300.00,£100.00,£200.00,yesterday\n",
+        );
+        assert_eq!(summary.rows_read, 2);
+        assert_eq!(summary.rows_written, 0);
+        assert_eq!(summary.dropped_for(DropReason::BadDate), 2);
+        assert_eq!(
+            cleaned,
+            "Country,Product,Units Sold,Manufacturing Price Parsed,Sale Price Parsed,Date_ISO,Currency\n"
+        );
+    }
+
+    #[test]
+    fn a_nan_sale_price_drops_the_row_before_the_outliers() {
+        let row = |country, price| {
+            format!(
+                "Midmarket,{},Paseo,None,10,£5.00,{},£150.00,£0.00,£150.00,£50.00,£100.00,13/02/2020\n",
+                country, price
+            )
+        };
+        let rows: String = [
+            row("France", "NaN"),
+            row("Mexico", "inf"),
+            row("Canada", "£15.00"),
+            row("Germany", "£20.00"),
+            row("France", "£12.00"),
+            row("Mexico", "£7.00"),
+        ]
+        .concat();
+        let (summary, cleaned) = clean(&rows);
+        assert_eq!(summary.rows_written, 4);
+        // Missing or not finite, both are bad numbers rather than outliers
+        assert_eq!(summary.dropped_for(DropReason::BadNumber), 2);
+        assert_eq!(summary.dropped_for(DropReason::Outlier), 0);
+        assert!(!cleaned.contains("NaN") && !cleaned.contains("inf"));
+    }
+}
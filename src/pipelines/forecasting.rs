@@ -0,0 +1,82 @@
+use super::RowReader;
+use crate::clean::{CleanPipeline, Column, Step};
+use crate::error::DataAnalysisError;
+use crate::fields::ParseOptions;
+use crate::output::{MovingAverage, OutputOptions};
+use crate::rows::ForecastRow;
+use crate::summary::RunSummary;
+use std::path::Path;
+
+/// The forecasting export's header.
+pub const FORECAST_HEADERS: &[&str] = &[
+    "Segment",
+    "Country",
+    "Product",
+    "Discount Band",
+    "Units Sold",
+    "Procurement",
+    "Manufacturing Price",
+    "Sale Price",
+    "Budget",
+    "Discounts",
+    "Sales",
+    "COGS",
+    "Date",
+];
+
+/// Part D: cleans the forecasting export at `input_path` into `output_path`,
+/// sorted by date with a sales moving average.
+pub fn clean_forecasting(
+    options: &ParseOptions,
+    output: &OutputOptions,
+    moving_average: MovingAverage,
+    input_path: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+) -> Result<RunSummary, DataAnalysisError> {
+    forecasting(options)
+        .step(Step::SortByDate)
+        .step(Step::MovingAverage {
+            average: moving_average,
+            field: "sales",
+        })
+        .run(output, input_path, output_path)
+}
+
+/// The forecasting export's columns and checks, without the sorting and
+/// moving average steps.
+pub fn forecasting(options: &ParseOptions) -> CleanPipeline<'_> {
+    CleanPipeline::new("forecast", options)
+        .schema(FORECAST_HEADERS)
+        .column(Column::text("segment", "Segment"))
+        .column(Column::text("country", "Country"))
+        .column(Column::text("product", "Product"))
+        .column(Column::text("discount_band", "Discount Band"))
+        .column(Column::count("units_sold", "Units Sold"))
+        .column(Column::price("procurement", "Procurement"))
+        .column(
+            Column::price("manufacturing_price", "Manufacturing Price")
+                .output("Manufacturing Price Parsed"),
+        )
+        .column(Column::price("sale_price", "Sale Price").output("Sale Price Parsed"))
+        .column(Column::total("budget", "Budget").output("Budget Parsed"))
+        .column(Column::total("discounts", "Discounts").output("Discounts Parsed"))
+        // Averaged once sorted, where a made-up zero would drag it down
+        .column(
+            Column::total("sales", "Sales")
+                .output("Sales Parsed")
+                .never_zero(),
+        )
+        .column(Column::total("cogs", "COGS").output("COGS Parsed"))
+        .date_first()
+}
+
+pub type ForecastReader<'a> = RowReader<'a, ForecastRow>;
+
+impl<'a> ForecastReader<'a> {
+    pub fn from_path(
+        options: &'a ParseOptions,
+        input_path: impl AsRef<Path>,
+    ) -> Result<Self, DataAnalysisError> {
+        RowReader::open(forecasting(options), input_path)
+    }
+}
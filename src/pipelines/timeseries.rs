@@ -0,0 +1,196 @@
+use super::RowReader;
+use crate::clean::{CleanPipeline, Column, FieldKind};
+use crate::error::DataAnalysisError;
+use crate::fields::ParseOptions;
+use crate::output::OutputOptions;
+use crate::parsing::PercentMode;
+use crate::rows::TimeseriesRow;
+use crate::summary::RunSummary;
+use std::path::Path;
+
+/// The timeseries export's header.
+pub const TIMESERIES_HEADERS: &[&str] = &[
+    "Segment",
+    "Country",
+    "Product",
+    "Discount Band",
+    "Units Sold",
+    "Manufacturing Price",
+    "Sale Price",
+    "Budget",
+    "Discounts",
+    "Sales",
+    "COGS",
+    "Profit",
+    "Date",
+];
+
+/// Part C: cleans the timeseries export at `input_path` into `output_path`.
+pub fn clean_timeseries(
+    options: &ParseOptions,
+    percent_mode: PercentMode,
+    output: &OutputOptions,
+    input_path: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+) -> Result<RunSummary, DataAnalysisError> {
+    timeseries(options, percent_mode).run(output, input_path, output_path)
+}
+
+/// The timeseries export's columns and checks.
+pub fn timeseries(options: &ParseOptions, percent_mode: PercentMode) -> CleanPipeline<'_> {
+    CleanPipeline::new("timeseries", options)
+        .schema(TIMESERIES_HEADERS)
+        .column(Column::text("segment", "Segment"))
+        .column(Column::text("country", "Country"))
+        .column(Column::text("product", "Product"))
+        .column(Column::text("discount_band", "Discount Band"))
+        .column(Column::count("units_sold", "Units Sold"))
+        .column(
+            Column::price("manufacturing_price", "Manufacturing Price")
+                .output("Manufacturing Price Parsed"),
+        )
+        .column(Column::price("sale_price", "Sale Price").output("Sale Price Parsed"))
+        .column(Column::total("budget", "Budget").output("Budget Parsed"))
+        .column(Column::total("discounts", "Discounts").output("Discounts Parsed"))
+        .column(Column::total("sales", "Sales").output("Sales Parsed"))
+        .column(Column::total("cogs", "COGS").output("COGS Parsed"))
+        .column(Column::total("profit", "Profit").output("Profit Parsed"))
+        // Only some exports carry a discount percentage
+        .column(
+            Column::new(
+                "discount_percent",
+                "Discount %",
+                FieldKind::Percent(percent_mode),
+            )
+            .output("Discount Percent Parsed")
+            .optional(),
+        )
+        .with_currency()
+}
+
+pub type TimeseriesReader<'a> = RowReader<'a, TimeseriesRow>;
+
+impl<'a> TimeseriesReader<'a> {
+    pub fn from_path(
+        options: &'a ParseOptions,
+        input_path: impl AsRef<Path>,
+    ) -> Result<Self, DataAnalysisError> {
+        RowReader::open(timeseries(options, PercentMode::default()), input_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::DateColumns;
+    use crate::parsing::MoneyPrecision;
+    use crate::summary::DropReason;
+    use std::io;
+
+    const HEADER: &str = "Segment,Country,Product,Discount Band,Units Sold,Manufacturing Price,\
+                          Sale Price,Budget,Discounts,Sales,COGS,Profit,Date\n";
+
+    fn clean(rows: &str) -> (RunSummary, String) {
+        let options = ParseOptions::default();
+        let output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        let mut cleaned = Vec::new();
+        let summary = timeseries(&options, PercentMode::default())
+            .run_on(
+                &output,
+                format!("{}{}", HEADER, rows).as_bytes(),
+                &mut cleaned,
+            )
+            .unwrap();
+        (summary, String::from_utf8(cleaned).unwrap())
+    }
+
+    // An export that breaks off after `rows`, which a run has to read
+    // through before it can fail
+    fn cut_off(rows: &str) -> impl io::Read + use<> {
+        struct CutOff;
+        impl io::Read for CutOff {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("cut off"))
+            }
+        }
+        io::Read::chain(io::Cursor::new(format!("{}{}", HEADER, rows)), CutOff)
+    }
+
+    fn good_rows(count: usize) -> String {
+        "Midmarket,France,Paseo,None,10,£5.00,£15.00,£150.00,£0.00,£150.00,£50.00,£100.00,13/02/2020\n"
+            .repeat(count)
+    }
+
+    #[test]
+    fn rows_are_read_as_they_are_asked_for() {
+        let options = ParseOptions::default();
+        let mut rows = timeseries(&options, PercentMode::default())
+            .rows(cut_off(&good_rows(150)))
+            .unwrap();
+        assert_eq!(rows.next().unwrap().unwrap().line, 2);
+        assert_eq!(rows.by_ref().take(149).filter(Result::is_ok).count(), 149);
+        assert_eq!(rows.summary().rows_read, 150);
+        assert!(
+            rows.next()
+                .unwrap()
+                .unwrap_err()
+                .to_string()
+                .contains("cut off")
+        );
+    }
+
+    #[test]
+    fn max_errors_stops_before_the_rest_is_read() {
+        let options = ParseOptions {
+            max_errors: Some(1),
+            ..ParseOptions::default()
+        };
+        let output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        let rows = format!(
+            "{}Midmarket,France,Paseo,None,10,£5.00,£15.00,£150.00,£x,£150.00,£50.00,£100.00,14/02/2020\n{}",
+            good_rows(1),
+            good_rows(200)
+        );
+        let error = timeseries(&options, PercentMode::default())
+            .run_on(&output, cut_off(&rows), io::sink())
+            .unwrap_err();
+        let DataAnalysisError::TooManyErrors(error) = error else {
+            panic!("{}", error);
+        };
+        assert_eq!(error.line, 3);
+        assert_eq!(error.summary.rows_read, 2);
+    }
+
+    #[test]
+    fn dash_totals_are_missing_and_so_zero() {
+        let (summary, cleaned) = clean(
+            "Enterprise,Mexico,Montana,Low,\"$1,138.00\",£5.00,£125.00,\"£142,250.00\",£-,\
+             \"£136,560.00\",\"£136,560.00\",£-,25/11/2018\n",
+        );
+        assert_eq!(summary.rows_written, 1);
+        assert!(summary.drops.is_empty());
+        assert_eq!(summary.missing.get("-"), Some(&2));
+        let row = cleaned.lines().nth(1).unwrap();
+        assert_eq!(
+            row,
+            "Enterprise,Mexico,Montana,Low,1138,5.00,125.00,142250.00,0.00,136560.00,136560.00,0.00,2018-11-25,GBP"
+        );
+    }
+
+    #[test]
+    fn missing_totals_are_zero_but_bad_ones_drop_the_row() {
+        let (summary, cleaned) = clean(
+            "Midmarket,France,Paseo,None,10,£5.00,£15.00,£150.00,,£150.00,£50.00,N/A,13/02/2020\n\
+             Midmarket,France,Paseo,None,10,£5.00,£15.00,£150.00,£x,£150.00,£50.00,£100.00,14/02/2020\n",
+        );
+        assert_eq!(summary.rows_written, 1);
+        assert_eq!(summary.dropped_for(DropReason::BadNumber), 1);
+        assert_eq!(summary.rejected["Discounts"], 1);
+        let row = cleaned.lines().nth(1).unwrap();
+        assert!(
+            row.contains(",150.00,0.00,150.00,50.00,0.00,2020-02-13"),
+            "{}",
+            row
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::parsing::Money;
+use crate::sink::CellKind;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Most common values kept for each text column.
+pub const TOP_VALUES: usize = 10;
+
+/// Bars in a number column's histogram.
+pub const HISTOGRAM_BINS: usize = 10;
+
+/// What the cleaned file holds, column by column, for the --report-md and
+/// --report-html reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub columns: Vec<ColumnProfile>,
+    /// The earliest and latest dates written, `None` for no rows
+    pub dates: Option<(NaiveDate, NaiveDate)>,
+}
+
+impl Profile {
+    pub fn column(&self, name: &str) -> Option<&ColumnProfile> {
+        self.columns
+            .iter()
+            .find(|column| column.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// One cleaned column's blanks and values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnProfile {
+    pub name: String,
+    /// Blank cells, such as the moving average's leading gaps
+    pub nulls: usize,
+    pub distinct: usize,
+    /// The most common values with how many rows have each, most first
+    pub top: Vec<(String, usize)>,
+    /// Set for a number column with any numbers in it
+    pub numbers: Option<NumberProfile>,
+}
+
+/// How a number column's values are spread.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NumberProfile {
+    pub min: Money,
+    pub max: Money,
+    pub mean: Money,
+    /// Values in each of [`HISTOGRAM_BINS`] equal steps from `min` to `max`
+    pub histogram: Vec<usize>,
+}
+
+/// Builds a [`Profile`] from the cleaned rows as they're written.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    names: Vec<String>,
+    kinds: Vec<CellKind>,
+    nulls: Vec<usize>,
+    counts: Vec<BTreeMap<String, usize>>,
+    numbers: Vec<Vec<Money>>,
+    dates: Option<(NaiveDate, NaiveDate)>,
+}
+
+impl Profiler {
+    /// `names` and `kinds` are the cleaned file's header and what each
+    /// column holds.
+    pub fn new(names: &[&str], kinds: &[CellKind]) -> Self {
+        Profiler {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            kinds: kinds.to_vec(),
+            nulls: vec![0; names.len()],
+            counts: vec![BTreeMap::new(); names.len()],
+            numbers: vec![Vec::new(); names.len()],
+            dates: None,
+        }
+    }
+
+    pub fn add(&mut self, cells: &[&str], date: NaiveDate) {
+        for (i, cell) in cells.iter().enumerate().take(self.names.len()) {
+            if cell.is_empty() {
+                self.nulls[i] += 1;
+                continue;
+            }
+            *self.counts[i].entry(cell.to_string()).or_default() += 1;
+            if self.kinds.get(i).is_some_and(|kind| kind.is_number())
+                && let Ok(number) = Money::from_str(cell)
+            {
+                self.numbers[i].push(number);
+            }
+        }
+        self.dates = Some(match self.dates {
+            Some((first, last)) => (first.min(date), last.max(date)),
+            None => (date, date),
+        });
+    }
+
+    pub fn finish(self) -> Profile {
+        let columns = self
+            .names
+            .into_iter()
+            .zip(self.nulls)
+            .zip(self.counts.into_iter().zip(self.numbers))
+            .map(|((name, nulls), (counts, numbers))| {
+                let mut top: Vec<(String, usize)> = counts
+                    .iter()
+                    .map(|(value, count)| (value.clone(), *count))
+                    .collect();
+                // Stable, so ties stay in value order
+                top.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+                top.truncate(TOP_VALUES);
+                ColumnProfile {
+                    name,
+                    nulls,
+                    distinct: counts.len(),
+                    top,
+                    numbers: number_profile(&numbers),
+                }
+            })
+            .collect();
+        Profile {
+            columns,
+            dates: self.dates,
+        }
+    }
+}
+
+fn number_profile(values: &[Money]) -> Option<NumberProfile> {
+    let min = values.iter().min().copied()?;
+    let max = values.iter().max().copied()?;
+    let mean = values.iter().sum::<Money>() / Money::from(values.len());
+    let mut histogram = vec![0; HISTOGRAM_BINS];
+    let width = (max - min) / Money::from(HISTOGRAM_BINS);
+    for value in values {
+        let bin = match width.is_zero() {
+            true => 0,
+            false => usize::try_from(((value - min) / width).floor()).unwrap_or(0),
+        };
+        histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    Some(NumberProfile {
+        min,
+        max,
+        mean: mean.round_dp(2),
+        histogram,
+    })
+}
@@ -0,0 +1,71 @@
+use crate::error::DataAnalysisError;
+use crate::parsing::{Currency, Money, MoneyErrorReason};
+use csv::ReaderBuilder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+/// Exchange rates into one base currency, read from a two-column CSV:
+///
+/// ```text
+/// currency,rate
+/// GBP,1.27
+/// EUR,1.08
+/// ```
+///
+/// where `rate` is the value of one unit in the base currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeRates {
+    pub base: Currency,
+    rates: HashMap<Currency, Money>,
+}
+
+impl ExchangeRates {
+    pub fn load(path: &Path, base: Currency) -> Result<Self, DataAnalysisError> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(File::open(path).map_err(|e| DataAnalysisError::io("read", path, e))?);
+
+        let mut rates = HashMap::new();
+        for result in rdr.records() {
+            let record = result.map_err(DataAnalysisError::from)?;
+            let line = record.position().map(|p| p.line()).unwrap_or(0);
+            let currency: Currency = record
+                .get(0)
+                .unwrap_or("")
+                .parse()
+                .map_err(|e| format!("{} line {}: {}", path.display(), line, e))?;
+            let rate: Money = record.get(1).unwrap_or("").parse().map_err(|_| {
+                format!(
+                    "{} line {}: bad rate for {}",
+                    path.display(),
+                    line,
+                    currency
+                )
+            })?;
+            if rate <= Money::ZERO {
+                return Err(format!(
+                    "{} line {}: rate for {} must be positive",
+                    path.display(),
+                    line,
+                    currency
+                )
+                .into());
+            }
+            rates.insert(currency, rate);
+        }
+        rates.insert(base, Money::ONE);
+
+        Ok(ExchangeRates { base, rates })
+    }
+
+    /// `amount` converted from `currency` into the base currency.
+    pub fn to_base(&self, amount: Money, currency: Currency) -> Result<Money, MoneyErrorReason> {
+        let rate = self
+            .rates
+            .get(&currency)
+            .ok_or(MoneyErrorReason::NoExchangeRate(currency))?;
+        amount.checked_mul(*rate).ok_or(MoneyErrorReason::Overflow)
+    }
+}
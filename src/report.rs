@@ -0,0 +1,242 @@
+use crate::error::DataAnalysisError;
+use crate::summary::{RunReport, RunSummary};
+use std::fmt::Write;
+use std::path::Path;
+
+/// Countries listed in the Markdown report.
+const TOP_COUNTRIES: usize = 5;
+
+/// Columns the HTML report gives a table of most common values.
+const CATEGORIES: [&str; 4] = ["Segment", "Country", "Product", "Discount Band"];
+
+/// Histogram bars, lowest first.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin:0.5em 0 1.5em}\
+th,td{border:1px solid #ccc;padding:0.25em 0.6em;text-align:left}\
+th{background:#f3f3f3}td.n{text-align:right}\
+.bars{font-family:monospace;letter-spacing:1px}.failed{color:#b00}";
+
+/// The --report-md report: a section per run with its rows in and out,
+/// dates, outlier bounds, dropped rows, blanks per column and busiest
+/// countries, as GitHub-flavored Markdown. What a run doesn't have, such as
+/// outlier bounds outside the dashboard, is given as n/a.
+pub fn markdown(runs: &[RunReport]) -> String {
+    let mut md = String::from("# Data quality report\n");
+    for run in runs {
+        // Writing to a String can't fail
+        let _ = markdown_run(&mut md, run);
+    }
+    md
+}
+
+fn markdown_run(md: &mut String, run: &RunReport) -> std::fmt::Result {
+    writeln!(md, "\n## {}\n", run.pipeline)?;
+    writeln!(md, "| | |\n|---|---|")?;
+    writeln!(md, "| Input | {} |", cell(&run.input.display().to_string()))?;
+    writeln!(
+        md,
+        "| Output | {} |",
+        cell(&run.output.display().to_string())
+    )?;
+    if let Some(error) = &run.error {
+        writeln!(md, "| Failed | {} |", cell(error))?;
+    }
+    let Some(summary) = run.summary.as_deref() else {
+        return Ok(());
+    };
+    writeln!(md, "| Rows read | {} |", summary.rows_read)?;
+    writeln!(md, "| Rows written | {} |", summary.rows_written)?;
+    writeln!(md, "| Dropped | {}% |", summary.drop_rate().round_dp(1))?;
+    let dates = summary.profile.as_ref().and_then(|profile| profile.dates);
+    match dates {
+        Some((first, last)) => writeln!(md, "| Dates | {} to {} |", first, last)?,
+        None => writeln!(md, "| Dates | n/a |")?,
+    }
+    match &summary.outliers {
+        Some(outliers) => writeln!(
+            md,
+            "| Outlier bounds | {} {} to {}, {} removed |",
+            cell(&outliers.column),
+            outliers.lower.normalize(),
+            outliers.upper.normalize(),
+            outliers.removed
+        )?,
+        None => writeln!(md, "| Outlier bounds | n/a |")?,
+    }
+
+    if !summary.drops.is_empty() {
+        writeln!(md, "\n### Dropped rows\n")?;
+        writeln!(md, "| Reason | Column | Rows |\n|---|---|---:|")?;
+        for drop in &summary.drops {
+            let column = drop.column.as_deref().unwrap_or("");
+            writeln!(md, "| {} | {} | {} |", drop.reason, cell(column), drop.rows)?;
+        }
+    }
+    markdown_columns(md, summary)
+}
+
+fn markdown_columns(md: &mut String, summary: &RunSummary) -> std::fmt::Result {
+    let Some(profile) = &summary.profile else {
+        return Ok(());
+    };
+    writeln!(md, "\n### Blanks per column\n")?;
+    writeln!(md, "| Column | Blanks |\n|---|---:|")?;
+    for column in &profile.columns {
+        writeln!(md, "| {} | {} |", cell(&column.name), column.nulls)?;
+    }
+
+    writeln!(md, "\n### Top countries\n")?;
+    let Some(countries) = profile.column("Country") else {
+        return writeln!(md, "n/a: no Country column");
+    };
+    writeln!(md, "| Country | Rows |\n|---|---:|")?;
+    for (country, rows) in countries.top.iter().take(TOP_COUNTRIES) {
+        writeln!(md, "| {} | {} |", cell(country), rows)?;
+    }
+    Ok(())
+}
+
+/// The --report-html report: one page with, for each run, its
+/// rows in and out, then every cleaned column's blanks and distinct values,
+/// the number columns' min, mean, max and a text histogram, and the most
+/// common values of the Segment, Country, Product and Discount Band columns.
+/// It needs nothing but itself: the styling is inline.
+pub fn html(runs: &[RunReport]) -> String {
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Data profile</title>\n<style>{}</style>\n</head>\n<body>\n<h1>Data profile</h1>\n",
+        STYLE
+    );
+    for run in runs {
+        // Writing to a String can't fail
+        let _ = html_run(&mut page, run);
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+fn html_run(page: &mut String, run: &RunReport) -> std::fmt::Result {
+    writeln!(page, "<h2>{}</h2>", escape(&run.pipeline))?;
+    writeln!(page, "<table>")?;
+    let input = run.input.display().to_string();
+    let output = run.output.display().to_string();
+    writeln!(page, "<tr><th>Input</th><td>{}</td></tr>", escape(&input))?;
+    writeln!(page, "<tr><th>Output</th><td>{}</td></tr>", escape(&output))?;
+    if let Some(error) = &run.error {
+        writeln!(
+            page,
+            "<tr><th>Failed</th><td class=\"failed\">{}</td></tr>",
+            escape(error)
+        )?;
+    }
+    let Some(summary) = run.summary.as_deref() else {
+        return writeln!(page, "</table>");
+    };
+    writeln!(
+        page,
+        "<tr><th>Rows read</th><td class=\"n\">{}</td></tr>",
+        summary.rows_read
+    )?;
+    writeln!(
+        page,
+        "<tr><th>Rows written</th><td class=\"n\">{}</td></tr>",
+        summary.rows_written
+    )?;
+    writeln!(
+        page,
+        "<tr><th>Dropped</th><td class=\"n\">{}%</td></tr>",
+        summary.drop_rate().round_dp(1)
+    )?;
+    let dates = summary.profile.as_ref().and_then(|profile| profile.dates);
+    if let Some((first, last)) = dates {
+        writeln!(
+            page,
+            "<tr><th>Dates</th><td>{} to {}</td></tr>",
+            first, last
+        )?;
+    }
+    writeln!(page, "</table>")?;
+
+    let Some(profile) = &summary.profile else {
+        return Ok(());
+    };
+    writeln!(page, "<h3>Columns</h3>\n<table>")?;
+    writeln!(
+        page,
+        "<tr><th>Column</th><th>Blanks</th><th>Distinct</th><th>Min</th><th>Mean</th>\
+         <th>Max</th><th>Spread</th></tr>"
+    )?;
+    for column in &profile.columns {
+        write!(
+            page,
+            "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td>",
+            escape(&column.name),
+            column.nulls,
+            column.distinct
+        )?;
+        match &column.numbers {
+            Some(numbers) => writeln!(
+                page,
+                "<td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td>\
+                 <td class=\"bars\">{}</td></tr>",
+                numbers.min.normalize(),
+                numbers.mean.normalize(),
+                numbers.max.normalize(),
+                bars(&numbers.histogram)
+            )?,
+            None => writeln!(page, "<td></td><td></td><td></td><td></td></tr>")?,
+        }
+    }
+    writeln!(page, "</table>")?;
+
+    for name in CATEGORIES {
+        let Some(column) = profile.column(name) else {
+            continue;
+        };
+        writeln!(page, "<h3>{}</h3>\n<table>", escape(&column.name))?;
+        writeln!(page, "<tr><th>Value</th><th>Rows</th></tr>")?;
+        for (value, rows) in &column.top {
+            writeln!(
+                page,
+                "<tr><td>{}</td><td class=\"n\">{}</td></tr>",
+                escape(value),
+                rows
+            )?;
+        }
+        writeln!(page, "</table>")?;
+    }
+    Ok(())
+}
+
+// A histogram as one bar character per bin, scaled to the fullest
+fn bars(histogram: &[usize]) -> String {
+    let most = histogram.iter().copied().max().unwrap_or(0).max(1);
+    histogram
+        .iter()
+        .map(|&count| match count {
+            0 => ' ',
+            _ => BARS[(count * BARS.len() - 1) / most],
+        })
+        .collect()
+}
+
+// Text made safe to put in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes a report made by [`markdown`] or [`html`] to `path`.
+pub fn write_report(path: &Path, report: &str) -> Result<(), DataAnalysisError> {
+    std::fs::write(path, report).map_err(|e| DataAnalysisError::io("write", path, e))?;
+    Ok(())
+}
+
+// A table cell, with pipes and line breaks that would end it escaped
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
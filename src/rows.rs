@@ -0,0 +1,509 @@
+use crate::clean::{CleanRow, Value, reader_builder};
+use crate::dates::{DateParser, parse_date};
+use crate::encoding::Decoder;
+use crate::error::DataAnalysisError;
+use crate::fields::{ParseOptions, RecordView};
+use crate::parsing::{CountRules, Money, MoneyParser, parse_money};
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, Trim};
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer};
+use std::io::Read;
+
+/// A row whose numbers can be got at by field name, such as `sale_price`, so
+/// that steps like the outlier filter and moving average work on any export.
+pub trait Record {
+    /// The field as a number, `None` when the row has no such number.
+    fn number(&self, field: &str) -> Option<Money>;
+    fn date(&self) -> NaiveDate;
+}
+
+impl Record for CleanRow {
+    fn number(&self, field: &str) -> Option<Money> {
+        self.value(field).and_then(Value::number)
+    }
+
+    fn date(&self) -> NaiveDate {
+        self.date
+    }
+}
+
+/// A row of the Part B dashboard export.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DashboardRow {
+    #[serde(rename = "Country")]
+    pub country: String,
+    #[serde(rename = "Product")]
+    pub product: String,
+    #[serde(rename = "Units Sold", deserialize_with = "count")]
+    pub units_sold: i64,
+    #[serde(rename = "Manufacturing Price", deserialize_with = "money")]
+    pub manufacturing_price: Money,
+    #[serde(rename = "Sale Price", deserialize_with = "money")]
+    pub sale_price: Money,
+    #[serde(rename = "Date", deserialize_with = "date")]
+    pub date: NaiveDate,
+}
+
+/// A row of the Part C timeseries export.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TimeseriesRow {
+    #[serde(rename = "Segment")]
+    pub segment: String,
+    #[serde(rename = "Country")]
+    pub country: String,
+    #[serde(rename = "Product")]
+    pub product: String,
+    #[serde(rename = "Discount Band")]
+    pub discount_band: String,
+    #[serde(rename = "Units Sold", deserialize_with = "count")]
+    pub units_sold: i64,
+    #[serde(rename = "Manufacturing Price", deserialize_with = "money")]
+    pub manufacturing_price: Money,
+    #[serde(rename = "Sale Price", deserialize_with = "money")]
+    pub sale_price: Money,
+    #[serde(rename = "Budget", deserialize_with = "money")]
+    pub budget: Money,
+    #[serde(rename = "Discounts", deserialize_with = "money")]
+    pub discounts: Money,
+    #[serde(rename = "Sales", deserialize_with = "money")]
+    pub sales: Money,
+    #[serde(rename = "COGS", deserialize_with = "money")]
+    pub cogs: Money,
+    #[serde(rename = "Profit", deserialize_with = "money")]
+    pub profit: Money,
+    #[serde(rename = "Date", deserialize_with = "date")]
+    pub date: NaiveDate,
+}
+
+/// A row of the Part D forecasting export, which has Procurement but no
+/// Profit.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ForecastRow {
+    #[serde(rename = "Segment")]
+    pub segment: String,
+    #[serde(rename = "Country")]
+    pub country: String,
+    #[serde(rename = "Product")]
+    pub product: String,
+    #[serde(rename = "Discount Band")]
+    pub discount_band: String,
+    #[serde(rename = "Units Sold", deserialize_with = "count")]
+    pub units_sold: i64,
+    #[serde(rename = "Procurement", deserialize_with = "money")]
+    pub procurement: Money,
+    #[serde(rename = "Manufacturing Price", deserialize_with = "money")]
+    pub manufacturing_price: Money,
+    #[serde(rename = "Sale Price", deserialize_with = "money")]
+    pub sale_price: Money,
+    #[serde(rename = "Budget", deserialize_with = "money")]
+    pub budget: Money,
+    #[serde(rename = "Discounts", deserialize_with = "money")]
+    pub discounts: Money,
+    #[serde(rename = "Sales", deserialize_with = "money")]
+    pub sales: Money,
+    #[serde(rename = "COGS", deserialize_with = "money")]
+    pub cogs: Money,
+    #[serde(rename = "Date", deserialize_with = "date")]
+    pub date: NaiveDate,
+}
+
+/// Reads `source` as rows of `T`, such as [`DashboardRow`], with headers and
+/// cells trimmed and the default money and date parsers. Columns `T` doesn't
+/// have are ignored; one it needs that the file doesn't have fails every
+/// row, naming the field, and so does a missing or bad value. The pipelines
+/// don't read this way, as a run's options change how cells are parsed;
+/// for rows read with them see [`read_rows`], and for rows read by the
+/// pipelines' rules [`crate::pipelines::RowReader`].
+pub fn deserialize_rows<T: DeserializeOwned>(
+    source: impl Read,
+    delimiter: u8,
+) -> impl Iterator<Item = Result<T, DataAnalysisError>> {
+    let mut reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .delimiter(delimiter)
+        .from_reader(source);
+    let headers = reader.headers().cloned().unwrap_or_default();
+    reader.into_deserialize().map(move |row| {
+        row.map_err(|e| {
+            // Told the way the pipelines tell of a bad cell. A value the
+            // parsers turn down comes back without its column
+            let csv::ErrorKind::Deserialize { pos, err } = e.kind() else {
+                return DataAnalysisError::from(e);
+            };
+            let line = pos.as_ref().map_or(0, |pos| pos.line());
+            match err.field().and_then(|i| headers.get(i as usize)) {
+                Some(header) => format!("row {}: {} {}", line, header, err.kind()).into(),
+                None => format!("row {}: {}", line, err.kind()).into(),
+            }
+        })
+    })
+}
+
+/// A row type that can be read from one export record by header name.
+pub trait FromRecord: Sized {
+    /// Reads the row's cells from `view`, and its date with `dates`.
+    fn from_record(view: &RecordView, dates: &DateParser) -> Result<Self, DataAnalysisError>;
+}
+
+/// Reads `source` as rows of `T`, such as [`DashboardRow`], by header name
+/// with a run's `options`: its delimiter, encoding and money and date
+/// formats, the date order found from the Date column as a pipeline finds
+/// it. Nothing is defaulted or skipped, so a missing or bad cell fails its
+/// row, naming the line, the column and the value.
+pub fn read_rows<T: FromRecord>(
+    source: impl Read,
+    options: &ParseOptions,
+) -> Result<impl Iterator<Item = Result<T, DataAnalysisError>>, DataAnalysisError> {
+    let mut reader =
+        reader_builder(options.delimiter).from_reader(Decoder::new(source, options.encoding));
+    let headers = reader.headers()?.clone();
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let dates = options.dates.detect(records.iter().map(|record| {
+        let view = RecordView::new(&headers, record, options);
+        (view.line(), view.str("Date").unwrap_or(""))
+    }))?;
+    Ok(records
+        .into_iter()
+        .map(move |record| T::from_record(&RecordView::new(&headers, &record, options), &dates)))
+}
+
+fn money<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_money(&s)
+        .map_err(D::Error::custom)?
+        .ok_or_else(|| D::Error::custom("missing"))
+}
+
+fn count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    MoneyParser::default()
+        .parse_count(&s, CountRules::default())
+        .map_err(D::Error::custom)?
+        .ok_or_else(|| D::Error::custom("missing"))
+}
+
+fn date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_date(&s).map_err(D::Error::custom)
+}
+
+impl TryFrom<CleanRow> for DashboardRow {
+    type Error = DataAnalysisError;
+
+    fn try_from(row: CleanRow) -> Result<Self, Self::Error> {
+        Ok(DashboardRow {
+            country: row.text("country")?,
+            product: row.text("product")?,
+            units_sold: row.count("units_sold")?,
+            manufacturing_price: row.money("manufacturing_price")?,
+            sale_price: row.money("sale_price")?,
+            date: row.date,
+        })
+    }
+}
+
+impl TryFrom<CleanRow> for TimeseriesRow {
+    type Error = DataAnalysisError;
+
+    fn try_from(row: CleanRow) -> Result<Self, Self::Error> {
+        Ok(TimeseriesRow {
+            segment: row.text("segment")?,
+            country: row.text("country")?,
+            product: row.text("product")?,
+            discount_band: row.text("discount_band")?,
+            units_sold: row.count("units_sold")?,
+            manufacturing_price: row.money("manufacturing_price")?,
+            sale_price: row.money("sale_price")?,
+            budget: row.money("budget")?,
+            discounts: row.money("discounts")?,
+            sales: row.money("sales")?,
+            cogs: row.money("cogs")?,
+            profit: row.money("profit")?,
+            date: row.date,
+        })
+    }
+}
+
+impl TryFrom<CleanRow> for ForecastRow {
+    type Error = DataAnalysisError;
+
+    fn try_from(row: CleanRow) -> Result<Self, Self::Error> {
+        Ok(ForecastRow {
+            segment: row.text("segment")?,
+            country: row.text("country")?,
+            product: row.text("product")?,
+            discount_band: row.text("discount_band")?,
+            units_sold: row.count("units_sold")?,
+            procurement: row.money("procurement")?,
+            manufacturing_price: row.money("manufacturing_price")?,
+            sale_price: row.money("sale_price")?,
+            budget: row.money("budget")?,
+            discounts: row.money("discounts")?,
+            sales: row.money("sales")?,
+            cogs: row.money("cogs")?,
+            date: row.date,
+        })
+    }
+}
+
+impl FromRecord for DashboardRow {
+    fn from_record(view: &RecordView, dates: &DateParser) -> Result<Self, DataAnalysisError> {
+        Ok(DashboardRow {
+            country: view.str("Country")?.to_string(),
+            product: view.str("Product")?.to_string(),
+            units_sold: view.count("Units Sold")?,
+            manufacturing_price: view.money("Manufacturing Price")?,
+            sale_price: view.money("Sale Price")?,
+            date: view.date("Date", dates)?,
+        })
+    }
+}
+
+impl FromRecord for TimeseriesRow {
+    fn from_record(view: &RecordView, dates: &DateParser) -> Result<Self, DataAnalysisError> {
+        Ok(TimeseriesRow {
+            segment: view.str("Segment")?.to_string(),
+            country: view.str("Country")?.to_string(),
+            product: view.str("Product")?.to_string(),
+            discount_band: view.str("Discount Band")?.to_string(),
+            units_sold: view.count("Units Sold")?,
+            manufacturing_price: view.money("Manufacturing Price")?,
+            sale_price: view.money("Sale Price")?,
+            budget: view.money("Budget")?,
+            discounts: view.money("Discounts")?,
+            sales: view.money("Sales")?,
+            cogs: view.money("COGS")?,
+            profit: view.money("Profit")?,
+            date: view.date("Date", dates)?,
+        })
+    }
+}
+
+impl FromRecord for ForecastRow {
+    fn from_record(view: &RecordView, dates: &DateParser) -> Result<Self, DataAnalysisError> {
+        Ok(ForecastRow {
+            segment: view.str("Segment")?.to_string(),
+            country: view.str("Country")?.to_string(),
+            product: view.str("Product")?.to_string(),
+            discount_band: view.str("Discount Band")?.to_string(),
+            units_sold: view.count("Units Sold")?,
+            procurement: view.money("Procurement")?,
+            manufacturing_price: view.money("Manufacturing Price")?,
+            sale_price: view.money("Sale Price")?,
+            budget: view.money("Budget")?,
+            discounts: view.money("Discounts")?,
+            sales: view.money("Sales")?,
+            cogs: view.money("COGS")?,
+            date: view.date("Date", dates)?,
+        })
+    }
+}
+
+impl Record for DashboardRow {
+    fn number(&self, field: &str) -> Option<Money> {
+        match field {
+            "units_sold" => Some(Money::from(self.units_sold)),
+            "manufacturing_price" => Some(self.manufacturing_price),
+            "sale_price" => Some(self.sale_price),
+            _ => None,
+        }
+    }
+
+    fn date(&self) -> NaiveDate {
+        self.date
+    }
+}
+
+impl Record for TimeseriesRow {
+    fn number(&self, field: &str) -> Option<Money> {
+        match field {
+            "units_sold" => Some(Money::from(self.units_sold)),
+            "manufacturing_price" => Some(self.manufacturing_price),
+            "sale_price" => Some(self.sale_price),
+            "budget" => Some(self.budget),
+            "discounts" => Some(self.discounts),
+            "sales" => Some(self.sales),
+            "cogs" => Some(self.cogs),
+            "profit" => Some(self.profit),
+            _ => None,
+        }
+    }
+
+    fn date(&self) -> NaiveDate {
+        self.date
+    }
+}
+
+impl Record for ForecastRow {
+    fn number(&self, field: &str) -> Option<Money> {
+        match field {
+            "units_sold" => Some(Money::from(self.units_sold)),
+            "procurement" => Some(self.procurement),
+            "manufacturing_price" => Some(self.manufacturing_price),
+            "sale_price" => Some(self.sale_price),
+            "budget" => Some(self.budget),
+            "discounts" => Some(self.discounts),
+            "sales" => Some(self.sales),
+            "cogs" => Some(self.cogs),
+            _ => None,
+        }
+    }
+
+    fn date(&self) -> NaiveDate {
+        self.date
+    }
+}
+
+// The fixture is the dashboard's
+#[cfg(all(test, feature = "dashboard"))]
+mod tests {
+    use super::*;
+    use crate::fields::ParseOptions;
+    use crate::output::{DateColumns, OutputOptions};
+    use crate::parsing::MoneyPrecision;
+    use crate::pipelines::dashboard;
+
+    // The top of the dashboard export as it comes, blank row and subtotal
+    // included
+    const EXPORT: &str = "\
+Segment,Country, Product , Discount Band , Units Sold , Manufacturing Price , Sale Price , Budget , Discounts ,  Sales , COGS , Profit ,Date
+Government,Canada, Carretera , None , null , null , null , null , null , null ,null,null,null
+Midmarket,Mexico, Carretera , None ,\" $1,618.50 \", £3.00 , £20.00 ,\" £32,370.00 \", £-   ,\" £32,370.00 \",\" £16,185.00 \",\" £16,185.00 \",10/11/2018
+Government,Germany, Carretera , None ,\" $1,321.00 \", £3.00 , £20.00 ,\" £26,420.00 \", £-   ,\" £26,420.00 \",\" £13,210.00 \",\" £13,210.00 \",25/02/2019
+Midmarket,France, Carretera , None ,\" $2,178.00 \", £3.00 , £15.00 ,\" £32,670.00 \", £-   ,\" £32,670.00 \",\" £21,780.00 \",\" £10,890.00 \",01/10/2019
+Midmarket,Germany, Carretera , None , $888.00 , £3.00 , £15.00 ,\" £13,320.00 \", £-   ,\" £13,320.00 \",\" £8,880.00 \",\" £4,440.00 \",21/05/2020
+Subtotal,null,null,null, null , null , null ,\" £1,444,590.50 \", £-   ,\" £1,444,590.50 \",\" £1,113,314.00 \",\" £331,276.50 \",null
+";
+
+    #[test]
+    fn the_cleaned_dashboard_is_unchanged() {
+        let options = ParseOptions::default();
+        let output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        let mut cleaned = Vec::new();
+        dashboard(&options)
+            .run_on(&output, EXPORT.as_bytes(), &mut cleaned)
+            .unwrap();
+        // Mexico's 1,618.50 units aren't a whole number
+        assert_eq!(
+            String::from_utf8(cleaned).unwrap(),
+            "Country,Product,Units Sold,Manufacturing Price Parsed,Sale Price Parsed,Date_ISO,Currency\n\
+             Germany,Carretera,1321,3.00,20.00,2019-02-25,GBP\n\
+             France,Carretera,2178,3.00,15.00,2019-10-01,GBP\n\
+             Germany,Carretera,888,3.00,15.00,2020-05-21,GBP\n"
+        );
+    }
+
+    #[test]
+    fn deserialized_rows_are_the_pipelines_rows() {
+        let options = ParseOptions::default();
+        let cleaned: Vec<DashboardRow> = dashboard(&options)
+            .rows(EXPORT.as_bytes())
+            .unwrap()
+            .map(|row| row.and_then(DashboardRow::try_from))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let deserialized: Vec<DashboardRow> = deserialize_rows(EXPORT.as_bytes(), b',')
+            .filter_map(Result::ok)
+            .collect();
+        assert_eq!(deserialized, cleaned);
+    }
+
+    #[test]
+    fn read_rows_name_the_line_column_and_value_that_failed() {
+        let options = ParseOptions::default();
+        let rows: Vec<Result<DashboardRow, _>> =
+            read_rows(EXPORT.as_bytes(), &options).unwrap().collect();
+        let errors: Vec<String> = rows
+            .iter()
+            .filter_map(|row| row.as_ref().err())
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "row 2: Units Sold missing",
+                "row 3: Units Sold '$1,618.50' - not a whole number",
+                "row 7: Units Sold missing",
+            ]
+        );
+        let cleaned: Vec<DashboardRow> = dashboard(&options)
+            .rows(EXPORT.as_bytes())
+            .unwrap()
+            .map(|row| row.and_then(DashboardRow::try_from))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let read: Vec<DashboardRow> = rows.into_iter().filter_map(Result::ok).collect();
+        assert_eq!(read, cleaned);
+    }
+
+    #[test]
+    fn read_rows_follow_the_runs_options() {
+        let options = ParseOptions {
+            delimiter: b';',
+            ..ParseOptions::default()
+        };
+        let rows: Vec<ForecastRow> = read_rows(
+            "Segment;Country;Product;Discount Band;Units Sold;Procurement;Manufacturing Price;\
+             Sale Price;Budget;Discounts;Sales;COGS;Date\n\
+             Government;France;Paseo;None;10;£1.00;£5.00;£15.00;£150.00;£0.00;£150.00;£50.00;02/25/2020\n"
+                .as_bytes(),
+            &options,
+        )
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(rows[0].sales, Money::new(15000, 2));
+        // Month-first, as 25 can't be a month
+        assert_eq!(rows[0].date, NaiveDate::from_ymd_opt(2020, 2, 25).unwrap());
+    }
+
+    #[test]
+    fn columns_are_bound_by_name_and_extra_ones_ignored() {
+        let rows: Vec<DashboardRow> = deserialize_rows(
+            "Date,Notes,Sale Price,Country,Manufacturing Price,Units Sold,Product\n\
+             25/02/2019,late,£20.00,Germany,£3.00,\"1,321\",Carretera\n"
+                .as_bytes(),
+            b',',
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(
+            rows,
+            vec![DashboardRow {
+                country: "Germany".into(),
+                product: "Carretera".into(),
+                units_sold: 1321,
+                manufacturing_price: Money::new(300, 2),
+                sale_price: Money::new(2000, 2),
+                date: NaiveDate::from_ymd_opt(2019, 2, 25).unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn a_missing_column_fails_naming_it_and_a_blank_one_its_row() {
+        let missing = deserialize_rows::<DashboardRow>(
+            "Country,Product,Units Sold,Manufacturing Price,Date\n\
+             Germany,Carretera,1321,£3.00,25/02/2019\n"
+                .as_bytes(),
+            b',',
+        )
+        .next()
+        .unwrap()
+        .unwrap_err();
+        assert!(missing.to_string().contains("Sale Price"), "{}", missing);
+
+        let blank = deserialize_rows::<DashboardRow>(
+            "Country,Product,Units Sold,Manufacturing Price,Sale Price,Date\n\
+             Germany,Carretera,1321,£3.00,,25/02/2019\n"
+                .as_bytes(),
+            b',',
+        )
+        .next()
+        .unwrap()
+        .unwrap_err();
+        // csv doesn't say which field a parser turned down
+        assert_eq!(blank.to_string(), "row 2: missing");
+    }
+}
@@ -0,0 +1,427 @@
+use crate::error::DataAnalysisError;
+use crate::parsing::Money;
+use csv::{Writer, WriterBuilder};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What a cleaned column holds, for sinks that write numbers differently
+/// from text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellKind {
+    Text,
+    /// A price, a total or an average of one
+    Money,
+    /// Any other decimal, such as a percentage
+    Number,
+    /// A whole number, such as Units Sold or an ISO week
+    Integer,
+    /// The date column, written as --output-date-format has it
+    Date,
+}
+
+impl CellKind {
+    /// Written as a number by sinks that have them.
+    pub fn is_number(self) -> bool {
+        matches!(self, CellKind::Money | CellKind::Number | CellKind::Integer)
+    }
+}
+
+/// Where a pipeline writes its cleaned rows: a header, then each row with a
+/// cell per header, then `finish` once they're all there.
+pub trait RowSink {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError>;
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError>;
+    /// Flushes whatever is still buffered.
+    fn finish(&mut self) -> Result<(), DataAnalysisError>;
+    /// What each column holds, given before the header. CSV has no use for it.
+    fn column_kinds(&mut self, _kinds: &[CellKind]) {}
+}
+
+impl<S: RowSink + ?Sized> RowSink for Box<S> {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        (**self).write_header(header)
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        (**self).write_row(cells)
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        (**self).finish()
+    }
+
+    fn column_kinds(&mut self, kinds: &[CellKind]) {
+        (**self).column_kinds(kinds)
+    }
+}
+
+/// Writes rows as CSV.
+pub struct CsvSink<W: Write> {
+    writer: Writer<W>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W, delimiter: u8) -> Self {
+        CsvSink::from_writer(
+            WriterBuilder::new()
+                .delimiter(delimiter)
+                .from_writer(writer),
+        )
+    }
+
+    pub fn from_writer(writer: Writer<W>) -> Self {
+        CsvSink { writer }
+    }
+}
+
+impl<W: Write> RowSink for CsvSink<W> {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        self.write_row(header)
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        Ok(self.writer.write_record(cells)?)
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Writes rows as JSON Lines, an object per row keyed by the header in
+/// snake_case. Number columns are JSON numbers, written as they would be in
+/// the CSV, and a blank cell is null.
+pub struct JsonlSink<W: Write> {
+    writer: BufWriter<W>,
+    keys: Vec<String>,
+    kinds: Vec<CellKind>,
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonlSink {
+            writer: BufWriter::new(writer),
+            keys: Vec::new(),
+            kinds: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> RowSink for JsonlSink<W> {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        self.keys = header
+            .iter()
+            .map(|h| serde_json::to_string(&snake_case(h)))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        let mut line = String::from("{");
+        for (i, (key, cell)) in self.keys.iter().zip(cells).enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            line.push_str(key);
+            line.push(':');
+            let number = match self.kinds.get(i) {
+                Some(kind) if kind.is_number() => Money::from_str(cell).ok(),
+                _ => None,
+            };
+            match number {
+                _ if cell.is_empty() => line.push_str("null"),
+                Some(number) => line.push_str(&number.to_string()),
+                None => line.push_str(&serde_json::to_string(cell)?),
+            }
+        }
+        line.push('}');
+        writeln!(self.writer, "{}", line)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        Ok(self.writer.flush()?)
+    }
+
+    fn column_kinds(&mut self, kinds: &[CellKind]) {
+        self.kinds = kinds.to_vec();
+    }
+}
+
+/// "Sales_MA_3" as sales_ma_3 and "Manufacturing Price Parsed" as
+/// manufacturing_price_parsed.
+pub fn snake_case(header: &str) -> String {
+    let words: Vec<String> = header
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.join("_")
+}
+
+/// Writes rows to `path` through another sink, by way of a ".part" file
+/// beside it that `finish` moves into place once everything is flushed.
+/// Until then `path` is left as it was, and a sink dropped before `finish`,
+/// as when a run fails or panics partway, removes its part file.
+pub struct FileSink {
+    sink: Option<Box<dyn RowSink>>,
+    /// The part file again, to sync once the sink is done with it
+    file: Option<File>,
+    part: PathBuf,
+    path: PathBuf,
+}
+
+impl FileSink {
+    /// Writes CSV.
+    pub fn create(path: &Path, builder: &WriterBuilder) -> Result<Self, DataAnalysisError> {
+        FileSink::create_with(path, |file| {
+            Box::new(CsvSink::from_writer(builder.from_writer(file)))
+        })
+    }
+
+    /// Writes with the sink `sink` makes over the part file.
+    pub fn create_with(
+        path: &Path,
+        sink: impl FnOnce(File) -> Box<dyn RowSink>,
+    ) -> Result<Self, DataAnalysisError> {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".part");
+        let part = path.with_file_name(name);
+        let created = File::create(&part).and_then(|file| Ok((file.try_clone()?, file)));
+        let (file, writer) = created.map_err(|e| DataAnalysisError::io("write", &part, e))?;
+        Ok(FileSink {
+            sink: Some(sink(writer)),
+            file: Some(file),
+            part,
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn sink(&mut self) -> Result<&mut Box<dyn RowSink>, DataAnalysisError> {
+        self.sink
+            .as_mut()
+            .ok_or_else(|| format!("{} is already finished", self.path.display()).into())
+    }
+}
+
+impl RowSink for FileSink {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        self.sink()?.write_header(header)
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        self.sink()?.write_row(cells)
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        let Some(mut sink) = self.sink.take() else {
+            return Ok(());
+        };
+        sink.finish()?;
+        drop(sink);
+        if let Some(file) = self.file.take() {
+            file.sync_all()
+                .map_err(|e| DataAnalysisError::io("write", &self.part, e))?;
+        }
+        std::fs::rename(&self.part, &self.path)
+            .map_err(|e| DataAnalysisError::io("write", &self.path, e))?;
+        Ok(())
+    }
+
+    fn column_kinds(&mut self, kinds: &[CellKind]) {
+        if let Some(sink) = &mut self.sink {
+            sink.column_kinds(kinds);
+        }
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        // Closed first, as Windows won't remove an open file
+        drop(self.sink.take());
+        drop(self.file.take());
+        if self.part.exists() {
+            let _ = std::fs::remove_file(&self.part);
+        }
+    }
+}
+
+/// Writes each row to several sinks, named for their errors. One that fails
+/// is dropped, so a [`FileSink`] removes its part file, and the rest carry
+/// on; `finish` then fails naming each that didn't make it.
+pub struct FanOutSink {
+    sinks: Vec<(String, Box<dyn RowSink>)>,
+    total: usize,
+    failed: Vec<String>,
+}
+
+impl FanOutSink {
+    pub fn new(sinks: Vec<(String, Box<dyn RowSink>)>) -> Self {
+        FanOutSink {
+            total: sinks.len(),
+            sinks,
+            failed: Vec::new(),
+        }
+    }
+
+    fn each(
+        &mut self,
+        mut write: impl FnMut(&mut Box<dyn RowSink>) -> Result<(), DataAnalysisError>,
+    ) -> Result<(), DataAnalysisError> {
+        let failed = &mut self.failed;
+        self.sinks.retain_mut(|(name, sink)| match write(sink) {
+            Ok(()) => true,
+            Err(e) => {
+                failed.push(format!("{}: {}", name, e));
+                false
+            }
+        });
+        match self.sinks.is_empty() {
+            true => Err(self.failures().into()),
+            false => Ok(()),
+        }
+    }
+
+    fn failures(&self) -> String {
+        format!(
+            "{} of {} outputs failed: {}",
+            self.failed.len(),
+            self.total,
+            self.failed.join("; ")
+        )
+    }
+}
+
+impl RowSink for FanOutSink {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        self.each(|sink| sink.write_header(header))
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        self.each(|sink| sink.write_row(cells))
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        self.each(|sink| sink.finish())?;
+        match self.failed.is_empty() {
+            true => Ok(()),
+            false => Err(self.failures().into()),
+        }
+    }
+
+    fn column_kinds(&mut self, kinds: &[CellKind]) {
+        for (_, sink) in &mut self.sinks {
+            sink.column_kinds(kinds);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::{CleanPipeline, Column};
+    use crate::fields::{ParseMode, ParseOptions};
+    use crate::output::{DateColumns, OutputOptions};
+    use crate::parsing::MoneyPrecision;
+
+    fn cleaned(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("data_analysis_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn part(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".part");
+        PathBuf::from(name)
+    }
+
+    #[test]
+    fn finishing_puts_the_file_in_place() {
+        let path = cleaned("finished.csv");
+        let mut sink = FileSink::create(&path, &WriterBuilder::new()).unwrap();
+        sink.write_header(&["Country"]).unwrap();
+        sink.write_row(&["France"]).unwrap();
+        assert!(part(&path).exists() && !path.exists());
+        sink.finish().unwrap();
+        drop(sink);
+        assert!(!part(&path).exists());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "Country\nFrance\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Writes CSV until its `fail_at`th row, which fails as a full disk would
+    struct FailingSink {
+        csv: CsvSink<File>,
+        rows: usize,
+        fail_at: usize,
+    }
+
+    impl RowSink for FailingSink {
+        fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+            self.csv.write_header(header)
+        }
+
+        fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+            self.rows += 1;
+            if self.rows == self.fail_at {
+                return Err("no space left on device".into());
+            }
+            self.csv.write_row(cells)
+        }
+
+        fn finish(&mut self) -> Result<(), DataAnalysisError> {
+            self.csv.finish()
+        }
+    }
+
+    #[test]
+    fn a_sink_failing_partway_leaves_no_file() {
+        let path = cleaned("full_disk.csv");
+        let mut sink = FileSink::create_with(&path, |file| {
+            Box::new(FailingSink {
+                csv: CsvSink::from_writer(WriterBuilder::new().from_writer(file)),
+                rows: 0,
+                fail_at: 5000,
+            })
+        })
+        .unwrap();
+        sink.write_header(&["Country", "Sales"]).unwrap();
+        for _ in 1..5000 {
+            sink.write_row(&["France", "10.00"]).unwrap();
+        }
+        // Past the writer's buffer, so rows are already in the part file
+        assert!(std::fs::metadata(part(&path)).unwrap().len() > 0);
+        let error = sink.write_row(&["France", "10.00"]).unwrap_err();
+        assert!(error.to_string().contains("no space"), "{}", error);
+        drop(sink);
+        assert!(!path.exists());
+        assert!(!part(&path).exists());
+    }
+
+    #[test]
+    fn a_run_failing_before_it_writes_leaves_no_file() {
+        let (input, path) = (cleaned("strict.csv"), cleaned("strict_cleaned.csv"));
+        let rows = "Country,Sales,Date\nFrance,£10.00,13/01/2020\n".to_string()
+            + &"France,£10.00,14/01/2020\n".repeat(5000)
+            + "Mexico,lots,15/01/2020\n";
+        std::fs::write(&input, rows).unwrap();
+        let options = ParseOptions {
+            mode: ParseMode::Strict,
+            ..ParseOptions::default()
+        };
+        let output = OutputOptions::new(MoneyPrecision::default(), DateColumns::default());
+        let error = CleanPipeline::new("sales", &options)
+            .column(Column::text("country", "Country"))
+            .column(Column::total("sales", "Sales"))
+            .run(&output, &input, &path)
+            .unwrap_err();
+        assert!(error.to_string().contains("lots"), "{}", error);
+        assert!(!path.exists());
+        assert!(!part(&path).exists());
+        std::fs::remove_file(&input).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! The cleaned rows as a table in a SQLite database.
+
+use crate::console::info;
+use crate::dates::ISO_DATE_FORMAT;
+use crate::error::DataAnalysisError;
+use crate::sink::{CellKind, RowSink};
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use rusqlite::types::Value;
+use std::path::Path;
+
+/// How [`SqliteSink`] treats a table that's already there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableMode {
+    /// Fail, as a cleaned file that's already there does
+    Create,
+    /// Drop it and create it again
+    Replace,
+    /// Add the rows to it, failing unless its columns are the ones written
+    Append,
+}
+
+/// Writes rows into one table, in a single transaction committed by
+/// `finish`: TEXT for text, INTEGER for whole numbers, REAL for money and
+/// the other decimals, and the date as ISO 8601 TEXT. A blank cell is NULL.
+/// Dropped before `finish`, the database is left as it was.
+pub struct SqliteSink {
+    connection: Connection,
+    table: String,
+    mode: TableMode,
+    kinds: Vec<CellKind>,
+    insert: String,
+    date_format: String,
+    rows: usize,
+}
+
+impl SqliteSink {
+    /// Opens the database at `path`, creating it if it isn't there.
+    pub fn open(
+        path: &Path,
+        table: &str,
+        mode: TableMode,
+        date_format: &str,
+    ) -> Result<Self, DataAnalysisError> {
+        SqliteSink::new(Connection::open(path)?, table, mode, date_format)
+    }
+
+    /// A database in memory, gone once the sink is, as for a dry run.
+    pub fn in_memory(table: &str, date_format: &str) -> Result<Self, DataAnalysisError> {
+        SqliteSink::new(
+            Connection::open_in_memory()?,
+            table,
+            TableMode::Create,
+            date_format,
+        )
+    }
+
+    fn new(
+        connection: Connection,
+        table: &str,
+        mode: TableMode,
+        date_format: &str,
+    ) -> Result<Self, DataAnalysisError> {
+        // Closing the connection rolls back a transaction left open
+        connection.execute_batch("BEGIN")?;
+        Ok(SqliteSink {
+            connection,
+            table: table.to_string(),
+            mode,
+            kinds: Vec::new(),
+            insert: String::new(),
+            date_format: date_format.to_string(),
+            rows: 0,
+        })
+    }
+
+    // The table's columns and their types, empty when it isn't there
+    fn columns(&self) -> Result<Vec<(String, String)>, DataAnalysisError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, type FROM pragma_table_info(?1)")?;
+        let columns = statement
+            .query_map([&self.table], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(columns)
+    }
+
+    fn value(&self, i: usize, cell: &str) -> Result<Value, DataAnalysisError> {
+        if cell.is_empty() {
+            return Ok(Value::Null);
+        }
+        let bad = |what: &str| format!("'{}' in column {} isn't {}", cell, i + 1, what);
+        Ok(match self.kinds.get(i).copied().unwrap_or(CellKind::Text) {
+            CellKind::Text => Value::Text(cell.to_string()),
+            CellKind::Integer => Value::Integer(cell.parse().map_err(|_| bad("a whole number"))?),
+            CellKind::Money | CellKind::Number => {
+                Value::Real(cell.parse().map_err(|_| bad("a number"))?)
+            }
+            CellKind::Date => {
+                let date = NaiveDate::parse_from_str(cell, &self.date_format)
+                    .map_err(|_| bad(&format!("a {} date", self.date_format)))?;
+                Value::Text(date.format(ISO_DATE_FORMAT).to_string())
+            }
+        })
+    }
+}
+
+impl RowSink for SqliteSink {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        let columns: Vec<(String, &str)> = header
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let kind = self.kinds.get(i).copied().unwrap_or(CellKind::Text);
+                (name.to_string(), sql_type(kind))
+            })
+            .collect();
+        let table = quote(&self.table);
+        let existing = self.columns()?;
+        match self.mode {
+            _ if existing.is_empty() => {}
+            TableMode::Create => {
+                return Err(format!(
+                    "the database already has a {} table; pass --overwrite to replace it or --append to add to it",
+                    self.table
+                )
+                .into());
+            }
+            TableMode::Replace => self
+                .connection
+                .execute_batch(&format!("DROP TABLE {}", table))?,
+            TableMode::Append => {
+                let matches = existing.len() == columns.len()
+                    && existing
+                        .iter()
+                        .zip(&columns)
+                        .all(|((name, kind), (header, sql))| {
+                            name == header && kind.eq_ignore_ascii_case(sql)
+                        });
+                if !matches {
+                    let list = |columns: Vec<String>| columns.join(", ");
+                    return Err(format!(
+                        "the {} table has the columns {}, not the {} this run writes",
+                        self.table,
+                        list(
+                            existing
+                                .iter()
+                                .map(|(n, k)| format!("{} {}", n, k))
+                                .collect()
+                        ),
+                        list(
+                            columns
+                                .iter()
+                                .map(|(n, k)| format!("{} {}", n, k))
+                                .collect()
+                        ),
+                    )
+                    .into());
+                }
+            }
+        }
+        if existing.is_empty() || self.mode == TableMode::Replace {
+            let definitions: Vec<String> = columns
+                .iter()
+                .map(|(name, sql)| format!("{} {}", quote(name), sql))
+                .collect();
+            self.connection.execute_batch(&format!(
+                "CREATE TABLE {} ({})",
+                table,
+                definitions.join(", ")
+            ))?;
+        }
+        let names: Vec<String> = columns.iter().map(|(name, _)| quote(name)).collect();
+        let places: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+        self.insert = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table,
+            names.join(", "),
+            places.join(", ")
+        );
+        Ok(())
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        let values = cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| self.value(i, cell))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut insert = self.connection.prepare_cached(&self.insert)?;
+        insert.execute(rusqlite::params_from_iter(values))?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        if self.connection.is_autocommit() {
+            return Ok(());
+        }
+        self.connection.execute_batch("COMMIT")?;
+        info!("Inserted {} rows into the {} table", self.rows, self.table);
+        Ok(())
+    }
+
+    fn column_kinds(&mut self, kinds: &[CellKind]) {
+        self.kinds = kinds.to_vec();
+    }
+}
+
+fn sql_type(kind: CellKind) -> &'static str {
+    match kind {
+        CellKind::Text | CellKind::Date => "TEXT",
+        CellKind::Integer => "INTEGER",
+        CellKind::Money | CellKind::Number => "REAL",
+    }
+}
+
+// An identifier in double quotes, so headers with spaces work
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const HEADER: [&str; 4] = ["Country", "Units Sold", "Sales_MA_2", "Date"];
+    const KINDS: [CellKind; 4] = [
+        CellKind::Text,
+        CellKind::Integer,
+        CellKind::Number,
+        CellKind::Date,
+    ];
+
+    fn database(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("data_analysis_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    // Writes `rows` to `path`'s sales table, finishing when `finish` says to
+    fn write(
+        path: &Path,
+        mode: TableMode,
+        header: &[&str],
+        rows: &[[&str; 4]],
+        finish: bool,
+    ) -> Result<(), DataAnalysisError> {
+        let mut sink = SqliteSink::open(path, "sales", mode, "%d/%m/%Y")?;
+        sink.column_kinds(&KINDS);
+        sink.write_header(header)?;
+        for row in rows {
+            sink.write_row(row)?;
+        }
+        if finish {
+            sink.finish()?;
+        }
+        Ok(())
+    }
+
+    fn count(path: &Path) -> i64 {
+        let connection = Connection::open(path).unwrap();
+        connection
+            .query_row("SELECT COUNT(*) FROM sales", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn rows_are_typed_with_iso_dates_and_nulls() {
+        let path = database("typed.sqlite");
+        let rows = [
+            ["France", "10", "", "25/02/2019"],
+            ["Mexico", "20", "150", "26/02/2019"],
+        ];
+        write(&path, TableMode::Create, &HEADER, &rows, true).unwrap();
+        let connection = Connection::open(&path).unwrap();
+        let first: (String, String, String, String) = connection
+            .query_row(
+                "SELECT typeof(\"Country\"), typeof(\"Units Sold\"), typeof(\"Sales_MA_2\"), \"Date\" FROM sales LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            first,
+            (
+                "text".into(),
+                "integer".into(),
+                "null".into(),
+                "2019-02-25".into()
+            )
+        );
+        let average: f64 = connection
+            .query_row(
+                "SELECT \"Sales_MA_2\" FROM sales WHERE \"Country\" = 'Mexico'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(average, 150.0);
+        drop(connection);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_table_already_there_is_refused_replaced_or_added_to() {
+        let path = database("modes.sqlite");
+        let row = [["France", "10", "100", "25/02/2019"]];
+        write(&path, TableMode::Create, &HEADER, &row, true).unwrap();
+        let refused = write(&path, TableMode::Create, &HEADER, &row, true).unwrap_err();
+        assert!(refused.to_string().contains("--overwrite"), "{}", refused);
+
+        write(&path, TableMode::Append, &HEADER, &row, true).unwrap();
+        assert_eq!(count(&path), 2);
+        let other = ["Country", "Units", "Sales_MA_2", "Date"];
+        let mismatch = write(&path, TableMode::Append, &other, &row, true).unwrap_err();
+        assert!(mismatch.to_string().contains("not the"), "{}", mismatch);
+
+        write(&path, TableMode::Replace, &HEADER, &row, true).unwrap();
+        assert_eq!(count(&path), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn an_unfinished_sink_leaves_the_table_as_it_was() {
+        let path = database("unfinished.sqlite");
+        let row = [["France", "10", "100", "25/02/2019"]];
+        write(&path, TableMode::Create, &HEADER, &row, true).unwrap();
+        write(&path, TableMode::Replace, &HEADER, &[row[0], row[0]], false).unwrap();
+        write(&path, TableMode::Append, &HEADER, &[row[0], row[0]], false).unwrap();
+        assert_eq!(count(&path), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use crate::parsing::Money;
+use std::collections::VecDeque;
+
+/// The value a fraction `q` of the way through `sorted`, interpolating
+/// between the two values either side of that point the way numpy and R do
+/// by default (Hyndman and Fan's type 7). `None` for no values or a NaN `q`;
+/// `sorted` should be in order and hold no NaN, as [`quartiles`] leaves it.
+pub fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    if q.is_nan() {
+        return None;
+    }
+    let rank = last as f64 * q.clamp(0.0, 1.0);
+    let below = rank.floor();
+    let index = (below as usize).min(last);
+    let (low, high) = (sorted[index], sorted[(index + 1).min(last)]);
+    Some(low + (rank - below) * (high - low))
+}
+
+/// The first and third quartiles of `values`, in any order. NaN values are
+/// left out, so only NaN gives `None` like no values do.
+pub fn quartiles(values: &[f64]) -> Option<(f64, f64)> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    sorted.sort_by(f64::total_cmp);
+    Some((quantile(&sorted, 0.25)?, quantile(&sorted, 0.75)?))
+}
+
+/// The lowest and highest values within `k` interquartile ranges of the
+/// quartiles of `values`, inclusive. NaN values are left out, as with
+/// [`quartiles`]; a single value bounds itself and no values give `None`.
+pub fn iqr_bounds(values: &[f64], k: f64) -> Option<(f64, f64)> {
+    let (q1, q3) = quartiles(values)?;
+    let iqr = q3 - q1;
+    Some((q1 - k * iqr, q3 + k * iqr))
+}
+
+/// A trailing average of up to `window` values for each one. Leading values
+/// with fewer than `min_periods` behind them, themselves included, get `None`.
+pub fn moving_average(values: &[Money], window: usize, min_periods: usize) -> Vec<Option<Money>> {
+    let mut window = Window::new(window, min_periods);
+    values
+        .iter()
+        .map(|&value| window.push(Some(value)))
+        .collect()
+}
+
+/// A trailing window over a series fed in one value at a time, where a
+/// value can be missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    size: usize,
+    min_periods: usize,
+    values: VecDeque<Option<Money>>,
+    sum: Money,
+    present: usize,
+}
+
+impl Window {
+    /// Holds the last `size` values, or just the last one for 0.
+    pub fn new(size: usize, min_periods: usize) -> Self {
+        Window {
+            size: size.max(1),
+            min_periods,
+            values: VecDeque::new(),
+            sum: Money::ZERO,
+            present: 0,
+        }
+    }
+
+    /// Adds `value` and gives the average of the values in the window, or
+    /// `None` while it has fewer than `min_periods`. A missing value is a
+    /// gap: it takes up its place in the window but isn't averaged as zero.
+    pub fn push(&mut self, value: Option<Money>) -> Option<Money> {
+        if self.values.len() == self.size
+            && let Some(Some(oldest)) = self.values.pop_front()
+        {
+            self.sum -= oldest;
+            self.present -= 1;
+        }
+        self.values.push_back(value);
+        if let Some(value) = value {
+            self.sum += value;
+            self.present += 1;
+        }
+        (self.present > 0 && self.present >= self.min_periods)
+            .then(|| self.sum / Money::from(self.present))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantiles_interpolate_like_numpy() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(quantile(&sorted, 0.25), Some(1.75));
+        assert_eq!(quantile(&sorted, 0.5), Some(2.5));
+        assert_eq!(quantile(&sorted, 1.5), Some(4.0));
+        assert_eq!(quantile(&sorted, f64::NAN), None);
+        assert_eq!(quantile(&[], 0.5), None);
+        assert_eq!(quantile(&[7.0], 0.75), Some(7.0));
+    }
+
+    #[test]
+    fn iqr_bounds_leave_out_nan() {
+        let values = [4.0, f64::NAN, 1.0, 3.0, 2.0];
+        assert_eq!(iqr_bounds(&values, 1.5), Some((-0.5, 5.5)));
+        assert_eq!(iqr_bounds(&[5.0], 1.5), Some((5.0, 5.0)));
+        assert_eq!(iqr_bounds(&[f64::NAN], 1.5), None);
+        assert_eq!(iqr_bounds(&[], 1.5), None);
+    }
+}
@@ -0,0 +1,645 @@
+use crate::console::info;
+use crate::dates::Reconcile;
+use crate::error::DataAnalysisError;
+use crate::fields::{ParseMode, RowSlice};
+use crate::parsing::{Currency, Money};
+use crate::profile::Profile;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Rows listed for the less common currencies in a mixed column
+const SAMPLE_ROWS: usize = 5;
+
+/// Bad values a summary keeps; past this many they're only counted, so a
+/// hopeless file can't fill memory.
+pub const MAX_ROW_ERRORS: usize = 10_000;
+
+/// A value that was rejected, with enough to find it in the input.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowError {
+    pub line: u64,
+    pub column: String,
+    /// The cell as it was, `None` when it was missing
+    pub value: Option<String>,
+    pub reason: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{} '{}' - {}", self.column, value, self.reason),
+            None => write!(f, "{} {}", self.column, self.reason),
+        }
+    }
+}
+
+/// Why rows were left out. The ids, such as `bad_number`, are what the
+/// summary prints and serializes, and stay the same from run to run so they
+/// can be watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// A date that's missing or can't be read
+    BadDate,
+    /// A number that's missing or can't be read, under skip-row
+    BadNumber,
+    /// More or fewer fields than the header
+    ColumnCount,
+    /// Dated outside --from-date/--to-date
+    DateRange,
+    /// Didn't match every --filter
+    Filter,
+    Outlier,
+    /// A negative number in a column whose policy rejects them
+    Negative,
+    /// A new row an --append run left out, as a later one has its key
+    Duplicate,
+}
+
+impl DropReason {
+    pub fn id(self) -> &'static str {
+        match self {
+            DropReason::BadDate => "bad_date",
+            DropReason::BadNumber => "bad_number",
+            DropReason::ColumnCount => "column_count",
+            DropReason::DateRange => "date_range",
+            DropReason::Filter => "filter",
+            DropReason::Outlier => "outlier",
+            DropReason::Negative => "negative",
+            DropReason::Duplicate => "duplicate",
+        }
+    }
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// Rows left out for one reason, and in one column when it's about a value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DropCount {
+    pub reason: DropReason,
+    pub column: Option<String>,
+    pub rows: usize,
+}
+
+/// How often one currency turned up in a column, and where.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CurrencyCount {
+    pub count: usize,
+    /// The first few rows it was seen on
+    pub rows: Vec<u64>,
+}
+
+/// The dashboard's outlier filter: the range it kept and what fell outside.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlierCount {
+    pub column: String,
+    pub lower: Money,
+    pub upper: Money,
+    pub removed: usize,
+}
+
+/// How --append merged the new rows into a cleaned file already there.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppendCount {
+    pub path: PathBuf,
+    /// Rows already there that no new row replaced
+    pub kept: usize,
+    /// Rows already there that a new row with the same key took the place of
+    pub replaced: usize,
+    /// New rows with a key that wasn't there before
+    pub added: usize,
+}
+
+/// What happened during a run, returned by each pipeline for the caller to
+/// look at or [`print`](RunSummary::print).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// Data rows in the input
+    pub rows_read: usize,
+    /// The part of the input read, when --offset or --limit left some out
+    pub slice: RowSlice,
+    /// Rows from the input in the cleaned file, or that would be on a dry
+    /// run. Those kept from an --append file are in `appended`
+    pub rows_written: usize,
+    /// Values that failed to parse or validate, per column. Missing values
+    /// aren't among them, though under skip-row they drop their row all the
+    /// same, so see `drops` for the rows
+    pub rejected: BTreeMap<String, usize>,
+    /// The first [`MAX_ROW_ERRORS`] of those values, in the order they were met
+    pub errors: Vec<RowError>,
+    /// Bad values past [`MAX_ROW_ERRORS`], counted but not kept
+    pub errors_not_kept: usize,
+    /// Fields that matched each missing-value marker
+    pub missing: BTreeMap<String, usize>,
+    /// Currencies detected per money column
+    pub currencies: BTreeMap<String, BTreeMap<String, CurrencyCount>>,
+    /// How day/month dates were read, e.g. "day-first, inferred from '25/02/2019'"
+    pub date_order: Option<String>,
+    /// Dates given with a two-digit year
+    pub two_digit_years: usize,
+    /// Two-digit-year dates that ended up more than a year in the future
+    pub suspicious_dates: usize,
+    /// Dates with a time of day other than midnight, which can mean the wrong
+    /// column was exported
+    pub timed_dates: usize,
+    /// Missing or bad dates replaced by the first of the Month/Year columns' month
+    pub rebuilt_dates: usize,
+    /// Missing or bad numbers left blank under keep-blank
+    pub blanked: usize,
+    /// Negative numbers clamped to zero or made positive, per column
+    pub negatives_changed: BTreeMap<String, usize>,
+    /// Rows left out for having more or fewer fields than the header
+    pub wrong_width: usize,
+    /// The first few of them
+    pub wrong_width_rows: Vec<u64>,
+    /// Rows dated outside --from-date/--to-date
+    pub outside_date_range: usize,
+    /// Rows that didn't match every --filter
+    pub filtered_out: usize,
+    /// Dates that disagreed with the Month/Year columns, by how that was settled
+    pub date_conflicts: BTreeMap<String, usize>,
+    /// Set when outliers were filtered out
+    pub outliers: Option<OutlierCount>,
+    /// Set when the rows were merged into a cleaned file with --append
+    pub appended: Option<AppendCount>,
+    /// Every row left out, by reason and column, in the order first met
+    pub drops: Vec<DropCount>,
+    /// The cleaned file column by column, when a report asked for it
+    pub profile: Option<Profile>,
+    /// How long the run took, from opening the input to the last row written
+    pub duration: Duration,
+}
+
+impl RunSummary {
+    /// Percentage of the rows read that didn't make it into the cleaned file.
+    pub fn drop_rate(&self) -> Money {
+        if self.rows_read == 0 {
+            return Money::ZERO;
+        }
+        let dropped = Money::from(self.rows_read - self.rows_written);
+        dropped * Money::ONE_HUNDRED / Money::from(self.rows_read)
+    }
+
+    /// How many rows were left out for each reason that left any out.
+    pub fn dropped_by_reason(&self) -> BTreeMap<DropReason, usize> {
+        let mut totals = BTreeMap::new();
+        for drop in &self.drops {
+            *totals.entry(drop.reason).or_default() += drop.rows;
+        }
+        totals
+    }
+
+    /// Rows left out for `reason`, in any column.
+    pub fn dropped_for(&self, reason: DropReason) -> usize {
+        self.drops
+            .iter()
+            .filter(|drop| drop.reason == reason)
+            .map(|drop| drop.rows)
+            .sum()
+    }
+
+    /// Counts `rows` left out for `reason`, over a value in `column` if given.
+    pub fn count_drop(&mut self, reason: DropReason, column: Option<&str>, rows: usize) {
+        let found = self
+            .drops
+            .iter_mut()
+            .find(|drop| drop.reason == reason && drop.column.as_deref() == column);
+        match found {
+            Some(drop) => drop.rows += rows,
+            None => self.drops.push(DropCount {
+                reason,
+                column: column.map(str::to_string),
+                rows,
+            }),
+        }
+    }
+
+    pub fn reject(&mut self, column: &str) {
+        *self.rejected.entry(column.to_string()).or_default() += 1;
+    }
+
+    /// Keeps a bad value for the end of the run, up to [`MAX_ROW_ERRORS`].
+    pub fn record_error(&mut self, error: RowError) {
+        if self.errors.len() < MAX_ROW_ERRORS {
+            self.errors.push(error);
+        } else {
+            self.errors_not_kept += 1;
+        }
+    }
+
+    /// Bad values and rows with the wrong number of fields, as --max-errors
+    /// counts them.
+    pub fn error_count(&self) -> usize {
+        self.errors.len() + self.errors_not_kept + self.wrong_width
+    }
+
+    /// The first few bad values and wrong-width rows, in row order.
+    pub fn first_errors(&self) -> Vec<String> {
+        let widths = self
+            .wrong_width_rows
+            .iter()
+            .map(|&line| (line, format!("row {}: wrong number of fields", line)));
+        let mut first: Vec<(u64, String)> = self
+            .errors
+            .iter()
+            .take(SAMPLE_ROWS)
+            .map(|error| (error.line, format!("row {}: {}", error.line, error)))
+            .chain(widths)
+            .collect();
+        first.sort_by_key(|(line, _)| *line);
+        first
+            .into_iter()
+            .take(SAMPLE_ROWS)
+            .map(|(_, e)| e)
+            .collect()
+    }
+
+    pub fn count_wrong_width(&mut self, line: u64) {
+        self.wrong_width += 1;
+        self.count_drop(DropReason::ColumnCount, None, 1);
+        if self.wrong_width_rows.len() < SAMPLE_ROWS {
+            self.wrong_width_rows.push(line);
+        }
+    }
+
+    pub fn count_missing(&mut self, marker: &str) {
+        *self.missing.entry(marker.to_string()).or_default() += 1;
+    }
+
+    pub fn count_date_conflict(&mut self, resolution: Reconcile) {
+        *self
+            .date_conflicts
+            .entry(resolution.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn count_currency(&mut self, column: &str, currency: Currency, line: u64) {
+        let tally = self
+            .currencies
+            .entry(column.to_string())
+            .or_default()
+            .entry(currency.to_string())
+            .or_default();
+        tally.count += 1;
+        if tally.rows.len() < SAMPLE_ROWS {
+            tally.rows.push(line);
+        }
+    }
+
+    /// One line per column holding more than one currency, e.g.
+    /// "Sale Price: USD 4312, GBP 27 (GBP on rows 12, 40, 88)".
+    pub fn mixed_currencies(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (column, tallies) in &self.currencies {
+            if tallies.len() < 2 {
+                continue;
+            }
+            let mut tallies: Vec<(&String, &CurrencyCount)> = tallies.iter().collect();
+            tallies.sort_by_key(|(_, tally)| std::cmp::Reverse(tally.count));
+            let counts: Vec<String> = tallies
+                .iter()
+                .map(|(currency, tally)| format!("{} {}", currency, tally.count))
+                .collect();
+            let samples: Vec<String> = tallies[1..]
+                .iter()
+                .map(|(currency, tally)| {
+                    let rows: Vec<String> = tally.rows.iter().map(u64::to_string).collect();
+                    format!("{} on rows {}", currency, rows.join(", "))
+                })
+                .collect();
+            lines.push(format!(
+                "{}: {} ({})",
+                column,
+                counts.join(", "),
+                samples.join("; ")
+            ));
+        }
+        lines
+    }
+
+    /// Fails a strict run when a column mixes currencies; other modes just
+    /// get the warning from [`RunSummary::print`].
+    pub fn check_currencies(&self, mode: ParseMode) -> Result<(), DataAnalysisError> {
+        let mixed = self.mixed_currencies();
+        if mode == ParseMode::Strict && !mixed.is_empty() {
+            return Err(format!("mixed currencies - {}", mixed.join("; ")).into());
+        }
+        Ok(())
+    }
+
+    pub fn print(&self) {
+        info!("Rows: {} read, {} kept", self.rows_read, self.rows_written);
+        if !self.slice.is_whole() {
+            info!("Partial input, from --offset/--limit: {}", self.slice);
+        }
+        let (dates, numbers) = (
+            self.dropped_for(DropReason::BadDate),
+            self.dropped_for(DropReason::BadNumber),
+        );
+        if dates + numbers > 0 {
+            info!(
+                "Rows skipped: {} for dates, {} for numeric values",
+                dates, numbers
+            );
+        }
+        if self.blanked > 0 {
+            info!("Numbers left blank: {}", self.blanked);
+        }
+        for (column, count) in &self.negatives_changed {
+            info!(
+                "Negative {} values changed by its policy: {}",
+                column, count
+            );
+        }
+        if self.wrong_width > 0 {
+            let rows: Vec<String> = self.wrong_width_rows.iter().map(u64::to_string).collect();
+            let first = if rows.len() < self.wrong_width {
+                "first on "
+            } else {
+                ""
+            };
+            info!(
+                "Rows with the wrong number of fields: {} ({}rows {})",
+                self.wrong_width,
+                first,
+                rows.join(", ")
+            );
+        }
+        if self.outside_date_range > 0 {
+            info!("Rows outside the date range: {}", self.outside_date_range);
+        }
+        if self.filtered_out > 0 {
+            info!("Rows left out by --filter: {}", self.filtered_out);
+        }
+        if let Some(order) = &self.date_order {
+            info!("Date order: {}", order);
+        }
+        if self.two_digit_years > 0 {
+            info!(
+                "Two-digit years: {} ({} more than a year ahead)",
+                self.two_digit_years, self.suspicious_dates
+            );
+        }
+        if !self.date_conflicts.is_empty() {
+            let counts: Vec<String> = self
+                .date_conflicts
+                .iter()
+                .map(|(resolution, count)| format!("{} {}", resolution, count))
+                .collect();
+            info!("Date vs Month/Year conflicts: {}", counts.join(", "));
+        }
+        if self.rebuilt_dates > 0 {
+            info!("Dates rebuilt from Month/Year: {}", self.rebuilt_dates);
+        }
+        if self.timed_dates > 0 {
+            info!("Dates with a time of day: {}", self.timed_dates);
+        }
+        if let Some(outliers) = &self.outliers {
+            info!(
+                "Outliers on {}: kept {} to {}, removed {} rows",
+                outliers.column,
+                outliers.lower.normalize(),
+                outliers.upper.normalize(),
+                outliers.removed
+            );
+        }
+        if let Some(appended) = &self.appended {
+            info!(
+                "Appended to {}: {} rows kept, {} replaced, {} added",
+                appended.path.display(),
+                appended.kept,
+                appended.replaced,
+                appended.added
+            );
+        }
+        if !self.drops.is_empty() {
+            info!("Dropped rows:");
+            for drop in &self.drops {
+                match &drop.column {
+                    Some(column) => {
+                        info!("  {} ({}): {} rows", drop.reason, column, drop.rows)
+                    }
+                    None => info!("  {}: {} rows", drop.reason, drop.rows),
+                }
+            }
+        }
+        if !self.rejected.is_empty() {
+            info!("Rejected values, not counting missing ones:");
+            for (column, count) in &self.rejected {
+                info!("  {}: {} values", column, count);
+            }
+            if let Some(first) = self.errors.first() {
+                info!("  the first, on row {}: {}", first.line, first);
+            }
+            if self.errors_not_kept > 0 {
+                info!(
+                    "  only the first {} kept for the report, {} more not",
+                    MAX_ROW_ERRORS, self.errors_not_kept
+                );
+            }
+        }
+        if !self.missing.is_empty() {
+            info!("Missing values:");
+            for (marker, count) in &self.missing {
+                info!("  '{}': {}", marker, count);
+            }
+        }
+        for line in self.mixed_currencies() {
+            info!("warning: mixed currencies in {}", line);
+        }
+    }
+}
+
+/// A run that dropped more rows than --max-drop-rate allows.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "dropped {dropped} of {read} rows ({}%), more than --max-drop-rate {max}%",
+    rate.round_dp(1)
+)]
+pub struct DropRateExceeded {
+    pub dropped: usize,
+    pub read: usize,
+    pub rate: Money,
+    pub max: Money,
+    /// The run's summary, which is still worth showing
+    pub summary: Box<RunSummary>,
+}
+
+impl DropRateExceeded {
+    /// Exit status for this failure, so schedulers can tell it from a crash
+    pub const EXIT_CODE: u8 = 3;
+}
+
+/// A run stopped partway for meeting more errors than --max-errors allows.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "stopped at row {line} on reaching --max-errors, {max} errors; the first were:{}",
+    summary.first_errors().iter().map(|e| format!("\n  {}", e)).collect::<String>()
+)]
+pub struct TooManyErrors {
+    /// The row it stopped on
+    pub line: u64,
+    pub max: usize,
+    /// The run's summary up to that row
+    pub summary: Box<RunSummary>,
+}
+
+/// The summary a failed run still has, when it failed over --max-drop-rate
+/// or --max-errors.
+pub fn failed_summary(error: &DataAnalysisError) -> Option<&RunSummary> {
+    match error {
+        DataAnalysisError::DropRate(exceeded) => Some(&exceeded.summary),
+        DataAnalysisError::TooManyErrors(stopped) => Some(&stopped.summary),
+        _ => None,
+    }
+}
+
+/// One pipeline's run as --summary-out writes it. A run's report borrows
+/// from it, and one read back from the JSON owns what it holds.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunReport<'a> {
+    pub pipeline: Cow<'a, str>,
+    pub input: Cow<'a, Path>,
+    pub output: Cow<'a, Path>,
+    /// The version of data_analysis that ran it
+    pub version: Cow<'a, str>,
+    /// When it ended, in RFC 3339
+    pub finished_at: String,
+    /// Why it failed, `None` when it didn't
+    pub error: Option<String>,
+    /// Missing only when it failed before or without one
+    pub summary: Option<Cow<'a, RunSummary>>,
+}
+
+impl<'a> RunReport<'a> {
+    pub fn new(
+        pipeline: &'a str,
+        input: &'a Path,
+        output: &'a Path,
+        result: &'a Result<RunSummary, DataAnalysisError>,
+    ) -> Self {
+        let (error, summary) = match result {
+            Ok(summary) => (None, Some(summary)),
+            Err(e) => (Some(e.to_string()), failed_summary(e)),
+        };
+        RunReport {
+            pipeline: Cow::Borrowed(pipeline),
+            input: Cow::Borrowed(input),
+            output: Cow::Borrowed(output),
+            version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+            finished_at: Local::now().to_rfc3339(),
+            error,
+            summary: summary.map(Cow::Borrowed),
+        }
+    }
+}
+
+/// Writes `reports`, a [`RunReport`] or a list of them, to `path` as
+/// pretty-printed JSON.
+pub fn write_reports(path: &Path, reports: &impl Serialize) -> Result<(), DataAnalysisError> {
+    let mut json = serde_json::to_string_pretty(reports)?;
+    json.push('\n');
+    std::fs::write(path, json).map_err(|e| DataAnalysisError::io("write", path, e))?;
+    Ok(())
+}
+
+/// One pipeline's part in an `all` run.
+#[derive(Debug)]
+pub struct PipelineRun {
+    pub name: &'static str,
+    pub output: PathBuf,
+    pub duration: Duration,
+    /// Rows read and written, or why it failed
+    pub outcome: Result<(usize, usize), String>,
+}
+
+/// The table printed at the end of an `all` run.
+pub fn print_runs(runs: &[PipelineRun]) {
+    info!(
+        "{:<10}  {:>7}  {:>7}  {:>7}  {:>7}  Output",
+        "Pipeline", "Read", "Written", "Dropped", "Time"
+    );
+    for run in runs {
+        let time = format!("{:.2}s", run.duration.as_secs_f64());
+        match &run.outcome {
+            Ok((read, written)) => info!(
+                "{:<10}  {:>7}  {:>7}  {:>7}  {:>7}  {}",
+                run.name,
+                read,
+                written,
+                read - written,
+                time,
+                run.output.display()
+            ),
+            Err(_) => info!(
+                "{:<10}  {:>7}  {:>7}  {:>7}  {:>7}  failed",
+                run.name, "-", "-", "-", time
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_read_back_from_their_json() {
+        let mut summary = RunSummary {
+            rows_read: 12,
+            rows_written: 9,
+            outliers: Some(OutlierCount {
+                column: "Sale Price".to_string(),
+                lower: Money::new(-420, 0),
+                upper: Money::new(73250, 2),
+                removed: 1,
+            }),
+            duration: Duration::from_millis(420),
+            ..Default::default()
+        };
+        summary.count_drop(DropReason::BadNumber, Some("Units Sold"), 2);
+        summary.count_drop(DropReason::Outlier, Some("Sale Price"), 1);
+        summary.record_error(RowError {
+            line: 6,
+            column: "Units Sold".to_string(),
+            value: Some("$1,523.75".to_string()),
+            reason: "not a whole number".to_string(),
+        });
+        let ok = Ok(summary);
+        let failed = Err(DataAnalysisError::EmptyDataset);
+        let reports = [
+            RunReport::new("dashboard", Path::new("in.csv"), Path::new("out.csv"), &ok),
+            RunReport::new(
+                "forecast",
+                Path::new("f.csv"),
+                Path::new("f_out.csv"),
+                &failed,
+            ),
+        ];
+
+        let json = serde_json::to_string_pretty(&reports).unwrap();
+        let read: Vec<RunReport> = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&read).unwrap(), json);
+        let summary = read[0].summary.as_deref().unwrap();
+        assert_eq!(summary.dropped_by_reason()[&DropReason::BadNumber], 2);
+        assert_eq!(
+            summary.outliers.as_ref().unwrap().upper,
+            Money::new(73250, 2)
+        );
+        assert_eq!(summary.errors[0].line, 6);
+        assert_eq!(summary.duration, Duration::from_millis(420));
+        assert_eq!(read[0].version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(read[1].output, Path::new("f_out.csv"));
+        assert_eq!(read[1].error.as_deref(), Some("no rows to work on"));
+        assert!(read[1].summary.is_none());
+    }
+}
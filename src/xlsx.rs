@@ -0,0 +1,235 @@
+//! Excel workbooks: the cleaned rows as a worksheet, and an .xlsx export
+//! read as the CSV it would have been saved as.
+
+use crate::error::DataAnalysisError;
+use crate::sink::{CellKind, RowSink};
+use calamine::{Data, Reader, Xlsx};
+use chrono::{Datelike, NaiveDate};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+// Excel's own formats for the money and date cells
+const MONEY_FORMAT: &str = "#,##0.00";
+const DATE_FORMAT: &str = "yyyy-mm-dd";
+
+/// Writes rows as one worksheet with the header frozen above them: numbers
+/// as numbers, money with two decimals and the date as an Excel date. The
+/// rows go to a temporary file as they come rather than staying in memory,
+/// and the workbook is written out by `finish`.
+pub struct XlsxSink<W: Write + Send> {
+    writer: Option<W>,
+    workbook: Workbook,
+    sheet: String,
+    kinds: Vec<CellKind>,
+    row: u32,
+    date_format: String,
+    money: Format,
+    date: Format,
+}
+
+impl<W: Write + Send> XlsxSink<W> {
+    /// `sheet` names the worksheet, and date cells are read with the chrono
+    /// `date_format` they were written in.
+    pub fn new(writer: W, sheet: &str, date_format: &str) -> Self {
+        let mut workbook = Workbook::new();
+        workbook.add_worksheet_with_constant_memory();
+        XlsxSink {
+            writer: Some(writer),
+            workbook,
+            sheet: sheet.to_string(),
+            kinds: Vec::new(),
+            row: 0,
+            date_format: date_format.to_string(),
+            money: Format::new().set_num_format(MONEY_FORMAT),
+            date: Format::new().set_num_format(DATE_FORMAT),
+        }
+    }
+}
+
+impl<W: Write + Send> RowSink for XlsxSink<W> {
+    fn write_header(&mut self, header: &[&str]) -> Result<(), DataAnalysisError> {
+        let bold = Format::new().set_bold();
+        let sheet = self.workbook.worksheet_from_index(0)?;
+        sheet.set_name(&self.sheet)?.set_freeze_panes(1, 0)?;
+        for (col, name) in (0..).zip(header) {
+            sheet.write_string_with_format(0, col, *name, &bold)?;
+        }
+        self.row = 1;
+        Ok(())
+    }
+
+    fn write_row(&mut self, cells: &[&str]) -> Result<(), DataAnalysisError> {
+        let row = self.row;
+        let sheet = self.workbook.worksheet_from_index(0)?;
+        for (col, cell) in (0..).zip(cells) {
+            if cell.is_empty() {
+                continue;
+            }
+            let bad = |what: &str| format!("row {}: '{}' isn't {}", row + 1, cell, what);
+            match self
+                .kinds
+                .get(usize::from(col))
+                .copied()
+                .unwrap_or(CellKind::Text)
+            {
+                CellKind::Text => sheet.write_string(row, col, *cell)?,
+                CellKind::Integer | CellKind::Number => {
+                    let number: f64 = cell.parse().map_err(|_| bad("a number"))?;
+                    sheet.write_number(row, col, number)?
+                }
+                CellKind::Money => {
+                    let number: f64 = cell.parse().map_err(|_| bad("a number"))?;
+                    sheet.write_number_with_format(row, col, number, &self.money)?
+                }
+                CellKind::Date => {
+                    let day = NaiveDate::parse_from_str(cell, &self.date_format)
+                        .map_err(|_| bad(&format!("a {} date", self.date_format)))?;
+                    sheet.write_date_with_format(row, col, excel_date(day)?, &self.date)?
+                }
+            };
+        }
+        self.row += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), DataAnalysisError> {
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(());
+        };
+        self.workbook.save_to_writer(&mut writer)?;
+        Ok(writer.flush()?)
+    }
+
+    fn column_kinds(&mut self, kinds: &[CellKind]) {
+        self.kinds = kinds.to_vec();
+    }
+}
+
+fn excel_date(day: NaiveDate) -> Result<ExcelDateTime, DataAnalysisError> {
+    let year =
+        u16::try_from(day.year()).map_err(|_| format!("{} is before any date Excel has", day))?;
+    // Both from chrono, so in range
+    Ok(ExcelDateTime::from_ymd(
+        year,
+        day.month() as u8,
+        day.day() as u8,
+    )?)
+}
+
+/// The first worksheet of `file`, the workbook at `path`, as CSV with
+/// `delimiter`, ready for the pipelines' CSV reader. Date cells become ISO dates, with
+/// the time after them when they have one, and error cells are blank.
+pub fn read_xlsx(file: File, path: &Path, delimiter: u8) -> Result<Vec<u8>, DataAnalysisError> {
+    let unreadable = |e: calamine::XlsxError| format!("can't read {}: {}", path.display(), e);
+    let mut workbook = Xlsx::new(BufReader::new(file)).map_err(unreadable)?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| format!("{} has no worksheets", path.display()))?
+        .map_err(unreadable)?;
+    let mut csv = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+    for row in range.rows() {
+        csv.write_record(row.iter().map(text))?;
+    }
+    csv.into_inner()
+        .map_err(|e| DataAnalysisError::Write(e.into_error()))
+}
+
+// A cell as Excel would have saved it to CSV, but with dates in ISO 8601
+fn text(cell: &Data) -> String {
+    match cell {
+        Data::Empty | Data::Error(_) => String::new(),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => s.clone(),
+        Data::Int(i) => i.to_string(),
+        Data::Float(f) => f.to_string(),
+        Data::Bool(b) => b.to_string().to_uppercase(),
+        Data::DateTime(date) => match date.as_datetime() {
+            Some(at) if at.time() == chrono::NaiveTime::MIN => at.date().to_string(),
+            Some(at) => at.to_string(),
+            None => date.as_f64().to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::{CleanPipeline, Column};
+    use crate::fields::ParseOptions;
+    use crate::parsing::Money;
+    use std::path::PathBuf;
+
+    // A workbook like an export saved from Excel: numbers and dates as
+    // themselves, and one price left blank
+    fn workbook(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("data_analysis_{}_{}", std::process::id(), name));
+        let mut sink = XlsxSink::new(File::create(&path).unwrap(), "Sheet1", "%d/%m/%Y");
+        sink.column_kinds(&[
+            CellKind::Text,
+            CellKind::Integer,
+            CellKind::Money,
+            CellKind::Date,
+        ]);
+        sink.write_header(&["Country", "Units Sold", "Sale Price", "Date"])
+            .unwrap();
+        sink.write_row(&["France", "1618", "12.5", "01/01/2014"])
+            .unwrap();
+        sink.write_row(&["Mexico", "20", "", "25/02/2014"]).unwrap();
+        sink.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn worksheets_read_back_as_csv_with_iso_dates() {
+        let path = workbook("read_back.xlsx");
+        let csv = read_xlsx(File::open(&path).unwrap(), &path, b',').unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "Country,Units Sold,Sale Price,Date\n\
+             France,1618,12.5,2014-01-01\n\
+             Mexico,20,,2014-02-25\n"
+        );
+    }
+
+    #[test]
+    fn cells_are_typed_with_the_header_frozen() {
+        let path = workbook("typed.xlsx");
+        let mut workbook = Xlsx::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let range = workbook.worksheet_range("Sheet1").unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(range.get_value((1, 1)), Some(&Data::Float(1618.0)));
+        assert_eq!(range.get_value((1, 2)), Some(&Data::Float(12.5)));
+        assert_eq!(range.get_value((2, 2)), Some(&Data::Empty));
+        let Some(Data::DateTime(date)) = range.get_value((2, 3)) else {
+            panic!("the date isn't an Excel date");
+        };
+        assert_eq!(
+            date.as_datetime().map(|at| at.date()),
+            NaiveDate::from_ymd_opt(2014, 2, 25)
+        );
+    }
+
+    #[test]
+    fn pipelines_read_xlsx_exports() {
+        let path = workbook("export.xlsx");
+        let options = ParseOptions::default();
+        let rows: Vec<_> = CleanPipeline::new("sales", &options)
+            .column(Column::text("country", "Country"))
+            .column(Column::count("units_sold", "Units Sold"))
+            .column(Column::total("sale_price", "Sale Price"))
+            .rows_from_path(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].count("units_sold").unwrap(), 1618);
+        assert_eq!(rows[0].money("sale_price").unwrap(), Money::new(125, 1));
+        assert_eq!(rows[1].date, NaiveDate::from_ymd_opt(2014, 2, 25).unwrap());
+    }
+}